@@ -0,0 +1,13 @@
+//Headless server, run with `cargo run --bin server -- --bind 0.0.0.0:7878 --seed 1234`
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    let config = rustgame::server::ServerConfig::from_args(std::env::args());
+    if let Err(err) = rustgame::server::run(config) {
+        macroquad::prelude::error!("Server stopped: {}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {}
@@ -0,0 +1,442 @@
+use std::collections::{HashMap, VecDeque};
+
+use macroquad::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use crate::{multiplayer::Event, touchbutton::Button};
+
+//Longer messages are cut, in characters
+pub const MAX_MESSAGE_LENGTH: usize = 120;
+//Messages that can be sent in a row before the rate limit kicks in
+pub const CHAT_BURST: f32 = 5.;
+//Messages per second allowed once the burst is used up
+pub const CHAT_RATE: f32 = 0.5;
+//Messages kept in the history
+const HISTORY_LENGTH: usize = 50;
+//Lines shown under the world while not typing, and for how long
+const SHOWN_LINES: usize = 6;
+const SHOWN_FOR: f64 = 10.;
+//Lines shown while typing
+const OPEN_LINES: usize = 12;
+const EMOTE_DURATION: f64 = 3.;
+//Id your own player goes by locally
+const LOCAL_ID: u32 = 0;
+//Quick messages for touch screens, where there is no keyboard to type on
+const PRESETS: [&str; 4] = ["Hi!", "Follow me", "Help!", "gg"];
+
+//Shown above the player for a few seconds, sent as its position in declaration order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter, Deserialize, Serialize)]
+pub enum Emote {
+    Wave,
+    Laugh,
+    Angry,
+    Heart,
+}
+
+impl Emote {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Emote::Wave => "o/",
+            Emote::Laugh => ":D",
+            Emote::Angry => ">:(",
+            Emote::Heart => "<3",
+        }
+    }
+
+    fn key(&self) -> KeyCode {
+        match self {
+            Emote::Wave => KeyCode::Key1,
+            Emote::Laugh => KeyCode::Key2,
+            Emote::Angry => KeyCode::Key3,
+            Emote::Heart => KeyCode::Key4,
+        }
+    }
+}
+
+//Something the local player wants to say
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum ChatInput {
+    Message(String),
+    Emote(Emote),
+}
+
+//Token bucket, `burst` tokens to start with and `rate` more every second
+pub struct RateLimiter {
+    burst: f32,
+    rate: f32,
+    tokens: f32,
+    last: Option<f64>,
+}
+
+impl RateLimiter {
+    pub fn new(burst: f32, rate: f32) -> RateLimiter {
+        RateLimiter {
+            burst,
+            rate,
+            tokens: burst,
+            last: None,
+        }
+    }
+
+    //The limit the server holds every client to
+    pub fn chat() -> RateLimiter {
+        RateLimiter::new(CHAT_BURST, CHAT_RATE)
+    }
+
+    pub fn try_take(&mut self, now: f64) -> bool {
+        if let Some(last) = self.last {
+            let refill = (now - last).max(0.) as f32 * self.rate;
+            self.tokens = (self.tokens + refill).min(self.burst);
+        }
+        self.last = Some(now);
+        if self.tokens >= 1. {
+            self.tokens -= 1.;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+//Trimmed, without control characters and cut to MAX_MESSAGE_LENGTH, None when nothing is left
+pub fn clean_message(text: &str) -> Option<String> {
+    let text: String = text
+        .trim()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_MESSAGE_LENGTH)
+        .collect();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+pub struct ChatLine {
+    //None for messages from the game itself
+    pub from: Option<String>,
+    pub text: String,
+    pub time: f64,
+}
+
+pub struct ChatBox {
+    history: VecDeque<ChatLine>,
+    //What is being typed, None when the chat is closed
+    input: Option<String>,
+    limiter: RateLimiter,
+    emotes: HashMap<u32, (Emote, f64)>,
+}
+
+impl ChatBox {
+    pub fn new() -> ChatBox {
+        ChatBox {
+            history: VecDeque::new(),
+            input: None,
+            limiter: RateLimiter::chat(),
+            emotes: HashMap::new(),
+        }
+    }
+
+    //While typing, keys should not move the player or flip toggles
+    pub fn is_typing(&self) -> bool {
+        self.input.is_some()
+    }
+
+    //Enter opens the chat and sends what was typed, Escape closes it, 1-4 emote
+    pub fn update(&mut self) -> Option<ChatInput> {
+        let input = match &mut self.input {
+            Some(input) => input,
+            None => {
+                //Characters typed while the chat was closed should not show up once it opens
+                while get_char_pressed().is_some() {}
+                if is_key_pressed(KeyCode::Enter) {
+                    self.input = Some(String::new());
+                    return None;
+                }
+                return Emote::iter()
+                    .find(|emote| is_key_pressed(emote.key()))
+                    .map(ChatInput::Emote);
+            }
+        };
+
+        while let Some(c) = get_char_pressed() {
+            if !c.is_control() && input.chars().count() < MAX_MESSAGE_LENGTH {
+                input.push(c);
+            }
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            input.pop();
+        }
+        if is_key_pressed(KeyCode::Escape) {
+            self.input = None;
+        } else if is_key_pressed(KeyCode::Enter) {
+            let text = self.input.take().unwrap_or_default();
+            return clean_message(&text).map(ChatInput::Message);
+        }
+        None
+    }
+
+    //Applies the rate limit and shows the input locally, returns the event to send
+    pub fn send(&mut self, input: ChatInput, now: f64) -> Option<Event> {
+        if !self.limiter.try_take(now) {
+            self.push(None, String::from("You are sending messages too fast"), now);
+            return None;
+        }
+        match input {
+            ChatInput::Message(text) => {
+                self.push(Some(String::from("You")), text.clone(), now);
+                Some(Event::Chat { id: 0, text })
+            }
+            ChatInput::Emote(emote) => {
+                self.show_emote(LOCAL_ID, emote, now);
+                Some(Event::Emote { id: 0, emote })
+            }
+        }
+    }
+
+    pub fn receive(&mut self, from: String, text: String, now: f64) {
+        self.push(Some(from), text, now);
+    }
+
+    fn push(&mut self, from: Option<String>, text: String, time: f64) {
+        self.history.push_back(ChatLine { from, text, time });
+        while self.history.len() > HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+    }
+
+    pub fn history(&self) -> impl Iterator<Item = &ChatLine> {
+        self.history.iter()
+    }
+
+    pub fn show_emote(&mut self, id: u32, emote: Emote, now: f64) {
+        self.emotes.insert(id, (emote, now));
+    }
+
+    //The emote a player is showing, your own player is id 0
+    pub fn emote_at(&self, id: u32, now: f64) -> Option<Emote> {
+        match self.emotes.get(&id) {
+            Some((emote, time)) if now - time < EMOTE_DURATION => Some(*emote),
+            _ => None,
+        }
+    }
+
+    pub fn forget(&mut self, id: u32) {
+        self.emotes.remove(&id);
+    }
+
+    //Bottom left in screen space, recent lines only unless the chat is open
+    pub fn render(&self, now: f64) {
+        let bottom = screen_height() - 110.;
+        let lines: Vec<&ChatLine> = match self.input {
+            Some(_) => self.history.iter().rev().take(OPEN_LINES).collect(),
+            None => self
+                .history
+                .iter()
+                .rev()
+                .take(SHOWN_LINES)
+                .filter(|line| now - line.time < SHOWN_FOR)
+                .collect(),
+        };
+
+        if let Some(input) = &self.input {
+            let height = 30. * (lines.len() + 1) as f32 + 10.;
+            draw_rectangle(
+                0.,
+                bottom - height + 10.,
+                700.,
+                height,
+                Color::new(0., 0., 0., 0.3),
+            );
+            draw_text(&format!("> {}_", input), 10., bottom, 30., BLACK);
+        }
+        let offset = if self.input.is_some() { 1 } else { 0 };
+        for (i, line) in lines.iter().enumerate() {
+            let y = bottom - (i + offset) as f32 * 30.;
+            match &line.from {
+                Some(from) => draw_text(&format!("{}: {}", from, line.text), 10., y, 30., BLACK),
+                None => draw_text(&line.text, 10., y, 30., DARKGRAY),
+            };
+        }
+    }
+}
+
+//A chat button that opens a row of quick messages and emotes
+pub struct QuickChat {
+    toggle: Button,
+    pos: Vec2,
+    open: bool,
+    options: Vec<(Button, Vec2, ChatInput)>,
+}
+
+impl QuickChat {
+    pub fn new(pos: Vec2, size: f32) -> QuickChat {
+        let inputs = PRESETS
+            .iter()
+            .map(|preset| ChatInput::Message(preset.to_string()))
+            .chain(Emote::iter().map(ChatInput::Emote));
+        let button_size = vec2(size * 1.6, size * 0.6);
+        let options = inputs
+            .enumerate()
+            .map(|(i, input)| {
+                let center = pos + vec2(0., -(i as f32 + 1.) * button_size.y * 1.1);
+                (Button::rectangle(center, button_size), center, input)
+            })
+            .collect();
+        QuickChat {
+            toggle: Button::rectangle(pos, vec2(size, size * 0.6)),
+            pos,
+            open: false,
+            options,
+        }
+    }
+
+    pub fn update(&mut self) -> Option<ChatInput> {
+        self.toggle.update();
+        if self.toggle.pressed() {
+            self.open = !self.open;
+        }
+        if !self.open {
+            return None;
+        }
+        for (button, _, input) in self.options.iter_mut() {
+            button.update();
+            if button.pressed() {
+                self.open = false;
+                return Some(input.clone());
+            }
+        }
+        None
+    }
+
+    pub fn render(&self) {
+        self.toggle.render();
+        draw_label("Chat", self.pos);
+        if !self.open {
+            return;
+        }
+        for (button, center, input) in self.options.iter() {
+            button.render();
+            let label = match input {
+                ChatInput::Message(text) => text.as_str(),
+                ChatInput::Emote(emote) => emote.symbol(),
+            };
+            draw_label(label, *center);
+        }
+    }
+}
+
+fn draw_label(label: &str, center: Vec2) {
+    let size = measure_text(label, None, 30, 1.);
+    draw_text(
+        label,
+        center.x - size.width / 2.,
+        center.y + size.height / 2.,
+        30.,
+        BLACK,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multiplayer::protocol::{from_binary, from_json, to_binary, to_json};
+
+    #[test]
+    fn the_burst_is_spent_then_refilled_at_the_rate() {
+        let mut limiter = RateLimiter::new(3., 0.5);
+        for _ in 0..3 {
+            assert!(limiter.try_take(10.));
+        }
+        assert!(!limiter.try_take(10.));
+        //Half a token a second, so one more after two seconds
+        assert!(!limiter.try_take(11.));
+        assert!(limiter.try_take(12.));
+        assert!(!limiter.try_take(12.));
+    }
+
+    #[test]
+    fn waiting_does_not_save_up_more_than_the_burst() {
+        let mut limiter = RateLimiter::new(2., 1.);
+        assert!(limiter.try_take(0.));
+        let taken = (0..5).filter(|_| limiter.try_take(1000.)).count();
+        assert_eq!(taken, 2);
+        //A clock going back refills nothing
+        assert!(!limiter.try_take(500.));
+    }
+
+    #[test]
+    fn sending_too_fast_is_refused_with_a_note() {
+        let mut chat = ChatBox::new();
+        for i in 0..CHAT_BURST as usize {
+            let text = format!("message {}", i);
+            assert_eq!(
+                chat.send(ChatInput::Message(text.clone()), 1.),
+                Some(Event::Chat { id: 0, text })
+            );
+        }
+        assert_eq!(chat.send(ChatInput::Emote(Emote::Wave), 1.), None);
+        let last = chat.history().last().unwrap();
+        assert_eq!(last.from, None);
+        assert_eq!(chat.history().count(), CHAT_BURST as usize + 1);
+    }
+
+    #[test]
+    fn the_history_keeps_the_newest_lines() {
+        let mut chat = ChatBox::new();
+        for i in 0..HISTORY_LENGTH + 10 {
+            chat.receive(String::from("Bob"), i.to_string(), i as f64);
+        }
+        let lines: Vec<&str> = chat.history().map(|line| line.text.as_str()).collect();
+        assert_eq!(lines.len(), HISTORY_LENGTH);
+        assert_eq!(lines[0], "10");
+        assert_eq!(lines[HISTORY_LENGTH - 1], (HISTORY_LENGTH + 9).to_string());
+    }
+
+    #[test]
+    fn messages_are_cleaned() {
+        assert_eq!(
+            clean_message("  hi\u{7} there \n"),
+            Some(String::from("hi there"))
+        );
+        assert_eq!(clean_message(" \t\n"), None);
+        assert_eq!(clean_message("\u{1b}"), None);
+        let long = "é".repeat(MAX_MESSAGE_LENGTH + 5);
+        let cut = clean_message(&long).unwrap();
+        assert_eq!(cut.chars().count(), MAX_MESSAGE_LENGTH);
+    }
+
+    #[test]
+    fn every_emote_is_read_back_as_itself() {
+        for emote in Emote::iter() {
+            let event = Event::Emote { id: 3, emote };
+            assert_eq!(from_binary(&to_binary(&event)), Ok(event.clone()));
+            assert_eq!(from_json(&to_json(&event)), Ok(event));
+        }
+        let keys: Vec<KeyCode> = Emote::iter().map(|emote| emote.key()).collect();
+        for (i, key) in keys.iter().enumerate() {
+            assert!(!keys[i + 1..].contains(key), "{:?} is bound twice", key);
+        }
+    }
+
+    #[test]
+    fn emotes_wear_off() {
+        let mut chat = ChatBox::new();
+        assert_eq!(
+            chat.send(ChatInput::Emote(Emote::Laugh), 5.),
+            Some(Event::Emote {
+                id: 0,
+                emote: Emote::Laugh
+            })
+        );
+        assert_eq!(chat.emote_at(LOCAL_ID, 5.), Some(Emote::Laugh));
+        assert_eq!(chat.emote_at(LOCAL_ID, 5. + EMOTE_DURATION), None);
+
+        chat.show_emote(7, Emote::Angry, 5.);
+        chat.forget(7);
+        assert_eq!(chat.emote_at(7, 5.), None);
+    }
+}
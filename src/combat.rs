@@ -0,0 +1,287 @@
+use std::collections::VecDeque;
+
+use macroquad::prelude::*;
+
+use crate::{
+    player::BlockingAction,
+    world::{entity::Direction, positions::Coords, shapes::Shape, tile::TileInteraction, World},
+};
+
+//How far an attack reaches, measured between player positions
+pub const ATTACK_RANGE: f32 = 5.;
+//Full width of the swing in front of the attacker, in radians
+pub const ATTACK_ARC: f32 = std::f32::consts::PI * 2. / 3.;
+//The swing, only its angle decides a miss since range is checked on its own
+const ATTACK_SHAPE: Shape = Shape::Cone {
+    length: ATTACK_RANGE,
+    angle: ATTACK_ARC,
+};
+//Targets closer than this are hit whatever way the attacker faces, they overlap
+const POINT_BLANK: f32 = 0.5;
+//Health an attack takes from whoever it lands on
+pub const ATTACK_DAMAGE: u32 = 34;
+//Share of the damage and knockback that gets through a block facing the attacker
+pub const BLOCK_FACTOR: f32 = 0.25;
+//Speed a hit knocks its target away with, on top of walking it stays under what the
+//server accepts
+pub const KNOCKBACK_SPEED: f32 = 12.;
+//Step when walking the line between attacker and target looking for walls
+const SIGHT_STEP: f32 = 0.25;
+//The furthest back in time an attack is resolved, so a very late packet can not hit
+//someone who has long since walked away
+pub const MAX_REWIND: f64 = 0.3;
+//How much position history is kept per player
+const HISTORY_LENGTH: f64 = 1.;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Attack {
+    pub pos: Vec2,
+    pub direction: Direction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Miss {
+    OutOfRange,
+    OutsideArc,
+    Blocked,
+}
+
+//What an attack that landed did, rolling through one leaves nothing to report
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    pub damage: u32,
+    pub blocked: bool,
+    pub knockback: Vec2,
+    //Health left afterwards, the target dies at 0
+    pub health: u32,
+}
+
+//Same rules on the server and in local play, the target position is wherever the target
+//was when the attack happened
+pub fn resolve_attack(world: &World, attack: &Attack, target: Vec2) -> Result<(), Miss> {
+    let offset = target - attack.pos;
+    let distance = offset.length();
+    if distance > ATTACK_RANGE {
+        return Err(Miss::OutOfRange);
+    }
+    if distance > POINT_BLANK && !ATTACK_SHAPE.contains(attack.direction, offset) {
+        return Err(Miss::OutsideArc);
+    }
+    if !has_line_of_sight(world, attack.pos, target) {
+        return Err(Miss::Blocked);
+    }
+    Ok(())
+}
+
+//Walls (Block tiles) between the two points stop the hit, tiles that are not generated do not
+pub fn has_line_of_sight(world: &World, from: Vec2, to: Vec2) -> bool {
+    let distance = from.distance(to);
+    let steps = (distance / SIGHT_STEP).ceil() as i32;
+    let start = Coords::from_vec2(from);
+    let end = Coords::from_vec2(to);
+    for step in 1..steps {
+        let coords = Coords::from_vec2(from.lerp(to, step as f32 / steps as f32));
+        //Standing right next to a wall should not hide you from yourself
+        if coords == start || coords == end {
+            continue;
+        }
+        match world.get_tile(&coords) {
+            Some(tile) if tile.interaction == TileInteraction::Block => return false,
+            _ => (),
+        }
+    }
+    true
+}
+
+//What a player was doing at one moment, all an attack is judged against
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayerState {
+    pub pos: Vec2,
+    pub direction: Direction,
+    //None while walking or standing around
+    pub action: Option<BlockingAction>,
+}
+
+//Where a player has been recently and what it was doing there, so attacks can be checked
+//against the past
+pub struct PositionHistory {
+    samples: VecDeque<(f64, PlayerState)>,
+}
+
+impl PositionHistory {
+    pub fn new() -> PositionHistory {
+        PositionHistory {
+            samples: VecDeque::new(),
+        }
+    }
+
+    pub fn record(&mut self, time: f64, state: PlayerState) {
+        self.samples.push_back((time, state));
+        while let Some((oldest, _)) = self.samples.front() {
+            if time - oldest > HISTORY_LENGTH {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    //The position is interpolated between the samples around `time`, the facing and action
+    //are the ones of the sample before it since neither can be halfway. Clamped to the
+    //oldest and newest
+    pub fn state_at(&self, time: f64) -> Option<PlayerState> {
+        let mut before = *self.samples.front()?;
+        for &(sample_time, state) in self.samples.iter() {
+            if sample_time == time || (sample_time > time && sample_time == before.0) {
+                return Some(state);
+            }
+            if sample_time > time {
+                let t = (time - before.0) / (sample_time - before.0);
+                return Some(PlayerState {
+                    pos: before.1.pos.lerp(state.pos, t as f32),
+                    ..before.1
+                });
+            }
+            before = (sample_time, state);
+        }
+        Some(before.1)
+    }
+}
+
+//Maps the time a peer stamps on its events onto our own clock, a client's onto the server's
+//or the server's onto a client's. The smallest difference seen is the closest estimate of
+//the one way delay, so that is the one that is kept
+pub struct ClockOffset {
+    offset: Option<f64>,
+}
+
+impl ClockOffset {
+    pub fn new() -> ClockOffset {
+        ClockOffset { offset: None }
+    }
+
+    pub fn observe(&mut self, remote_time: f64, local_time: f64) {
+        let offset = local_time - remote_time;
+        self.offset = Some(match self.offset {
+            Some(current) => current.min(offset),
+            None => offset,
+        });
+    }
+
+    //When the event happened on our clock, never in the future and never more than MAX_REWIND ago
+    pub fn to_local_time(&self, remote_time: f64, now: f64) -> f64 {
+        match self.offset {
+            Some(offset) => (remote_time + offset).clamp(now - MAX_REWIND, now),
+            None => now,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    fn state(x: f32, action: Option<BlockingAction>) -> PlayerState {
+        PlayerState {
+            pos: vec2(x, 0.),
+            direction: Direction::Right,
+            action,
+        }
+    }
+
+    #[test]
+    fn the_action_is_the_one_under_way_at_the_time() {
+        let mut history = PositionHistory::new();
+        history.record(1., state(0., None));
+        history.record(1.1, state(1., Some(BlockingAction::Roll)));
+        history.record(1.2, state(3., None));
+
+        let before_the_roll = history.state_at(1.05).unwrap();
+        assert!((before_the_roll.pos.x - 0.5).abs() < 0.0001);
+        assert_eq!(before_the_roll.action, None);
+        let rolling = history.state_at(1.15).unwrap();
+        assert!((rolling.pos.x - 2.).abs() < 0.0001);
+        assert_eq!(rolling.action, Some(BlockingAction::Roll));
+        assert_eq!(
+            history.state_at(1.1),
+            Some(state(1., Some(BlockingAction::Roll)))
+        );
+        assert_eq!(history.state_at(0.), Some(state(0., None)));
+        assert_eq!(history.state_at(5.), Some(state(3., None)));
+    }
+
+    #[test]
+    fn targets_behind_the_attacker_are_missed() {
+        let world = World::grass_with_walls(&[]);
+        let pos = vec2(10., 10.);
+        for direction in Direction::iter() {
+            let attack = Attack { pos, direction };
+            let facing = direction.to_vec2();
+            assert_eq!(resolve_attack(&world, &attack, pos + facing * 2.), Ok(()));
+            assert_eq!(
+                resolve_attack(&world, &attack, pos - facing * 2.),
+                Err(Miss::OutsideArc),
+                "{:?}",
+                direction
+            );
+            //Beside the attacker is outside a swing narrower than a half circle as well
+            let beside = vec2(-facing.y, facing.x) * 2.;
+            assert_eq!(
+                resolve_attack(&world, &attack, pos + beside),
+                Err(Miss::OutsideArc)
+            );
+            //Overlapping players are hit whatever way the attacker faces
+            assert_eq!(resolve_attack(&world, &attack, pos - facing * 0.4), Ok(()));
+        }
+    }
+
+    #[test]
+    fn walls_between_attacker_and_target_block_the_hit() {
+        let attack = Attack {
+            pos: vec2(0., 0.),
+            direction: Direction::Right,
+        };
+        let target = vec2(4., 0.);
+        assert_eq!(
+            resolve_attack(&World::grass_with_walls(&[]), &attack, target),
+            Ok(())
+        );
+        let world = World::grass_with_walls(&[Coords { x: 2, y: 0 }]);
+        assert_eq!(
+            world.get_tile(&Coords { x: 2, y: 0 }).unwrap().interaction,
+            TileInteraction::Block
+        );
+        assert_eq!(resolve_attack(&world, &attack, target), Err(Miss::Blocked));
+        //Diagonally the line passes the wall by
+        let past = vec2(3., 3.);
+        let attack = Attack {
+            pos: vec2(0., 0.),
+            direction: Direction::Down,
+        };
+        assert!(has_line_of_sight(&world, attack.pos, past));
+    }
+
+    #[test]
+    fn the_walls_either_player_stands_against_do_not_count() {
+        //Both stand on a wall tile, so the first and last steps along the line are in a wall
+        let world = World::grass_with_walls(&[Coords { x: 0, y: 0 }, Coords { x: 4, y: 0 }]);
+        let attack = Attack {
+            pos: vec2(0.1, 0.),
+            direction: Direction::Right,
+        };
+        assert_eq!(resolve_attack(&world, &attack, vec2(3.9, 0.)), Ok(()));
+        assert!(has_line_of_sight(&world, vec2(3.9, 0.), vec2(0.1, 0.)));
+        //A wall one tile further on still blocks
+        let world = World::grass_with_walls(&[Coords { x: 1, y: 0 }]);
+        assert_eq!(
+            resolve_attack(&world, &attack, vec2(3.9, 0.)),
+            Err(Miss::Blocked)
+        );
+    }
+}
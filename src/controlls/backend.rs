@@ -0,0 +1,82 @@
+use macroquad::prelude::*;
+
+use super::{
+    bindings::{Action, KeyBindings},
+    Controll, ToggleControll,
+};
+
+//A device the player can play with, the controller asks every backend it has each frame
+pub trait InputBackend {
+    //Reads the device, called once at the start of every frame
+    fn update(&mut self) {}
+    fn is_down(&self, controll: Controll, bindings: &KeyBindings) -> bool;
+    fn is_pressed(&self, toggle: ToggleControll, bindings: &KeyBindings) -> bool;
+    //Analog movement with a length up to 1, None when there is no stick or it is at rest
+    fn movement(&self) -> Option<Vec2> {
+        None
+    }
+}
+
+pub struct KeyboardBackend;
+
+impl InputBackend for KeyboardBackend {
+    fn is_down(&self, controll: Controll, bindings: &KeyBindings) -> bool {
+        bindings.is_down(Action::Controll(controll))
+    }
+
+    fn is_pressed(&self, toggle: ToggleControll, bindings: &KeyBindings) -> bool {
+        bindings.is_pressed(Action::Toggle(toggle))
+    }
+}
+
+//What a scripted device does during one frame
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FakeFrame {
+    pub down: Vec<Controll>,
+    pub pressed: Vec<ToggleControll>,
+    pub movement: Option<Vec2>,
+}
+
+//Plays back a list of frames, one per update, and rests once they run out.
+//Lets the controller be driven without a window, keyboard or gamepad
+pub struct FakeBackend {
+    frames: Vec<FakeFrame>,
+    next: usize,
+    current: FakeFrame,
+}
+
+impl FakeBackend {
+    pub fn new(frames: Vec<FakeFrame>) -> FakeBackend {
+        FakeBackend {
+            frames,
+            next: 0,
+            current: FakeFrame::default(),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.frames.len()
+    }
+}
+
+impl InputBackend for FakeBackend {
+    fn update(&mut self) {
+        self.current = match self.frames.get(self.next) {
+            Some(frame) => frame.clone(),
+            None => FakeFrame::default(),
+        };
+        self.next += 1;
+    }
+
+    fn is_down(&self, controll: Controll, _bindings: &KeyBindings) -> bool {
+        self.current.down.contains(&controll)
+    }
+
+    fn is_pressed(&self, toggle: ToggleControll, _bindings: &KeyBindings) -> bool {
+        self.current.pressed.contains(&toggle)
+    }
+
+    fn movement(&self) -> Option<Vec2> {
+        self.current.movement
+    }
+}
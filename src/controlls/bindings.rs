@@ -0,0 +1,443 @@
+use std::{collections::HashMap, fmt};
+
+use macroquad::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+
+use crate::storage;
+
+use super::{Controll, ToggleControll};
+
+//Storage key the bindings are saved under
+pub const BINDINGS_KEY: &str = "bindings";
+pub const BINDINGS_VERSION: u32 = 1;
+
+//Used by the chat and the rebinding screen, so they can not be bound to anything else
+const RESERVED_KEYS: &[KeyCode] = &[
+    KeyCode::Enter,
+    KeyCode::Escape,
+    KeyCode::Backspace,
+    KeyCode::F1,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+];
+
+//Every key that can be bound, KeyCode has no serde so keys are saved by their name here
+const KEYS: &[KeyCode] = &[
+    KeyCode::Space,
+    KeyCode::Apostrophe,
+    KeyCode::Comma,
+    KeyCode::Minus,
+    KeyCode::Period,
+    KeyCode::Slash,
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+    KeyCode::Semicolon,
+    KeyCode::Equal,
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::LeftBracket,
+    KeyCode::Backslash,
+    KeyCode::RightBracket,
+    KeyCode::GraveAccent,
+    KeyCode::Escape,
+    KeyCode::Enter,
+    KeyCode::Tab,
+    KeyCode::Backspace,
+    KeyCode::Insert,
+    KeyCode::Delete,
+    KeyCode::Right,
+    KeyCode::Left,
+    KeyCode::Down,
+    KeyCode::Up,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+    KeyCode::Home,
+    KeyCode::End,
+    KeyCode::CapsLock,
+    KeyCode::ScrollLock,
+    KeyCode::NumLock,
+    KeyCode::PrintScreen,
+    KeyCode::Pause,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F10,
+    KeyCode::F11,
+    KeyCode::F12,
+    KeyCode::Kp0,
+    KeyCode::Kp1,
+    KeyCode::Kp2,
+    KeyCode::Kp3,
+    KeyCode::Kp4,
+    KeyCode::Kp5,
+    KeyCode::Kp6,
+    KeyCode::Kp7,
+    KeyCode::Kp8,
+    KeyCode::Kp9,
+    KeyCode::KpDecimal,
+    KeyCode::KpDivide,
+    KeyCode::KpMultiply,
+    KeyCode::KpSubtract,
+    KeyCode::KpAdd,
+    KeyCode::KpEnter,
+    KeyCode::KpEqual,
+    KeyCode::LeftShift,
+    KeyCode::LeftControl,
+    KeyCode::LeftAlt,
+    KeyCode::LeftSuper,
+    KeyCode::RightShift,
+    KeyCode::RightControl,
+    KeyCode::RightAlt,
+    KeyCode::RightSuper,
+    KeyCode::Menu,
+    KeyCode::World1,
+    KeyCode::World2,
+];
+
+pub fn key_name(key: KeyCode) -> String {
+    format!("{:?}", key)
+}
+
+pub fn key_from_name(name: &str) -> Option<KeyCode> {
+    KEYS.iter().copied().find(|key| key_name(*key) == name)
+}
+
+//Anything a key can be bound to
+#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy)]
+pub enum Action {
+    Controll(Controll),
+    Toggle(ToggleControll),
+}
+
+impl Action {
+    //Held controlls first, then toggles, in declaration order
+    pub fn all() -> impl Iterator<Item = Action> {
+        Controll::iter()
+            .map(Action::Controll)
+            .chain(ToggleControll::iter().map(Action::Toggle))
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Action::Controll(controll) => format!("{:?}", controll),
+            Action::Toggle(toggle) => format!("{:?}", toggle),
+        }
+    }
+
+    fn default_keys(&self) -> Vec<KeyCode> {
+        let key = match self {
+            Action::Controll(controll) => match controll {
+                Controll::MoveUp => KeyCode::W,
+                Controll::MoveDown => KeyCode::S,
+                Controll::MoveLeft => KeyCode::A,
+                Controll::MoveRight => KeyCode::D,
+                Controll::Attack => KeyCode::J,
+                Controll::Roll => KeyCode::Space,
+                Controll::Block => KeyCode::L,
+                Controll::ZoomIn => KeyCode::E,
+                Controll::ZoomOut => KeyCode::Q,
+                Controll::ForceRender => KeyCode::R,
+                Controll::MoveSecondaryUp => KeyCode::Kp8,
+                Controll::MoveSecondaryDown => KeyCode::Kp2,
+                Controll::MoveSecondaryLeft => KeyCode::Kp4,
+                Controll::MoveSecondaryRight => KeyCode::Kp6,
+                Controll::SecondaryAttack => KeyCode::Kp0,
+                Controll::SecondaryRoll => KeyCode::KpMultiply,
+            },
+            Action::Toggle(toggle) => match toggle {
+                ToggleControll::Touch => KeyCode::T,
+                ToggleControll::FreeCamera => KeyCode::F,
+                ToggleControll::FreeZoom => KeyCode::I,
+                ToggleControll::TerrainGeneration => KeyCode::G,
+                ToggleControll::OtherAnimations => KeyCode::O,
+                ToggleControll::SecondaryPlayer => KeyCode::Kp5,
+                ToggleControll::DebugHitbox => KeyCode::H,
+                ToggleControll::Map => KeyCode::M,
+                ToggleControll::PlayerList => KeyCode::Tab,
+                ToggleControll::Record => KeyCode::F9,
+            },
+        };
+        vec![key]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindError {
+    Reserved(KeyCode),
+    Taken(KeyCode, Action),
+}
+
+impl fmt::Display for BindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BindError::Reserved(key) => write!(f, "{} can not be rebound", key_name(*key)),
+            BindError::Taken(key, action) => {
+                write!(f, "{} is already used by {}", key_name(*key), action.name())
+            }
+        }
+    }
+}
+
+//How the bindings are written to storage, keys by name so the file can be edited by hand
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+struct BindingsFile {
+    version: u32,
+    controlls: HashMap<Controll, Vec<String>>,
+    toggles: HashMap<ToggleControll, Vec<String>>,
+}
+
+//The keys bound to every action, any one of them triggers it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBindings {
+    keys: HashMap<Action, Vec<KeyCode>>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            keys: Action::all()
+                .map(|action| (action, action.default_keys()))
+                .collect(),
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn keys(&self, action: Action) -> &[KeyCode] {
+        match self.keys.get(&action) {
+            Some(keys) => keys,
+            None => &[],
+        }
+    }
+
+    pub fn is_down(&self, action: Action) -> bool {
+        self.keys(action).iter().any(|key| is_key_down(*key))
+    }
+
+    pub fn is_pressed(&self, action: Action) -> bool {
+        self.keys(action).iter().any(|key| is_key_pressed(*key))
+    }
+
+    pub fn action_for(&self, key: KeyCode) -> Option<Action> {
+        Action::all().find(|action| self.keys(*action).contains(&key))
+    }
+
+    //Adds a key to the action, unless it already does something else
+    pub fn bind(&mut self, action: Action, key: KeyCode) -> Result<(), BindError> {
+        if RESERVED_KEYS.contains(&key) {
+            return Err(BindError::Reserved(key));
+        }
+        match self.action_for(key) {
+            Some(bound) if bound == action => return Ok(()),
+            Some(bound) => return Err(BindError::Taken(key, bound)),
+            None => (),
+        }
+        self.keys.entry(action).or_default().push(key);
+        Ok(())
+    }
+
+    pub fn clear(&mut self, action: Action) {
+        self.keys.insert(action, Vec::new());
+    }
+
+    //Keys bound to more than one action, or to one the game uses for something else
+    pub fn conflicts(&self) -> Vec<(KeyCode, Vec<Action>)> {
+        let mut used: HashMap<KeyCode, Vec<Action>> = HashMap::new();
+        for action in Action::all() {
+            for key in self.keys(action) {
+                used.entry(*key).or_default().push(action);
+            }
+        }
+        KEYS.iter()
+            .filter_map(|key| match used.remove(key) {
+                Some(actions) if actions.len() > 1 || RESERVED_KEYS.contains(key) => {
+                    Some((*key, actions))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn to_json(&self) -> String {
+        let names = |action: Action| -> Vec<String> {
+            self.keys(action).iter().map(|key| key_name(*key)).collect()
+        };
+        let file = BindingsFile {
+            version: BINDINGS_VERSION,
+            controlls: Controll::iter()
+                .map(|controll| (controll, names(Action::Controll(controll))))
+                .collect(),
+            toggles: ToggleControll::iter()
+                .map(|toggle| (toggle, names(Action::Toggle(toggle))))
+                .collect(),
+        };
+        serde_json::to_string_pretty(&file).unwrap()
+    }
+
+    //Files with a key bound twice or to something reserved are refused. Actions missing from
+    //the file get their default keys, unless the file already uses them for something else
+    pub fn from_json(json: &str) -> Result<KeyBindings, String> {
+        let file: BindingsFile = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        if file.version != BINDINGS_VERSION {
+            return Err(format!(
+                "bindings version {} is not supported, expected {}",
+                file.version, BINDINGS_VERSION
+            ));
+        }
+        let saved = file
+            .controlls
+            .into_iter()
+            .map(|(controll, keys)| (Action::Controll(controll), keys))
+            .chain(
+                file.toggles
+                    .into_iter()
+                    .map(|(toggle, keys)| (Action::Toggle(toggle), keys)),
+            );
+        let mut bindings = KeyBindings {
+            keys: HashMap::new(),
+        };
+        for (action, names) in saved {
+            let mut keys = Vec::new();
+            for name in names {
+                match key_from_name(&name) {
+                    Some(key) => keys.push(key),
+                    None => return Err(format!("unknown key {} for {}", name, action.name())),
+                }
+            }
+            bindings.keys.insert(action, keys);
+        }
+        match bindings.conflicts().into_iter().next() {
+            Some((key, actions)) => {
+                let names: Vec<String> = actions.iter().map(|action| action.name()).collect();
+                let reserved = if RESERVED_KEYS.contains(&key) {
+                    ", which is reserved"
+                } else {
+                    ""
+                };
+                return Err(format!(
+                    "{} is bound to {}{}",
+                    key_name(key),
+                    names.join(" and "),
+                    reserved
+                ));
+            }
+            None => (),
+        }
+        for action in Action::all() {
+            if !bindings.keys.contains_key(&action) {
+                bindings.clear(action);
+                for key in action.default_keys() {
+                    //Left unbound when the key is taken
+                    let _ = bindings.bind(action, key);
+                }
+            }
+        }
+        Ok(bindings)
+    }
+
+    //The saved bindings, or the defaults when there are none or they can not be read
+    pub fn load() -> KeyBindings {
+        match storage::load(BINDINGS_KEY).map(|json| KeyBindings::from_json(&json)) {
+            Some(Ok(bindings)) => bindings,
+            Some(Err(err)) => {
+                warn!("Ignoring key bindings: {}", err);
+                KeyBindings::default()
+            }
+            None => KeyBindings::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::save(BINDINGS_KEY, &self.to_json())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(controlls: &str) -> String {
+        format!(
+            r#"{{"version": 1, "controlls": {{{}}}, "toggles": {{}}}}"#,
+            controlls
+        )
+    }
+
+    #[test]
+    fn saved_bindings_load_back() {
+        let mut bindings = KeyBindings::default();
+        let attack = Action::Controll(Controll::Attack);
+        bindings.clear(attack);
+        bindings.bind(attack, KeyCode::K).unwrap();
+        assert_eq!(KeyBindings::from_json(&bindings.to_json()), Ok(bindings));
+    }
+
+    #[test]
+    fn a_key_bound_twice_is_refused() {
+        let json = file(r#""Attack": ["K"], "Roll": ["K"]"#);
+        assert!(KeyBindings::from_json(&json).is_err());
+    }
+
+    #[test]
+    fn a_reserved_key_is_refused() {
+        let json = file(r#""Attack": ["Enter"]"#);
+        assert!(KeyBindings::from_json(&json).is_err());
+    }
+
+    #[test]
+    fn defaults_do_not_take_keys_the_file_uses() {
+        //MoveUp is missing, W would be its default
+        let bindings = KeyBindings::from_json(&file(r#""Attack": ["W"]"#)).unwrap();
+        assert_eq!(
+            bindings.keys(Action::Controll(Controll::Attack)),
+            &[KeyCode::W]
+        );
+        assert!(bindings.keys(Action::Controll(Controll::MoveUp)).is_empty());
+        assert_eq!(
+            bindings.keys(Action::Controll(Controll::Roll)),
+            &[KeyCode::Space]
+        );
+        assert!(bindings.conflicts().is_empty());
+    }
+}
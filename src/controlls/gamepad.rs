@@ -0,0 +1,302 @@
+use std::collections::HashSet;
+
+use macroquad::prelude::*;
+
+use super::{backend::InputBackend, bindings::KeyBindings, Controll, ToggleControll};
+
+//Sticks closer to the middle than this count as resting
+const DEAD_ZONE: f32 = 0.2;
+
+//Buttons named by where they sit on the pad, so they mean the same on every brand
+#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy)]
+pub enum GamepadButton {
+    South,
+    East,
+    West,
+    North,
+    LeftBumper,
+    RightBumper,
+    Select,
+    Start,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+impl GamepadButton {
+    fn for_controll(controll: Controll) -> Option<GamepadButton> {
+        match controll {
+            Controll::MoveUp => Some(GamepadButton::DPadUp),
+            Controll::MoveDown => Some(GamepadButton::DPadDown),
+            Controll::MoveLeft => Some(GamepadButton::DPadLeft),
+            Controll::MoveRight => Some(GamepadButton::DPadRight),
+            Controll::Attack => Some(GamepadButton::West),
+            Controll::Roll => Some(GamepadButton::South),
+            Controll::Block => Some(GamepadButton::East),
+            Controll::ZoomIn => Some(GamepadButton::RightBumper),
+            Controll::ZoomOut => Some(GamepadButton::LeftBumper),
+            _ => None,
+        }
+    }
+
+    fn for_toggle(toggle: ToggleControll) -> Option<GamepadButton> {
+        match toggle {
+            ToggleControll::Map => Some(GamepadButton::Select),
+            ToggleControll::PlayerList => Some(GamepadButton::Start),
+            _ => None,
+        }
+    }
+}
+
+//One pad as it was when last read, the stick uses screen directions so down is positive y
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GamepadState {
+    pub stick: Vec2,
+    pub buttons: HashSet<GamepadButton>,
+}
+
+//The first connected pad, with the left stick for walking
+pub struct GamepadBackend {
+    //Asked for the pad once every frame
+    source: Box<dyn FnMut() -> GamepadState>,
+    state: GamepadState,
+    previous: HashSet<GamepadButton>,
+}
+
+impl GamepadBackend {
+    //None when the build has no gamepad support or the platform would not give access
+    pub fn new() -> Option<GamepadBackend> {
+        GamepadSource::new()
+            .map(|mut source| GamepadBackend::with_source(Box::new(move || source.read())))
+    }
+
+    //Reads the pad from anywhere, like a script of states
+    pub fn with_source(source: Box<dyn FnMut() -> GamepadState>) -> GamepadBackend {
+        GamepadBackend {
+            source,
+            state: GamepadState::default(),
+            previous: HashSet::new(),
+        }
+    }
+}
+
+impl InputBackend for GamepadBackend {
+    fn update(&mut self) {
+        let state = (self.source)();
+        self.previous = std::mem::replace(&mut self.state, state).buttons;
+    }
+
+    fn is_down(&self, controll: Controll, _bindings: &KeyBindings) -> bool {
+        match GamepadButton::for_controll(controll) {
+            Some(button) => self.state.buttons.contains(&button),
+            None => false,
+        }
+    }
+
+    fn is_pressed(&self, toggle: ToggleControll, _bindings: &KeyBindings) -> bool {
+        match GamepadButton::for_toggle(toggle) {
+            Some(button) => {
+                self.state.buttons.contains(&button) && !self.previous.contains(&button)
+            }
+            None => false,
+        }
+    }
+
+    //Past the dead zone the intensity starts from 0, like the touch joystick
+    fn movement(&self) -> Option<Vec2> {
+        let length = self.state.stick.length();
+        if length <= DEAD_ZONE {
+            return None;
+        }
+        let intensity = ((length - DEAD_ZONE) / (1. - DEAD_ZONE)).min(1.);
+        Some(self.state.stick / length * intensity)
+    }
+}
+
+#[cfg(all(feature = "gamepad", not(target_arch = "wasm32")))]
+struct GamepadSource {
+    gilrs: gilrs::Gilrs,
+}
+
+#[cfg(all(feature = "gamepad", not(target_arch = "wasm32")))]
+impl GamepadSource {
+    fn new() -> Option<GamepadSource> {
+        match gilrs::Gilrs::new() {
+            Ok(gilrs) => Some(GamepadSource { gilrs }),
+            Err(err) => {
+                warn!("No gamepad support: {}", err);
+                None
+            }
+        }
+    }
+
+    fn read(&mut self) -> GamepadState {
+        use gilrs::{Axis, Button};
+
+        //Events have to be drained for gilrs to update the state of its pads
+        while self.gilrs.next_event().is_some() {}
+        let gamepad = match self.gilrs.gamepads().next() {
+            Some((_, gamepad)) => gamepad,
+            None => return GamepadState::default(),
+        };
+        let buttons = [
+            (Button::South, GamepadButton::South),
+            (Button::East, GamepadButton::East),
+            (Button::West, GamepadButton::West),
+            (Button::North, GamepadButton::North),
+            (Button::LeftTrigger, GamepadButton::LeftBumper),
+            (Button::RightTrigger, GamepadButton::RightBumper),
+            (Button::Select, GamepadButton::Select),
+            (Button::Start, GamepadButton::Start),
+            (Button::DPadUp, GamepadButton::DPadUp),
+            (Button::DPadDown, GamepadButton::DPadDown),
+            (Button::DPadLeft, GamepadButton::DPadLeft),
+            (Button::DPadRight, GamepadButton::DPadRight),
+        ];
+        GamepadState {
+            //gilrs has up as positive y
+            stick: vec2(
+                gamepad.value(Axis::LeftStickX),
+                -gamepad.value(Axis::LeftStickY),
+            ),
+            buttons: buttons
+                .iter()
+                .filter(|(button, _)| gamepad.is_pressed(*button))
+                .map(|(_, button)| *button)
+                .collect(),
+        }
+    }
+}
+
+//The host page has to register these next to the miniquad loader, reading the first pad
+//from navigator.getGamepads() in the standard mapping:
+//gamepad_axis(index) returns the axis value, gamepad_button(index) returns 1 while pressed
+#[cfg(all(feature = "gamepad", target_arch = "wasm32"))]
+extern "C" {
+    fn gamepad_axis(index: u32) -> f32;
+    fn gamepad_button(index: u32) -> i32;
+}
+
+#[cfg(all(feature = "gamepad", target_arch = "wasm32"))]
+struct GamepadSource;
+
+#[cfg(all(feature = "gamepad", target_arch = "wasm32"))]
+impl GamepadSource {
+    fn new() -> Option<GamepadSource> {
+        Some(GamepadSource)
+    }
+
+    fn read(&mut self) -> GamepadState {
+        //Button indices of the standard mapping
+        let buttons = [
+            (0, GamepadButton::South),
+            (1, GamepadButton::East),
+            (2, GamepadButton::West),
+            (3, GamepadButton::North),
+            (4, GamepadButton::LeftBumper),
+            (5, GamepadButton::RightBumper),
+            (8, GamepadButton::Select),
+            (9, GamepadButton::Start),
+            (12, GamepadButton::DPadUp),
+            (13, GamepadButton::DPadDown),
+            (14, GamepadButton::DPadLeft),
+            (15, GamepadButton::DPadRight),
+        ];
+        unsafe {
+            GamepadState {
+                stick: vec2(gamepad_axis(0), gamepad_axis(1)),
+                buttons: buttons
+                    .iter()
+                    .filter(|(index, _)| gamepad_button(*index) != 0)
+                    .map(|(_, button)| *button)
+                    .collect(),
+            }
+        }
+    }
+}
+
+//Built without the gamepad feature
+#[cfg(not(feature = "gamepad"))]
+struct GamepadSource;
+
+#[cfg(not(feature = "gamepad"))]
+impl GamepadSource {
+    fn new() -> Option<GamepadSource> {
+        None
+    }
+
+    fn read(&mut self) -> GamepadState {
+        GamepadState::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Plays the states back one per update, then rests
+    fn scripted(states: Vec<GamepadState>) -> GamepadBackend {
+        let mut states = states.into_iter();
+        GamepadBackend::with_source(Box::new(move || states.next().unwrap_or_default()))
+    }
+
+    fn stick(x: f32, y: f32) -> GamepadState {
+        GamepadState {
+            stick: vec2(x, y),
+            ..GamepadState::default()
+        }
+    }
+
+    fn intensity(backend: &GamepadBackend) -> f32 {
+        match backend.movement() {
+            Some(movement) => movement.length(),
+            None => 0.,
+        }
+    }
+
+    #[test]
+    fn the_dead_zone_is_cut_out_of_the_stick() {
+        let halfway = (1. + DEAD_ZONE) / 2.;
+        let mut gamepad = scripted(vec![
+            stick(DEAD_ZONE / 2., 0.),
+            stick(0., DEAD_ZONE),
+            stick(-halfway, 0.),
+            stick(0., 1.),
+            stick(1., 1.),
+        ]);
+        gamepad.update();
+        assert_eq!(gamepad.movement(), None);
+        gamepad.update();
+        assert_eq!(gamepad.movement(), None);
+        gamepad.update();
+        assert!((intensity(&gamepad) - 0.5).abs() < 0.0001);
+        assert!(gamepad.movement().unwrap().x < 0.);
+        gamepad.update();
+        assert_eq!(gamepad.movement(), Some(vec2(0., 1.)));
+        //Corners of a square stick range are no faster
+        gamepad.update();
+        assert!((intensity(&gamepad) - 1.).abs() < 0.0001);
+        gamepad.update();
+        assert_eq!(gamepad.movement(), None);
+    }
+
+    #[test]
+    fn buttons_toggle_once_per_press() {
+        let select = GamepadState {
+            buttons: HashSet::from([GamepadButton::Select, GamepadButton::West]),
+            ..GamepadState::default()
+        };
+        let mut gamepad = scripted(vec![select.clone(), select, GamepadState::default()]);
+        let bindings = KeyBindings::default();
+        let expected = [(true, true), (false, true), (false, false)];
+        for (map_pressed, attack_down) in expected {
+            gamepad.update();
+            assert_eq!(
+                gamepad.is_pressed(ToggleControll::Map, &bindings),
+                map_pressed
+            );
+            assert_eq!(gamepad.is_down(Controll::Attack, &bindings), attack_down);
+        }
+    }
+}
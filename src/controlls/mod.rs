@@ -0,0 +1,257 @@
+pub mod backend;
+pub mod bindings;
+pub mod gamepad;
+pub mod rebind;
+
+use serde_derive::{Deserialize, Serialize};
+use std::{collections::HashMap, hash::Hash};
+use strum::IntoEnumIterator; // 0.17.1
+use strum_macros::EnumIter; // 0.17.1
+
+use macroquad::prelude::{vec2, Vec2};
+
+use self::{
+    backend::{InputBackend, KeyboardBackend},
+    bindings::KeyBindings,
+};
+
+#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy, EnumIter, Deserialize, Serialize)]
+pub enum Controll {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Attack,
+    Roll,
+    Block,
+    //Camera
+    ZoomIn,
+    ZoomOut,
+    ForceRender,
+    //Player 2
+    MoveSecondaryUp,
+    MoveSecondaryDown,
+    MoveSecondaryLeft,
+    MoveSecondaryRight,
+    SecondaryAttack,
+    SecondaryRoll,
+}
+
+#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy, EnumIter, Deserialize, Serialize)]
+pub enum ToggleControll {
+    Touch,
+    FreeCamera,
+    FreeZoom,
+    TerrainGeneration,
+    OtherAnimations,
+    SecondaryPlayer,
+    DebugHitbox,
+    Map,
+    PlayerList,
+    Record,
+}
+
+impl ToggleControll {
+    fn default(&self) -> bool {
+        match self {
+            ToggleControll::Touch => false,
+            ToggleControll::FreeCamera => false,
+            ToggleControll::FreeZoom => false,
+            ToggleControll::TerrainGeneration => true,
+            ToggleControll::OtherAnimations => true,
+            ToggleControll::SecondaryPlayer => false,
+            ToggleControll::DebugHitbox => false,
+            ToggleControll::Map => false,
+            ToggleControll::PlayerList => false,
+            ToggleControll::Record => false,
+        }
+    }
+}
+
+pub struct Controller {
+    toggles: HashMap<ToggleControll, bool>,
+    pub bindings: KeyBindings,
+    //Keyboard unless told otherwise, a controll is down when any of them has it down
+    backends: Vec<Box<dyn InputBackend>>,
+}
+
+impl Controller {
+    pub fn default() -> Controller {
+        let mut toggles = HashMap::new();
+        for toggle_controll in ToggleControll::iter() {
+            toggles.insert(toggle_controll, toggle_controll.default());
+        }
+
+        Controller {
+            toggles,
+            bindings: KeyBindings::default(),
+            backends: vec![Box::new(KeyboardBackend)],
+        }
+    }
+
+    //Without the keyboard, for driving the game from something else
+    pub fn with_backends(backends: Vec<Box<dyn InputBackend>>) -> Controller {
+        let mut controller = Controller::default();
+        controller.backends = backends;
+        controller
+    }
+
+    pub fn add_backend(&mut self, backend: Box<dyn InputBackend>) {
+        self.backends.push(backend);
+    }
+
+    //Reads every device, once per frame before anything asks for input
+    pub fn poll(&mut self) {
+        for backend in self.backends.iter_mut() {
+            backend.update();
+        }
+    }
+
+    pub fn update(&mut self) {
+        for toggle in self.pressed_toggles() {
+            self.flip(toggle);
+        }
+    }
+
+    //Toggles whose key went down this frame
+    pub fn pressed_toggles(&self) -> Vec<ToggleControll> {
+        ToggleControll::iter()
+            .filter(|toggle| {
+                self.backends
+                    .iter()
+                    .any(|backend| backend.is_pressed(*toggle, &self.bindings))
+            })
+            .collect()
+    }
+
+    pub fn flip(&mut self, toggle: ToggleControll) {
+        let enabled = self.is_enabled(toggle);
+        self.toggles.insert(toggle, !enabled);
+    }
+
+    pub fn set(&mut self, toggle: ToggleControll, enabled: bool){
+        self.toggles.insert(toggle, enabled);
+    }
+
+    pub fn is_enabled(&self, toggle: ToggleControll) -> bool {
+        return *self.toggles.get(&toggle).unwrap();
+    }
+
+    pub fn is(&mut self, controll: Controll) -> bool {
+        self.backends
+            .iter()
+            .any(|backend| backend.is_down(controll, &self.bindings))
+    }
+
+    //Analog movement from the first backend with a stick out of rest
+    pub fn movement(&self) -> Option<Vec2> {
+        self.backends
+            .iter()
+            .find_map(|backend| backend.movement())
+    }
+
+    //Where the player wants to walk, a stick out of rest wins over the movement keys
+    pub fn walk(&mut self) -> Vec2 {
+        match self.movement() {
+            Some(analog) => return analog,
+            None => (),
+        }
+        let mut movement = vec2(0., 0.);
+        if self.is(Controll::MoveRight) {
+            movement.x = 1.;
+        }
+        if self.is(Controll::MoveLeft) {
+            movement.x = -1.;
+        }
+        if self.is(Controll::MoveUp) {
+            movement.y = -1.;
+        }
+        if self.is(Controll::MoveDown) {
+            movement.y = 1.;
+        }
+        movement.normalize_or_zero()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        backend::{FakeBackend, FakeFrame},
+        *,
+    };
+
+    fn scripted(frames: Vec<FakeFrame>) -> Controller {
+        Controller::with_backends(vec![Box::new(FakeBackend::new(frames))])
+    }
+
+    fn down(controlls: &[Controll]) -> FakeFrame {
+        FakeFrame {
+            down: controlls.to_vec(),
+            ..FakeFrame::default()
+        }
+    }
+
+    fn pressed(toggles: &[ToggleControll]) -> FakeFrame {
+        FakeFrame {
+            pressed: toggles.to_vec(),
+            ..FakeFrame::default()
+        }
+    }
+
+    #[test]
+    fn controlls_are_down_only_in_their_frames() {
+        let mut controller = scripted(vec![
+            down(&[Controll::Attack]),
+            down(&[]),
+            down(&[Controll::Attack, Controll::Roll]),
+        ]);
+        let expected = [(true, false), (false, false), (true, true), (false, false)];
+        for (attack, roll) in expected {
+            controller.poll();
+            assert_eq!(controller.is(Controll::Attack), attack);
+            assert_eq!(controller.is(Controll::Roll), roll);
+            assert!(!controller.is(Controll::Block));
+        }
+    }
+
+    #[test]
+    fn toggles_flip_only_in_the_frames_they_are_pressed() {
+        let map = ToggleControll::Map;
+        let list = ToggleControll::PlayerList;
+        let mut controller = scripted(vec![pressed(&[map]), pressed(&[]), pressed(&[map, list])]);
+        let expected = [
+            (vec![map], true, false),
+            (vec![], true, false),
+            (vec![map, list], false, true),
+            (vec![], false, true),
+        ];
+        for (toggles, map_enabled, list_enabled) in expected {
+            controller.poll();
+            assert_eq!(controller.pressed_toggles(), toggles);
+            controller.update();
+            assert_eq!(controller.is_enabled(map), map_enabled);
+            assert_eq!(controller.is_enabled(list), list_enabled);
+        }
+    }
+
+    #[test]
+    fn a_stick_wins_over_the_movement_keys() {
+        let mut controller = scripted(vec![
+            FakeFrame {
+                down: vec![Controll::MoveRight],
+                movement: Some(vec2(0., -0.5)),
+                ..FakeFrame::default()
+            },
+            down(&[Controll::MoveRight]),
+            down(&[Controll::MoveRight, Controll::MoveUp]),
+        ]);
+        controller.poll();
+        assert_eq!(controller.walk(), vec2(0., -0.5));
+        controller.poll();
+        assert_eq!(controller.walk(), vec2(1., 0.));
+        controller.poll();
+        assert_eq!(controller.walk(), vec2(1., -1.).normalize());
+        controller.poll();
+        assert_eq!(controller.walk(), Vec2::ZERO);
+    }
+}
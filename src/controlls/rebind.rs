@@ -0,0 +1,127 @@
+use macroquad::prelude::*;
+
+use super::bindings::{key_name, Action, KeyBindings};
+
+const LINE_HEIGHT: f32 = 24.;
+
+//Lists every action with its keys, F1 opens and closes it
+pub struct RebindScreen {
+    open: bool,
+    selected: usize,
+    //Waiting for the key to add to the selected action
+    listening: bool,
+    message: Option<String>,
+}
+
+impl RebindScreen {
+    pub fn new() -> RebindScreen {
+        RebindScreen {
+            open: false,
+            selected: 0,
+            listening: false,
+            message: None,
+        }
+    }
+
+    //While open, keys should not reach the game
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    //Up and Down pick an action, Enter adds a key to it, Backspace clears it and Delete
+    //puts every binding back to its default, returns true when the bindings changed
+    pub fn update(&mut self, bindings: &mut KeyBindings) -> bool {
+        if !self.open {
+            if is_key_pressed(KeyCode::F1) {
+                self.open = true;
+                self.message = None;
+            }
+            return false;
+        }
+
+        let actions: Vec<Action> = Action::all().collect();
+        let action = actions[self.selected];
+        if self.listening {
+            let key = match get_last_key_pressed() {
+                Some(key) => key,
+                None => return false,
+            };
+            self.listening = false;
+            if key == KeyCode::Escape {
+                self.message = None;
+                return false;
+            }
+            return match bindings.bind(action, key) {
+                Ok(_) => {
+                    self.message = None;
+                    true
+                }
+                Err(err) => {
+                    self.message = Some(err.to_string());
+                    false
+                }
+            };
+        }
+
+        if is_key_pressed(KeyCode::F1) || is_key_pressed(KeyCode::Escape) {
+            self.open = false;
+        } else if is_key_pressed(KeyCode::Up) {
+            self.selected = (self.selected + actions.len() - 1) % actions.len();
+        } else if is_key_pressed(KeyCode::Down) {
+            self.selected = (self.selected + 1) % actions.len();
+        } else if is_key_pressed(KeyCode::Enter) {
+            self.listening = true;
+            self.message = Some(format!(
+                "Press a key for {}, Escape to cancel",
+                action.name()
+            ));
+        } else if is_key_pressed(KeyCode::Backspace) {
+            bindings.clear(action);
+            return true;
+        } else if is_key_pressed(KeyCode::Delete) {
+            *bindings = KeyBindings::default();
+            self.message = Some(String::from("Back to the default keys"));
+            return true;
+        }
+        false
+    }
+
+    pub fn render(&self, bindings: &KeyBindings) {
+        if !self.open {
+            return;
+        }
+        draw_rectangle(
+            0.,
+            0.,
+            screen_width(),
+            screen_height(),
+            Color::new(1., 1., 1., 0.85),
+        );
+        draw_text(
+            "Key bindings - Enter add, Backspace clear, Delete defaults, F1 close",
+            20.,
+            40.,
+            30.,
+            BLACK,
+        );
+        match &self.message {
+            Some(message) => draw_text(message, 20., 70., 24., RED),
+            None => (),
+        };
+
+        //Scrolls so the selected line stays on screen
+        let shown = ((screen_height() - 110.) / LINE_HEIGHT).max(1.) as usize;
+        let first = (self.selected + 1).saturating_sub(shown);
+        for (i, action) in Action::all().enumerate().skip(first).take(shown) {
+            let y = 110. + (i - first) as f32 * LINE_HEIGHT;
+            let keys: Vec<String> = bindings
+                .keys(action)
+                .iter()
+                .map(|key| key_name(*key))
+                .collect();
+            let color = if i == self.selected { BLUE } else { BLACK };
+            draw_text(&action.name(), 20., y, 24., color);
+            draw_text(&keys.join(", "), 320., y, 24., color);
+        }
+    }
+}
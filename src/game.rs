@@ -0,0 +1,930 @@
+use std::collections::HashMap;
+
+use macroquad::prelude::*;
+
+use crate::{
+    chat::{ChatBox, ChatInput, Emote},
+    combat::{self, Attack, ClockOffset, Hit},
+    controlls::{
+        bindings::{key_name, Action, KeyBindings},
+        Controll, Controller, ToggleControll,
+    },
+    multiplayer::{
+        interpolation::{InterpolationConfig, SnapshotBuffer},
+        prediction::Prediction,
+        Event, MultiplayerHandler,
+    },
+    player::{animation::Animation, BlockingAction, Interaction, Player, SPAWN_POINT},
+    replay::{RecordedFrame, Recording},
+    timestep::FixedTimestep,
+    world::{
+        entity::{Direction, WorldEntity},
+        positions::Coords,
+        tile::{TileAction, TileInteraction},
+        TileChange, World,
+    },
+};
+
+const MAX_ZOOM: f32 = 0.005;
+const MIN_ZOOM: f32 = 0.1;
+//Size of the area generated around the player, the server holds chunk requests to it
+pub const MAX_RENDER: f32 = 256.;
+const PLAYER_SPEED: f32 = 15.;
+const SECONDARY_SPEED: f32 = 20.;
+//Furthest from the camera a tile can be placed
+const PLACE_REACH: f32 = 16.;
+//The map is drawn this many times smaller than the world
+const MAP_SCALE: f32 = 8.;
+const MAP_ZOOM: f32 = 0.008;
+
+//Everything read from the keyboard, mouse and touch screen in one frame, filled in by
+//whoever owns the window so the game itself never asks macroquad for input
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameInput {
+    //Size of the window, wider windows show more of the world
+    pub screen: Vec2,
+    //Direction to walk in, a length of 1 is full speed
+    pub movement: Vec2,
+    pub action: Option<BlockingAction>,
+    //Multiplied into the camera zoom, 1 leaves it as is
+    pub zoom: f32,
+    //Drags the free camera, in screen pixels
+    pub pan: Vec2,
+    //Mouse or touch position in screen coordinates
+    pub pointer: Option<Vec2>,
+    //Places a tile at the pointer
+    pub place: bool,
+    //Teleports to the pointer while the map is open
+    pub teleport: bool,
+    //Generates the whole view at once instead of around the player
+    pub force_render: bool,
+    //Toggles whose key was pressed, each one flips
+    pub toggles: Vec<ToggleControll>,
+    pub chat: Option<ChatInput>,
+    //The debug player that pretends to be someone else
+    pub secondary_movement: Vec2,
+    pub secondary_action: Option<BlockingAction>,
+}
+
+impl Default for FrameInput {
+    fn default() -> Self {
+        FrameInput {
+            screen: vec2(800., 600.),
+            movement: vec2(0., 0.),
+            action: None,
+            zoom: 1.,
+            pan: vec2(0., 0.),
+            pointer: None,
+            place: false,
+            teleport: false,
+            force_render: false,
+            toggles: Vec::new(),
+            chat: None,
+            secondary_movement: vec2(0., 0.),
+            secondary_action: None,
+        }
+    }
+}
+
+pub struct WorldCamera {
+    pub mode: CameraMode,
+    pub z: f32,
+}
+
+pub enum CameraMode {
+    PlayerLock,
+    Follow(Vec2),
+}
+
+//One session of the game, `update` never touches the window so it can be run headless
+pub struct Game<'a> {
+    pub world: World,
+    pub controller: Controller,
+    pub chat: ChatBox,
+    pub camera: WorldCamera,
+    player: Player<'a>,
+    other_players: HashMap<u32, Player<'a>>,
+    snapshots: HashMap<u32, SnapshotBuffer>,
+    interpolation: InterpolationConfig,
+    //Maps the clock updates are stamped with onto ours, so snapshots keep the spacing they
+    //were sent with instead of whatever the network did to it
+    server_clock: ClockOffset,
+    multiplayer_handler: Box<dyn MultiplayerHandler>,
+    //Room and id once a server welcomed us, names of everyone in the room
+    session: Option<(String, u32)>,
+    roster: HashMap<u32, String>,
+    prediction: Prediction,
+    seq: u32,
+    //The simulation runs in fixed steps, drawing blends between the last two of them
+    timestep: FixedTimestep,
+    previous_pos: Vec2,
+    player2: Player<'a>,
+    //Seconds of game time, advanced by update
+    time: f64,
+    screen: Vec2,
+    //Tile under the pointer when it is in reach
+    cursor: Option<Vec2>,
+    animations: &'a HashMap<(Interaction, Direction), Animation>,
+    texture: &'a Texture2D,
+    recording: Option<Recording>,
+}
+
+impl<'a> Game<'a> {
+    pub fn new(
+        world: World,
+        multiplayer_handler: Box<dyn MultiplayerHandler>,
+        animations: &'a HashMap<(Interaction, Direction), Animation>,
+        texture: &'a Texture2D,
+        tick_rate: f32,
+    ) -> Game<'a> {
+        let mut player = Player::new_playable(SPAWN_POINT.x, SPAWN_POINT.y, animations, texture);
+        player.respawn();
+        let player2 = Player::new_other(
+            String::from("Player2"),
+            10.,
+            10.,
+            0.,
+            0.,
+            animations,
+            texture,
+        );
+        Game {
+            world,
+            controller: Controller::default(),
+            chat: ChatBox::new(),
+            camera: WorldCamera {
+                mode: CameraMode::PlayerLock,
+                z: 0.04,
+            },
+            previous_pos: player.get_position(),
+            player,
+            other_players: HashMap::new(),
+            snapshots: HashMap::new(),
+            interpolation: InterpolationConfig::default(),
+            server_clock: ClockOffset::new(),
+            multiplayer_handler,
+            session: None,
+            roster: HashMap::new(),
+            prediction: Prediction::new(),
+            seq: 0,
+            timestep: FixedTimestep::new(tick_rate),
+            player2,
+            time: 0.,
+            screen: vec2(800., 600.),
+            cursor: None,
+            animations,
+            texture,
+            recording: None,
+        }
+    }
+
+    pub fn player(&self) -> &Player<'a> {
+        &self.player
+    }
+
+    pub fn player_mut(&mut self) -> &mut Player<'a> {
+        &mut self.player
+    }
+
+    pub fn other_players(&self) -> &HashMap<u32, Player<'a>> {
+        &self.other_players
+    }
+
+    pub fn session(&self) -> Option<&(String, u32)> {
+        self.session.as_ref()
+    }
+
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.timestep.ticks()
+    }
+
+    pub fn tick_rate(&self) -> f32 {
+        self.timestep.tick_rate()
+    }
+
+    //Drops the part of a step carried over from earlier frames
+    pub fn reset_clock(&mut self) {
+        self.timestep = FixedTimestep::new(self.timestep.tick_rate());
+        self.previous_pos = self.player.get_position();
+    }
+
+    //Handled on the next update as if the server had sent it
+    pub fn receive(&mut self, event: Event) {
+        self.multiplayer_handler.downstream_event(event);
+    }
+
+    //Every update from now on is recorded until `stop_recording`
+    pub fn start_recording(&mut self, rand_seed: u64) {
+        self.reset_clock();
+        self.player.reset_state();
+        self.recording = Some(Recording::start(self, rand_seed));
+    }
+
+    pub fn stop_recording(&mut self) -> Option<Recording> {
+        self.recording.take()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    //A room's world belongs to the server and should not be saved over the local one
+    pub fn is_local(&self) -> bool {
+        self.session.is_none()
+    }
+
+    pub fn update(&mut self, input: FrameInput, dt: f32) {
+        for toggle in input.toggles.iter() {
+            self.controller.flip(*toggle);
+        }
+        self.time += dt as f64;
+        self.screen = input.screen;
+        let steps = self.timestep.advance(dt);
+        let step = self.timestep.step();
+
+        let said = input.chat.clone();
+        match said.and_then(|said| self.chat.send(said, self.time)) {
+            Some(event) => self.multiplayer_handler.upstream_event(event),
+            None => (),
+        }
+
+        let events = self.multiplayer_handler.get_events();
+        match &mut self.recording {
+            Some(recording) => {
+                let frame = RecordedFrame::new(&input, dt, events.clone());
+                recording.frames.push(frame);
+            }
+            None => (),
+        }
+        for event in events {
+            self.handle_event(event);
+        }
+
+        //Typing in the chat takes the keyboard away from everything else
+        if self.chat.is_typing() {
+            self.player.set_velocity(vec2(0., 0.));
+        } else {
+            self.handle_player_input(&input);
+        }
+        self.handle_debug_player(&input, steps as f32 * step);
+
+        if !self.controller.is_enabled(ToggleControll::FreeZoom) {
+            self.camera.z = self.camera.z.clamp(MAX_ZOOM, MIN_ZOOM);
+        }
+
+        //Controlls done, make view and render zones
+        let z = self.camera.z;
+        let view_zone = make_view_rect(
+            self.camera_target(self.player.get_position()),
+            self.view_size(z),
+        );
+        let render_zone = make_view_rect(
+            self.player.get_position(),
+            self.view_size(z).normalize() * MAX_RENDER,
+        );
+
+        let generate = self
+            .controller
+            .is_enabled(ToggleControll::TerrainGeneration);
+        if generate {
+            if input.force_render {
+                self.world.generate_at(view_zone, view_zone);
+            } else {
+                self.world.generate_at(render_zone, view_zone);
+            }
+        }
+        for _ in 0..steps {
+            self.previous_pos = self.player.get_position();
+            let velocity = self.player.get_velocity();
+            self.world.update_entity(&mut self.player, step);
+            self.world.update_world_by_entity(&mut self.player);
+            let correction = self.prediction.smooth(step);
+            self.player
+                .set_position(self.player.get_position() + correction);
+            self.seq = self
+                .prediction
+                .record(velocity, step, self.player.get_position());
+        }
+        for change in self.world.take_tile_changes() {
+            self.multiplayer_handler.upstream_event(Event::TileChanged {
+                x: change.coords.x,
+                y: change.coords.y,
+                tile: change.tile,
+                revision: change.revision,
+            });
+        }
+        for (pos, revision) in self.world.take_new_chunks() {
+            self.multiplayer_handler
+                .upstream_event(Event::ChunkRequest {
+                    x: pos.x,
+                    y: pos.y,
+                    revision,
+                });
+        }
+        self.multiplayer_handler.set_your_player_pos(
+            self.player.get_position(),
+            self.player.get_velocity(),
+            self.seq,
+            self.time,
+        );
+
+        //Remote players are only moved by their updates, the world just animates them
+        let animate = self.controller.is_enabled(ToggleControll::OtherAnimations);
+        for (id, other_player) in self.other_players.iter_mut() {
+            let sample = self
+                .snapshots
+                .get(id)
+                .and_then(|buffer| buffer.sample(self.time, &self.interpolation));
+            match sample {
+                Some(sample) => {
+                    other_player.set_position(sample.pos);
+                    other_player.set_velocity(sample.velocity);
+                }
+                None => (),
+            }
+            //Their edits arrive as TileChanged, so the events only have to be cleared
+            other_player.clear_world_events();
+            if animate {
+                for _ in 0..steps {
+                    self.world.animate_entity(other_player, step);
+                }
+            }
+        }
+
+        let mut player_positions = vec![self.player.get_position()];
+        player_positions.extend(
+            self.other_players
+                .values()
+                .map(|other| other.get_position()),
+        );
+        self.world.evict(&player_positions);
+
+        self.handle_pointer(&input);
+    }
+
+    fn handle_event(&mut self, event: Event) {
+        match event {
+            Event::PlayerUpdate {
+                name,
+                id,
+                x,
+                y,
+                vx,
+                vy,
+                time,
+                ..
+            } => {
+                let sent = if time > 0. {
+                    self.server_clock.observe(time, self.time);
+                    self.server_clock.to_local_time(time, self.time)
+                } else {
+                    self.time
+                };
+                if !name.is_empty() {
+                    self.roster.insert(id, name.clone());
+                }
+                if !self.other_players.contains_key(&id) {
+                    let new_player =
+                        Player::new_other(name, x, y, vx, vy, self.animations, self.texture);
+                    self.other_players.insert(id, new_player);
+                }
+                //Positions are applied from the buffer once per frame, see update
+                self.snapshots
+                    .entry(id)
+                    .or_insert_with(SnapshotBuffer::new)
+                    .push(&self.interpolation, sent, vec2(x, y), vec2(vx, vy));
+            }
+            Event::PlayerDisconnect { id } => {
+                self.other_players.remove(&id);
+                self.snapshots.remove(&id);
+                self.roster.remove(&id);
+                self.chat.forget(id);
+            }
+            Event::PlayerAction {
+                id,
+                x,
+                y,
+                direction,
+                action,
+                ..
+            } => match self.other_players.get_mut(&id) {
+                Some(other_player) => {
+                    other_player.force_action(action, vec2(x, y), direction);
+                    //Without a server deciding hits, they are resolved here with the same rules
+                    if action == BlockingAction::Attack
+                        && !self.multiplayer_handler.is_authoritative()
+                    {
+                        let attack = Attack {
+                            pos: vec2(x, y),
+                            direction,
+                        };
+                        let hit = combat::resolve_attack(
+                            &self.world,
+                            &attack,
+                            self.player.get_position(),
+                        );
+                        debug!("Attack from {}: {:?}", id, hit);
+                        let hit = match hit {
+                            Ok(()) => self.player.take_hit(&attack, combat::ATTACK_DAMAGE),
+                            Err(_) => None,
+                        };
+                        match hit {
+                            Some(hit) => self.report_hit(hit),
+                            None => (),
+                        }
+                    }
+                }
+                None => (),
+            },
+            Event::CommandTeleport { x, y } => {
+                self.player.set_position(vec2(x, y));
+            }
+            Event::CommandHit { health, kx, ky } => self.player.apply_hit(health, vec2(kx, ky)),
+            Event::PlayerHit { id, health, .. } => match self.other_players.get_mut(&id) {
+                Some(other_player) => other_player.set_health(health),
+                None => (),
+            },
+            Event::CommandAck { seq, x, y } => {
+                let current = self.player.get_position();
+                match self
+                    .prediction
+                    .acknowledge(&self.world, seq, vec2(x, y), current)
+                {
+                    Some(pos) => self.player.set_position(pos),
+                    None => (),
+                }
+            }
+            Event::TileChanged {
+                x,
+                y,
+                tile,
+                revision,
+            } => self.world.apply_tile_change(&TileChange {
+                coords: Coords { x, y },
+                tile,
+                revision,
+            }),
+            Event::ChunkData { chunk } => self.world.apply_chunk_data(chunk),
+            Event::Welcome { id, room, seed } => {
+                info!("Joined {} as player {}", room, id);
+                //The room's world replaces the local one, which stays as it was saved
+                if seed != self.world.get_seed() {
+                    self.world.reseed(seed);
+                }
+                self.session = Some((room, id));
+                self.server_clock = ClockOffset::new();
+            }
+            Event::Refused { reason } => error!("Server refused to let us in: {}", reason),
+            Event::PlayerJoined { id, name } => {
+                self.roster.insert(id, name);
+            }
+            Event::Chat { id, text } => {
+                let from = match self.roster.get(&id) {
+                    Some(name) => name.clone(),
+                    None => format!("Player{}", id),
+                };
+                self.chat.receive(from, text, self.time);
+            }
+            Event::Emote { id, emote } => self.chat.show_emote(id, emote, self.time),
+            //Answered by the server
+            Event::ChunkRequest { .. } => (),
+            Event::Join { .. } | Event::Leave => (),
+        }
+    }
+
+    //Without a server the one who was hit tells everyone, with the death as its own action
+    fn report_hit(&mut self, hit: Hit) {
+        self.multiplayer_handler.upstream_event(Event::PlayerHit {
+            id: 0,
+            damage: hit.damage,
+            health: hit.health,
+            blocked: hit.blocked,
+        });
+        if hit.health == 0 {
+            self.multiplayer_handler
+                .upstream_event(Event::PlayerAction {
+                    id: 0,
+                    x: self.player.get_position().x,
+                    y: self.player.get_position().y,
+                    direction: self.player.get_direction(),
+                    action: BlockingAction::Dying,
+                    time: self.time,
+                })
+        }
+    }
+
+    fn handle_player_input(&mut self, input: &FrameInput) {
+        let player_pos = self.player.get_position();
+        match (
+            &mut self.camera.mode,
+            self.controller.is_enabled(ToggleControll::FreeCamera),
+        ) {
+            (CameraMode::PlayerLock, true) => self.camera.mode = CameraMode::Follow(player_pos),
+            (CameraMode::Follow(target), true) => {
+                let size = make_view_size(self.camera.z, self.screen) * 0.3;
+                let zone = make_view_rect(*target, size);
+                if !zone.contains(player_pos) {
+                    *target += (player_pos - *target) / size;
+                }
+            }
+            (CameraMode::Follow(_), false) => self.camera.mode = CameraMode::PlayerLock,
+            _ => (),
+        };
+        match &mut self.camera.mode {
+            CameraMode::Follow(target) => {
+                let world_per_pixel = make_view_size(self.camera.z, self.screen) / self.screen;
+                *target -= input.pan * world_per_pixel;
+            }
+            CameraMode::PlayerLock => (),
+        }
+
+        let movement = if input.movement.length() > 1. {
+            input.movement.normalize()
+        } else {
+            input.movement
+        };
+        self.player.set_velocity(movement * PLAYER_SPEED);
+        self.camera.z *= input.zoom;
+
+        match input.action {
+            Some(action) => match self.player.try_action(action) {
+                Ok(_) => self
+                    .multiplayer_handler
+                    .upstream_event(Event::PlayerAction {
+                        id: 0,
+                        x: self.player.get_position().x,
+                        y: self.player.get_position().y,
+                        direction: self.player.get_direction(),
+                        action,
+                        time: self.time,
+                    }),
+                Err(_) => (),
+            },
+            None => (),
+        }
+    }
+
+    fn handle_debug_player(&mut self, input: &FrameInput, time: f32) {
+        if !self.controller.is_enabled(ToggleControll::SecondaryPlayer) {
+            return;
+        }
+        let player2 = &mut self.player2;
+        player2.update(&TileInteraction::Walkable, &TileAction::None, time);
+        player2.set_velocity(input.secondary_movement.normalize_or_zero() * SECONDARY_SPEED);
+        player2.set_position(player2.get_position() + player2.get_velocity() * time);
+        let player_update = Event::PlayerUpdate {
+            name: String::from("Player2"),
+            id: 0,
+            x: player2.get_position().x,
+            y: player2.get_position().y,
+            vx: player2.get_velocity().x,
+            vy: player2.get_velocity().y,
+            seq: 0,
+            time: 0.,
+        };
+        self.multiplayer_handler.downstream_event(player_update);
+
+        match input.secondary_action {
+            Some(action) => match player2.try_action(action) {
+                Ok(_) => {
+                    let event = Event::PlayerAction {
+                        id: 0,
+                        x: player2.get_position().x,
+                        y: player2.get_position().y,
+                        direction: player2.get_direction(),
+                        action,
+                        time: self.time,
+                    };
+                    self.multiplayer_handler.downstream_event(event)
+                }
+                Err(_) => (),
+            },
+            None => (),
+        }
+    }
+
+    //Placing in the world, and teleporting when the map is open
+    fn handle_pointer(&mut self, input: &FrameInput) {
+        self.cursor = None;
+        let target = self.camera_target(self.player.get_position());
+        match input.pointer {
+            Some(pointer) => {
+                let pos = ((pointer - self.screen / 2.) / self.screen
+                    * self.view_size(self.camera.z)
+                    + target)
+                    .round();
+                if target.distance_squared(pos) < PLACE_REACH * PLACE_REACH {
+                    self.cursor = Some(pos);
+                    if input.place {
+                        self.player.try_place(pos);
+                    }
+                }
+            }
+            None => (),
+        }
+
+        if self.controller.is_enabled(ToggleControll::Map) {
+            let target = self.player.get_position() / MAP_SCALE;
+            let map_size = self.view_size(MAP_ZOOM);
+            self.world.generate_at(
+                Rect::new(0., 0., 0., 0.),
+                make_view_rect(target * MAP_SCALE, map_size * MAP_SCALE),
+            );
+            match input.pointer {
+                Some(pointer) if input.teleport => {
+                    let pos = (pointer - self.screen / 2.) / self.screen * map_size * MAP_SCALE
+                        + target * MAP_SCALE;
+                    self.player.set_position(pos);
+                    self.controller.set(ToggleControll::Map, false);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn view_size(&self, z: f32) -> Vec2 {
+        make_view_size(z, self.screen)
+    }
+
+    //Where the camera looks when the player is at `player_pos`
+    fn camera_target(&self, player_pos: Vec2) -> Vec2 {
+        match self.camera.mode {
+            CameraMode::PlayerLock => {
+                if self.controller.is_enabled(ToggleControll::Touch) {
+                    //Leaves room for the touch controlls under the player
+                    player_pos + vec2(0., self.view_size(self.camera.z).y * 0.15)
+                } else {
+                    player_pos
+                }
+            }
+            CameraMode::Follow(target) => target,
+        }
+    }
+
+    //Draws the current state, the only part that needs a window
+    pub fn render(&mut self) {
+        let debug_render = self.controller.is_enabled(ToggleControll::DebugHitbox);
+        let shown_pos = self
+            .timestep
+            .blend(self.previous_pos, self.player.get_position());
+        let z = self.camera.z;
+        let target = self.camera_target(shown_pos);
+
+        //Render world
+        {
+            set_camera(&Camera2D {
+                target,
+                zoom: vec2(z, -z * (screen_width() / screen_height())),
+                ..Default::default()
+            });
+            let text_params = world_text_params();
+
+            self.world.render(make_view_rect(target, self.view_size(z)));
+
+            match self.cursor {
+                Some(pos) => draw_rectangle(pos.x, pos.y, 1., 1., BLACK),
+                None => (),
+            }
+
+            for (id, other_player) in self.other_players.iter_mut() {
+                if debug_render {
+                    match self.snapshots.get(id) {
+                        Some(buffer) => buffer.render_debug(self.time, &self.interpolation),
+                        None => (),
+                    }
+                }
+                other_player.render(&text_params, debug_render);
+                match self.chat.emote_at(*id, self.time) {
+                    Some(emote) => render_emote(emote, other_player.get_position(), text_params),
+                    None => (),
+                }
+            }
+
+            self.player.render_at(shown_pos, &text_params, debug_render);
+            match self.chat.emote_at(0, self.time) {
+                Some(emote) => render_emote(emote, shown_pos, text_params),
+                None => (),
+            }
+
+            if debug_render {
+                let render_zone = make_view_rect(
+                    self.player.get_position(),
+                    self.view_size(z).normalize() * MAX_RENDER,
+                );
+                draw_rectangle_lines(
+                    render_zone.x,
+                    render_zone.y,
+                    render_zone.w,
+                    render_zone.h,
+                    1.,
+                    PINK,
+                )
+            }
+        }
+
+        //Exprimental map
+        if self.controller.is_enabled(ToggleControll::Map) {
+            self.render_map();
+        }
+
+        //render on screen
+        {
+            set_default_camera();
+            for (i, line) in help_lines(&self.controller.bindings).iter().enumerate() {
+                draw_text(line, 10.0, 30.0 + i as f32 * 30.0, 30.0, BLACK);
+            }
+            draw_text(
+                &format!(
+                    "{:.0}, {:.0} seed {}",
+                    self.player.get_position().x,
+                    self.player.get_position().y,
+                    self.world.get_seed()
+                ),
+                10.0,
+                screen_height() - 10.,
+                30.0,
+                BLACK,
+            );
+            if debug_render {
+                let stats = self.world.residency_stats();
+                draw_text(
+                    &format!(
+                        "chunks {} map {} modified {} archived {} evicted {} discovered {}",
+                        stats.chunks,
+                        stats.map_chunks,
+                        stats.modified,
+                        stats.archived,
+                        stats.evicted,
+                        stats.discovered
+                    ),
+                    10.0,
+                    screen_height() - 40.,
+                    30.0,
+                    BLACK,
+                );
+                draw_text(
+                    &format!(
+                        "pending inputs {} correction {:.2} tick {} alpha {:.2}",
+                        self.prediction.pending(),
+                        self.prediction.correction().length(),
+                        self.timestep.ticks(),
+                        self.timestep.alpha()
+                    ),
+                    10.0,
+                    screen_height() - 70.,
+                    30.0,
+                    BLACK,
+                );
+            }
+            self.chat.render(self.time);
+            if self.controller.is_enabled(ToggleControll::PlayerList) {
+                self.render_player_list();
+            }
+        }
+    }
+
+    fn render_map(&mut self) {
+        set_default_camera();
+        draw_rectangle(
+            screen_width() * 0.08,
+            screen_height() * 0.08,
+            screen_width() * 0.84,
+            screen_height() * 0.84,
+            BROWN,
+        );
+
+        let zoom = vec2(MAP_ZOOM, -MAP_ZOOM * (screen_width() / screen_height()));
+        let target = self.player.get_position() / MAP_SCALE;
+        set_camera(&Camera2D {
+            zoom,
+            target,
+            ..Default::default()
+        });
+        let map_zone = make_view_rect(target, self.view_size(MAP_ZOOM) * 0.8);
+        self.world.render_map(map_zone);
+        let text_params = world_text_params();
+        for (_, other_player) in self.other_players.iter() {
+            let pos = other_player.get_position() / MAP_SCALE;
+            draw_circle(pos.x, pos.y, 0.5, RED);
+            draw_text_ex(other_player.get_name(), pos.x, pos.y, text_params);
+        }
+        let pos = self.player.get_position() / MAP_SCALE;
+        draw_circle(pos.x, pos.y, 0.5, RED);
+        draw_text_ex("You", pos.x, pos.y, text_params);
+    }
+
+    fn render_player_list(&self) {
+        let title = match &self.session {
+            Some((room, id)) => format!("Room {}, you are player {}", room, id),
+            None => String::from("Not in a room"),
+        };
+        let mut players: Vec<(&u32, &String)> = self.roster.iter().collect();
+        players.sort();
+        let x = screen_width() - 420.;
+        let height = 50. + players.len() as f32 * 30.;
+        draw_rectangle(x - 10., 10., 420., height, Color::new(0., 0., 0., 0.3));
+        draw_text(&title, x, 40., 30., BLACK);
+        for (i, (id, name)) in players.iter().enumerate() {
+            draw_text(
+                &format!("{} {}", id, name),
+                x,
+                70. + i as f32 * 30.,
+                30.,
+                BLACK,
+            );
+        }
+    }
+}
+
+//What the keys do with the current bindings, chat and emote keys can not be rebound
+fn help_lines(bindings: &KeyBindings) -> Vec<String> {
+    let keys = |action: Action| -> String {
+        let names: Vec<String> = bindings
+            .keys(action)
+            .iter()
+            .map(|key| key_name(*key))
+            .collect();
+        if names.is_empty() {
+            String::from("?")
+        } else {
+            names.join("/")
+        }
+    };
+    let controll = |controll: Controll| keys(Action::Controll(controll));
+    vec![
+        format!(
+            "{}{}{}{} to move player",
+            controll(Controll::MoveUp),
+            controll(Controll::MoveLeft),
+            controll(Controll::MoveDown),
+            controll(Controll::MoveRight)
+        ),
+        format!(
+            "{}-{} to zoom camera",
+            controll(Controll::ZoomOut),
+            controll(Controll::ZoomIn)
+        ),
+        format!("{} to roll", controll(Controll::Roll)),
+        format!("{} to swing sword", controll(Controll::Attack)),
+        format!("{} to open map", keys(Action::Toggle(ToggleControll::Map))),
+        String::from("Enter to chat, 1-4 to emote"),
+    ]
+}
+
+//Text in world space, two units high
+fn world_text_params() -> TextParams {
+    let (font_size, font_scale, font_aspect) = camera_font_scale(2.);
+    TextParams {
+        font_size,
+        font_scale,
+        font_scale_aspect: font_aspect,
+        color: BLACK,
+        ..Default::default()
+    }
+}
+
+//Above the name, in world space
+fn render_emote(emote: Emote, pos: Vec2, text_params: TextParams) {
+    draw_text_ex(emote.symbol(), pos.x + 1., pos.y - 4., text_params);
+}
+
+fn make_view_size(z: f32, screen: Vec2) -> Vec2 {
+    1. / (vec2(z, z * (screen.x / screen.y))) * 2.
+}
+
+fn make_view_rect(target: Vec2, size: Vec2) -> Rect {
+    let corner = target - size / 2.;
+    Rect::new(corner.x, corner.y, size.x, size.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_help_follows_the_bindings() {
+        let mut bindings = KeyBindings::default();
+        assert_eq!(
+            help_lines(&bindings),
+            [
+                "WASD to move player",
+                "Q-E to zoom camera",
+                "Space to roll",
+                "J to swing sword",
+                "M to open map",
+                "Enter to chat, 1-4 to emote",
+            ]
+        );
+
+        let attack = Action::Controll(Controll::Attack);
+        bindings.bind(attack, KeyCode::K).unwrap();
+        bindings.clear(Action::Controll(Controll::Roll));
+        let lines = help_lines(&bindings);
+        assert_eq!(lines[2], "? to roll");
+        assert_eq!(lines[3], "J/K to swing sword");
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use macroquad::prelude::*;
+
+//Fingers closer together than this, in pixels, do not pinch
+const MIN_PINCH_DISTANCE: f32 = 10.;
+
+//What two fingers did since the last frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gesture {
+    //How much further apart the fingers are, above 1 when spreading them
+    pub zoom: f32,
+    //How far the point between the fingers moved, in pixels
+    pub pan: Vec2,
+}
+
+impl Gesture {
+    pub fn none() -> Gesture {
+        Gesture {
+            zoom: 1.,
+            pan: vec2(0., 0.),
+        }
+    }
+}
+
+//Pinch and pan from touches no button or joystick took for itself
+pub struct GestureTracker {
+    //Last position of every touch, None for the ones something else owns
+    touches: HashMap<u64, Option<Vec2>>,
+}
+
+impl GestureTracker {
+    pub fn new() -> GestureTracker {
+        GestureTracker {
+            touches: HashMap::new(),
+        }
+    }
+
+    //Where the touches nobody claimed are
+    pub fn free_touches(&self) -> Vec<Vec2> {
+        self.touches.values().filter_map(|pos| *pos).collect()
+    }
+
+    //`claimed` is asked once for every new touch, touches it returns true for are left out
+    //until they end. Only works out a gesture while exactly two fingers are free
+    pub fn update(&mut self, touches: &[Touch], claimed: impl Fn(&Touch) -> bool) -> Gesture {
+        let mut previous = Vec::new();
+        let mut current = Vec::new();
+        for touch in touches {
+            let last = self.touches.entry(touch.id).or_insert_with(|| {
+                if claimed(touch) {
+                    None
+                } else {
+                    Some(touch.position)
+                }
+            });
+            match last {
+                Some(last) => {
+                    previous.push(*last);
+                    current.push(touch.position);
+                    *last = touch.position;
+                }
+                None => (),
+            }
+        }
+        self.touches.retain(|id, _| {
+            touches.iter().any(|touch| {
+                touch.id == *id
+                    && touch.phase != TouchPhase::Ended
+                    && touch.phase != TouchPhase::Cancelled
+            })
+        });
+
+        if current.len() != 2 {
+            return Gesture::none();
+        }
+        let before = previous[0].distance(previous[1]);
+        let after = current[0].distance(current[1]);
+        let zoom = if before > MIN_PINCH_DISTANCE && after > MIN_PINCH_DISTANCE {
+            after / before
+        } else {
+            1.
+        };
+        Gesture {
+            zoom,
+            pan: (current[0] + current[1]) / 2. - (previous[0] + previous[1]) / 2.,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(id: u64, phase: TouchPhase, x: f32, y: f32) -> Touch {
+        Touch {
+            id,
+            phase,
+            position: vec2(x, y),
+        }
+    }
+
+    fn unclaimed(_: &Touch) -> bool {
+        false
+    }
+
+    #[test]
+    fn spreading_two_fingers_zooms_in() {
+        let mut tracker = GestureTracker::new();
+        let start = [
+            touch(1, TouchPhase::Started, 100., 100.),
+            touch(2, TouchPhase::Started, 200., 100.),
+        ];
+        assert_eq!(tracker.update(&start, unclaimed), Gesture::none());
+
+        let spread = [
+            touch(1, TouchPhase::Moved, 50., 100.),
+            touch(2, TouchPhase::Moved, 250., 100.),
+        ];
+        let gesture = tracker.update(&spread, unclaimed);
+        assert_eq!(gesture.zoom, 2.);
+        assert_eq!(gesture.pan, vec2(0., 0.));
+
+        let pinch = [
+            touch(1, TouchPhase::Moved, 100., 100.),
+            touch(2, TouchPhase::Moved, 200., 100.),
+        ];
+        assert!(tracker.update(&pinch, unclaimed).zoom < 1.);
+    }
+
+    #[test]
+    fn moving_two_fingers_together_pans() {
+        let mut tracker = GestureTracker::new();
+        tracker.update(
+            &[
+                touch(1, TouchPhase::Started, 100., 100.),
+                touch(2, TouchPhase::Started, 200., 100.),
+            ],
+            unclaimed,
+        );
+        let gesture = tracker.update(
+            &[
+                touch(1, TouchPhase::Moved, 130., 80.),
+                touch(2, TouchPhase::Moved, 230., 80.),
+            ],
+            unclaimed,
+        );
+        assert_eq!(gesture.zoom, 1.);
+        assert_eq!(gesture.pan, vec2(30., -20.));
+    }
+
+    #[test]
+    fn claimed_touches_are_left_out() {
+        let mut tracker = GestureTracker::new();
+        //Touch 1 starts on a button, so it is not part of any gesture even once it leaves
+        let on_button = |touch: &Touch| touch.position.x < 50.;
+        tracker.update(
+            &[
+                touch(1, TouchPhase::Started, 20., 100.),
+                touch(2, TouchPhase::Started, 200., 100.),
+            ],
+            on_button,
+        );
+        let gesture = tracker.update(
+            &[
+                touch(1, TouchPhase::Moved, 300., 300.),
+                touch(2, TouchPhase::Moved, 210., 100.),
+            ],
+            on_button,
+        );
+        assert_eq!(gesture, Gesture::none());
+        assert_eq!(tracker.free_touches(), vec![vec2(210., 100.)]);
+
+        //A second free finger makes a gesture with touch 2 alone
+        tracker.update(
+            &[
+                touch(1, TouchPhase::Stationary, 300., 300.),
+                touch(2, TouchPhase::Stationary, 210., 100.),
+                touch(3, TouchPhase::Started, 310., 100.),
+            ],
+            on_button,
+        );
+        let gesture = tracker.update(
+            &[
+                touch(1, TouchPhase::Moved, 0., 0.),
+                touch(2, TouchPhase::Moved, 160., 100.),
+                touch(3, TouchPhase::Moved, 360., 100.),
+            ],
+            on_button,
+        );
+        assert_eq!(gesture.zoom, 2.);
+        assert_eq!(gesture.pan, vec2(0., 0.));
+    }
+
+    #[test]
+    fn ended_touches_are_forgotten() {
+        let mut tracker = GestureTracker::new();
+        tracker.update(
+            &[
+                touch(1, TouchPhase::Started, 100., 100.),
+                touch(2, TouchPhase::Started, 200., 100.),
+            ],
+            unclaimed,
+        );
+        tracker.update(
+            &[
+                touch(1, TouchPhase::Stationary, 100., 100.),
+                touch(2, TouchPhase::Ended, 200., 100.),
+            ],
+            unclaimed,
+        );
+        assert_eq!(tracker.free_touches(), vec![vec2(100., 100.)]);
+        //Only one finger left, and one that vanished without ending is dropped as well
+        assert_eq!(tracker.update(&[], unclaimed), Gesture::none());
+        assert!(tracker.free_touches().is_empty());
+    }
+}
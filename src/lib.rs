@@ -0,0 +1,23 @@
+pub mod chat;
+pub mod combat;
+pub mod controlls;
+pub mod game;
+pub mod gesture;
+pub mod multiplayer;
+pub mod player;
+pub mod replay;
+pub mod storage;
+pub mod timestep;
+pub mod touchbutton;
+pub mod touchlayout;
+pub mod world;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod server;
+
+#[cfg(target_arch = "wasm32")]
+#[macro_use]
+extern crate lazy_static;
+
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
@@ -1,593 +1,542 @@
-use crate::{controlls::ToggleControll, multiplayer::Event};
-use controlls::{Controll, Controller};
 use macroquad::prelude::*;
 use macroquad_virtual_joystick::{Joystick, JoystickDirection};
-use multiplayer::MultiplayerHandler;
-use player::{animation::load_textures, Player};
-use world::{entity::*, tile::*, *};
+use rustgame::{
+    chat::QuickChat,
+    controlls::{
+        bindings::KeyBindings, gamepad::GamepadBackend, rebind::RebindScreen, Controll,
+        Controller, ToggleControll,
+    },
+    game::{FrameInput, Game},
+    gesture::{Gesture, GestureTracker},
+    multiplayer::{DevLocalMultiplayer, MultiplayerHandler},
+    player::{self, animation::load_textures},
+    replay::{Recording, Replay, REPLAY_KEY},
+    storage,
+    timestep::{FixedTimestep, DEFAULT_TICK_RATE},
+    touchbutton::Button,
+    touchlayout::{LayoutEditor, TouchAction, TouchLayout},
+    world::{
+        atlas::load_tile_atlas, generation::WorldGenConfig, residency::ResidencyPolicy,
+        save::WorldSave, tile::*, *,
+    },
+};
 // use quad_url::*;
-use std::collections::HashMap;
-use touchbutton::Button;
 
-pub mod controlls;
-pub mod multiplayer;
-pub mod player;
-pub mod touchbutton;
-pub mod world;
+#[cfg(target_arch = "wasm32")]
+use rustgame::wasm::{self, WasmEventHandler};
 
 #[cfg(target_arch = "wasm32")]
-#[macro_use]
-extern crate lazy_static;
+fn get_multiplayer_handler() -> Box<dyn MultiplayerHandler> {
+    Box::new(WasmEventHandler {})
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn get_multiplayer_handler() -> Box<dyn MultiplayerHandler> {
+    use rustgame::multiplayer::net;
+
+    match net::ConnectOptions::from_args(std::env::args()) {
+        Some(options) => match net::TcpMultiplayer::connect(&options) {
+            Ok(handler) => {
+                info!("Connected to {}, joining {}", options.addr, options.room);
+                return Box::new(handler);
+            }
+            Err(err) => error!(
+                "Could not connect to {}: {}, playing offline",
+                options.addr, err
+            ),
+        },
+        None => (),
+    }
+    Box::new(DevLocalMultiplayer::new())
+}
 
 #[cfg(target_arch = "wasm32")]
-pub mod wasm;
+fn get_world_seed(fallback_seed: u32) -> u32 {
+    wasm::world_seed(fallback_seed)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn get_world_seed(fallback_seed: u32) -> u32 {
+    WorldGenConfig::seed_from_args(std::env::args()).unwrap_or(fallback_seed)
+}
+
 #[cfg(target_arch = "wasm32")]
-use crate::wasm::WasmEventHandler;
+fn get_tick_rate() -> f32 {
+    DEFAULT_TICK_RATE
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn get_tick_rate() -> f32 {
+    FixedTimestep::tick_rate_from_args(std::env::args()).unwrap_or(DEFAULT_TICK_RATE)
+}
 
 #[cfg(target_arch = "wasm32")]
-fn get_multiplayer_handler() -> Box<dyn MultiplayerHandler> {
-    Box::new(WasmEventHandler {})
+fn get_residency_policy() -> ResidencyPolicy {
+    ResidencyPolicy::default()
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-fn get_multiplayer_handler() -> Box<dyn MultiplayerHandler> {
-    Box::new(multiplayer::DevLocalMultiplayer::new())
+fn get_residency_policy() -> ResidencyPolicy {
+    ResidencyPolicy::from_args(std::env::args())
+}
+
+//There is no command line in the browser, recording is started with its key instead
+#[cfg(target_arch = "wasm32")]
+fn has_flag(_flag: &str) -> bool {
+    false
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn has_flag(flag: &str) -> bool {
+    std::env::args().any(|arg| arg == flag)
 }
 
-const MAX_ZOOM: f32 = 0.005;
-const MIN_ZOOM: f32 = 0.1;
-const MAX_RENDER: f32 = 256.;
+//`--replay` plays the last recording back instead of starting a game
+fn load_replay() -> Option<Recording> {
+    if !has_flag("--replay") {
+        return None;
+    }
+    match storage::load(REPLAY_KEY).map(|json| Recording::from_json(&json)) {
+        Some(Ok(recording)) => Some(recording),
+        Some(Err(err)) => {
+            error!("Could not read the recording: {}", err);
+            None
+        }
+        None => {
+            error!("There is no recording to replay");
+            None
+        }
+    }
+}
+
+fn save_recording(recording: &Recording) {
+    match storage::save(REPLAY_KEY, &recording.to_json()) {
+        Ok(_) => info!("Saved a recording of {} frames", recording.frames.len()),
+        Err(err) => warn!("Could not save the recording: {}", err),
+    }
+}
+
+//Worlds are saved per seed, so playing another seed never writes over a saved world
+fn world_save_key(seed: u32) -> String {
+    format!("world_{}", seed)
+}
+//Seed of the world played last, continued when no seed is asked for
+const LAST_WORLD_KEY: &str = "last_world";
+//Where the world was saved before saves were kept per seed
+const OLD_WORLD_SAVE_KEY: &str = "world";
+const AUTOSAVE_INTERVAL: f64 = 3.;
+
+fn read_world_save(key: &str) -> Option<WorldSave> {
+    match storage::load(key).map(|json| WorldSave::from_json(&json)) {
+        Some(Ok(save)) => Some(save),
+        Some(Err(err)) => {
+            warn!("Ignoring world save {}: {}", key, err);
+            None
+        }
+        None => None,
+    }
+}
+
+fn last_world_seed() -> Option<u32> {
+    match storage::load(LAST_WORLD_KEY) {
+        Some(seed) => seed.trim().parse().ok(),
+        None => read_world_save(OLD_WORLD_SAVE_KEY).map(|save| save.seed),
+    }
+}
 
-struct WorldCamera {
-    mode: CameraMode,
-    z: f32,
+fn load_world_save(seed: u32) -> Option<WorldSave> {
+    match read_world_save(&world_save_key(seed)) {
+        Some(save) => Some(save),
+        None => read_world_save(OLD_WORLD_SAVE_KEY).filter(|save| save.seed == seed),
+    }
 }
 
-enum CameraMode {
-    PlayerLock,
-    Follow(Vec2),
+fn save_world(world: &mut World) {
+    let seed = world.get_seed();
+    match storage::save(&world_save_key(seed), &world.to_save().to_json()) {
+        Ok(_) => world.mark_saved(),
+        Err(err) => warn!("Could not save world: {}", err),
+    }
+    match storage::save(LAST_WORLD_KEY, &seed.to_string()) {
+        Ok(_) => (),
+        Err(err) => warn!("Could not remember the world seed: {}", err),
+    }
 }
 
 struct TouchControll {
-    attack: Button,
-    roll: Button,
-    joystick: Joystick,
-    zoom_in: Button,
-    zoom_out: Button,
+    buttons: Vec<(TouchAction, Button, Vec2)>,
+    joystick: Option<Joystick>,
+    //Center and radius, touches starting there belong to the joystick
+    joystick_area: (Vec2, f32),
+    chat: Option<QuickChat>,
+    //Opens the layout editor
+    edit: Button,
+    //While on, touching the world places tiles there
+    placing: bool,
+    gestures: GestureTracker,
+    //Pinch and pan of the last update
+    gesture: Gesture,
+    //A touch no controll owns, where tiles are placed
+    pointer: Option<Vec2>,
 }
 
 impl TouchControll {
+    fn from_layout(layout: &TouchLayout) -> TouchControll {
+        let screen = vec2(screen_width(), screen_height());
+        let mut touch = TouchControll {
+            buttons: Vec::new(),
+            joystick: None,
+            joystick_area: (vec2(0., 0.), 0.),
+            chat: None,
+            edit: Button::rectangle(vec2(60., 30.), vec2(100., 40.)),
+            placing: false,
+            gestures: GestureTracker::new(),
+            gesture: Gesture::none(),
+            pointer: None,
+        };
+        for element in layout.elements() {
+            let center = element.center(screen, layout.left_handed);
+            let size = element.size(screen);
+            match element.action {
+                TouchAction::Joystick => {
+                    touch.joystick = Some(Joystick::new(center.x, center.y, size / 2.));
+                    touch.joystick_area = (center, size / 2.);
+                }
+                TouchAction::Chat => touch.chat = Some(QuickChat::new(center, size)),
+                action => {
+                    let mut button = Button::circle(center, size);
+                    button.set_opacity(layout.opacity);
+                    touch.buttons.push((action, button, center));
+                }
+            }
+        }
+        touch.edit.set_opacity(layout.opacity);
+        touch
+    }
+
     fn update(&mut self) {
-        self.attack.update();
-        self.roll.update();
-        self.zoom_in.update();
-        self.zoom_out.update();
+        for (_, button, _) in self.buttons.iter_mut() {
+            button.update();
+        }
+        self.edit.update();
+        if self.is_pressed(TouchAction::Place) {
+            self.placing = !self.placing;
+        }
+
+        let mut owned: Vec<Option<u64>> = self
+            .buttons
+            .iter()
+            .map(|(_, button, _)| button.touch_id())
+            .collect();
+        owned.push(self.edit.touch_id());
+        let (center, radius) = self.joystick_area;
+        self.gesture = self.gestures.update(&touches(), |touch| {
+            owned.contains(&Some(touch.id)) || touch.position.distance(center) < radius
+        });
+        //Two free fingers are a gesture, not a place to put tiles
+        let free = self.gestures.free_touches();
+        self.pointer = if free.len() == 1 { Some(free[0]) } else { None };
+    }
+
+    fn is_down(&mut self, action: TouchAction) -> bool {
+        self.buttons
+            .iter_mut()
+            .any(|(button_action, button, _)| *button_action == action && button.down())
+    }
+
+    fn is_pressed(&mut self, action: TouchAction) -> bool {
+        self.buttons
+            .iter_mut()
+            .any(|(button_action, button, _)| *button_action == action && button.pressed())
     }
 
     fn render(&self) {
-        self.attack.render();
-        self.roll.render();
-        self.zoom_in.render();
-        self.zoom_out.render();
-        self.joystick.render();
+        for (action, button, center) in self.buttons.iter() {
+            button.render();
+            let label = match action {
+                TouchAction::Place if self.placing => "Placing",
+                action => action.label(),
+            };
+            draw_label(label, *center);
+        }
+        self.edit.render();
+        draw_label("Layout", vec2(60., 30.));
+        match &self.joystick {
+            Some(joystick) => joystick.render(),
+            None => (),
+        }
+        match &self.chat {
+            Some(chat) => chat.render(),
+            None => (),
+        }
     }
 }
 
+fn draw_label(label: &str, center: Vec2) {
+    let size = measure_text(label, None, 24, 1.);
+    draw_text(
+        label,
+        center.x - size.width / 2.,
+        center.y + size.height / 2.,
+        24.,
+        BLACK,
+    );
+}
+
 #[macroquad::main("2D")]
 async fn main() {
     rand::srand(macroquad::miniquad::date::now() as _);
-    let mut multiplayer_handler = get_multiplayer_handler();
-    let mut other_players: HashMap<u32, Player> = HashMap::new();
-    let mut controller = Controller::default();
-
-    //Changable settings (camera, player etc)
-    let mut world_camera = WorldCamera {
-        mode: CameraMode::PlayerLock,
-        z: 0.04,
-    };
 
-    let mut screen_size = (screen_width(), screen_height());
-    if screen_height() > screen_width() {
-        controller.set(ToggleControll::Touch, true);
+    match load_replay() {
+        Some(recording) => return watch_replay(recording).await,
+        None => (),
     }
 
-    let mut world = World::generate();
+    //The last world is continued unless another seed is asked for, which continues the save
+    //of that seed if there is one
+    let fallback_seed = last_world_seed().unwrap_or_else(rand::rand);
+    let mut world_gen_config = WorldGenConfig::with_seed(get_world_seed(fallback_seed));
+    world_gen_config.tiles = load_tile_table().await;
+    info!("World seed {}", world_gen_config.seed);
+    let mut world = World::generate(world_gen_config);
+    match load_world_save(world.get_seed()) {
+        Some(save) => world.load_save(&save),
+        None => info!("No saved world for this seed, starting a new world"),
+    }
+    world.set_residency_policy(get_residency_policy());
+    let mut last_save = get_time();
+    world.set_atlas(load_tile_atlas().await);
 
     let (textures, texture_map) = load_textures().await;
 
-    let mut player = Player::new_playable(-3., -10., &texture_map, &textures);
-    player.respawn();
-
-    let mut player2 = Player::new_other(
-        String::from("Player2"),
-        10.,
-        10.,
-        0.,
-        0.,
+    let mut game = Game::new(
+        world,
+        get_multiplayer_handler(),
         &texture_map,
         &textures,
+        get_tick_rate(),
     );
-    let mut touch = make_touch_controlls();
+    game.controller.bindings = KeyBindings::load();
+    match GamepadBackend::new() {
+        Some(gamepad) => game.controller.add_backend(Box::new(gamepad)),
+        None => (),
+    }
+    let mut rebind = RebindScreen::new();
+
+    let mut screen_size = (screen_width(), screen_height());
+    if screen_height() > screen_width() {
+        game.controller.set(ToggleControll::Touch, true);
+    }
+    let mut layout = TouchLayout::load();
+    let mut editor = LayoutEditor::new();
+    let mut touch = TouchControll::from_layout(&layout);
+    if has_flag("--record") {
+        game.controller.set(ToggleControll::Record, true);
+    }
 
     loop {
         clear_background(LIGHTGRAY);
+        game.controller.poll();
 
-        controller.update();
-        touch.update();
-
-        //Handle multiplayer
-        for event in multiplayer_handler.get_events() {
-            match event {
-                Event::PlayerUpdate {
-                    name,
-                    id,
-                    x,
-                    y,
-                    vx,
-                    vy,
-                } => match other_players.get_mut(&id) {
-                    Some(other_player) => {
-                        other_player.set_position(vec2(x, y));
-                        other_player.set_velocity(vec2(vx, vy))
-                    }
-                    None => {
-                        let new_player =
-                            Player::new_other(name, x, y, vx, vy, &texture_map, &textures);
-                        other_players.insert(id, new_player);
-                    }
-                },
-                Event::PlayerDisconnect { id } => {
-                    other_players.remove(&id);
-                }
-                Event::PlayerAction {
-                    id,
-                    x,
-                    y,
-                    direction,
-                    action,
-                } => match other_players.get_mut(&id) {
-                    Some(other_player) => {
-                        other_player.force_action(action, vec2(x, y), direction);
-                        match action {
-                            player::BlockingAction::Attack => {
-                                debug!("Distance {}", player.get_position().distance(vec2(x, y)));
-                                if player.get_position().distance(vec2(x, y)) < 5. {
-                                    player.kill();
-                                    multiplayer_handler.upstream_event(Event::PlayerAction {
-                                        id: 0,
-                                        x: player.get_position().x,
-                                        y: player.get_position().y,
-                                        direction: player.get_direction(),
-                                        action: player::BlockingAction::Dying,
-                                    })
-                                }
-                            }
-                            _ => (),
-                        }
-                    }
-                    None => (),
-                },
-                Event::CommandTeleport { x, y } => {
-                    player.set_position(vec2(x, y));
-                }
+        if !game.chat.is_typing() && rebind.update(&mut game.controller.bindings) {
+            match game.controller.bindings.save() {
+                Ok(_) => (),
+                Err(err) => warn!("Could not save key bindings: {}", err),
             }
         }
 
-        multiplayer_handler.set_your_player_pos(player.get_position(), player.get_velocity());
-
-        if screen_size != (screen_width(), screen_height()) {
-            screen_size = (screen_width(), screen_height());
-            touch = make_touch_controlls();
-        }
-
-        //Handle controlls
-        handle_player_input(
-            &mut player,
-            &mut controller,
-            &mut world_camera,
-            &mut touch,
-            &mut multiplayer_handler,
-        );
-        handle_debug_player(&mut controller, &mut player2, &mut multiplayer_handler);
-
-        if !controller.is_enabled(ToggleControll::FreeZoom) {
-            if world_camera.z < MAX_ZOOM {
-                world_camera.z = MAX_ZOOM
-            }
-            if world_camera.z > MIN_ZOOM {
-                world_camera.z = MIN_ZOOM
+        let touch_mode = game.controller.is_enabled(ToggleControll::Touch);
+        if touch_mode && editor.update(&mut layout) {
+            match layout.save() {
+                Ok(_) => (),
+                Err(err) => warn!("Could not save touch layout: {}", err),
             }
+            touch = TouchControll::from_layout(&layout);
         }
 
-        //Controlls done, make view and render zones
-        let z = world_camera.z;
-        let target = match world_camera.mode {
-            CameraMode::PlayerLock => {
-                if controller.is_enabled(ToggleControll::Touch) {
-                    let map_height = 1. / (z * (screen_width() / screen_height())) * 2.;
-                    player.get_position() + vec2(0., map_height * 0.15)
-                } else {
-                    player.get_position()
-                }
+        let mut chat_input = None;
+        if !rebind.is_open() {
+            chat_input = game.chat.update();
+        }
+        if touch_mode && !editor.is_open() {
+            match &mut touch.chat {
+                Some(chat) => chat_input = chat_input.or(chat.update()),
+                None => (),
             }
-            CameraMode::Follow(target) => target,
-        };
-        let view_zone = make_view_rect(target, make_view_size(z));
-        let render_zone = make_view_rect(
-            player.get_position(),
-            make_view_size(z).normalize() * MAX_RENDER,
-        );
-        let debug_render = controller.is_enabled(ToggleControll::DebugHitbox);
-
-        //Update world
-        {
-            if controller.is_enabled(ToggleControll::TerrainGeneration) {
-                if controller.is(Controll::ForceRender) {
-                    world.generate_at(view_zone, view_zone);
-                } else {
-                    world.generate_at(render_zone, view_zone);
-                }
+            touch.update();
+            if touch.edit.pressed() {
+                editor.open();
             }
-            world.update_entity(&mut player, get_frame_time());
-            world.update_world_by_entity(&mut player);
+        }
 
-            for (_, other_player) in other_players.iter_mut() {
-                if controller.is_enabled(ToggleControll::OtherAnimations) {
-                    world.update_entity(other_player, get_frame_time());
-                    world.update_world_by_entity(other_player);
-                }
-            }
+        if screen_size != (screen_width(), screen_height()) {
+            screen_size = (screen_width(), screen_height());
+            touch = TouchControll::from_layout(&layout);
         }
 
-        //Render world
-        {
-            let zoom = vec2(z, -z * (screen_width() / screen_height()));
-            set_camera(&Camera2D {
-                target,
-                zoom: zoom,
-                ..Default::default()
-            });
-            let (font_size, font_scale, font_aspect) = camera_font_scale(2.);
-            let text_params = TextParams {
-                font_size,
-                font_scale,
-                font_scale_aspect: font_aspect,
-                color: BLACK,
+        //Keys and touches meant for the rebinding screen or the layout editor stay there
+        let mut input = if rebind.is_open() || editor.is_open() {
+            FrameInput {
+                screen: vec2(screen_width(), screen_height()),
                 ..Default::default()
-            };
-
-            world.render(view_zone);
-
-            let (mouse_x, mouse_y) = mouse_position();
-            let pos = ((vec2(mouse_x, mouse_y) - vec2(screen_width(), screen_height()) / 2.)
-                / vec2(screen_width(), screen_height())
-                * (make_view_size(z))
-                + target).round();
-            if target.distance_squared(pos) < 16.*16. {
-                draw_rectangle(pos.x, pos.y, 1., 1., BLACK);
-                if is_mouse_button_down(MouseButton::Left) {
-                    player.try_place(pos);
-                }
-            }
-            
-
-            for (_, other_player) in other_players.iter_mut() {
-                if controller.is_enabled(ToggleControll::OtherAnimations) {
-                    world.update_entity(other_player, get_frame_time());
-                }
-                other_player.render(&text_params, debug_render);
-            }
-
-            player.render(&text_params, debug_render);
-
-            if debug_render {
-                draw_rectangle_lines(
-                    render_zone.x,
-                    render_zone.y,
-                    render_zone.w,
-                    render_zone.h,
-                    1.,
-                    PINK,
-                )
             }
+        } else if touch_mode {
+            read_input(&mut game.controller, Some(&mut touch))
+        } else {
+            read_input(&mut game.controller, None)
+        };
+        input.chat = chat_input;
+        if !game.chat.is_typing() && !rebind.is_open() {
+            input.toggles = game.controller.pressed_toggles();
         }
+        game.update(input, get_frame_time());
 
-        //Exprimental map
-        if controller.is_enabled(ToggleControll::Map) {
-            set_default_camera();
-            draw_rectangle(
-                screen_width() * 0.08,
-                screen_height() * 0.08,
-                screen_width() * 0.84,
-                screen_height() * 0.84,
-                BROWN,
-            );
-
-            let z = 0.008;
-            let zoom = vec2(z, -z * (screen_width() / screen_height()));
-
-            let target = player.get_position() / 8.;
-            // let target = vec2(0.,0.);
-            set_camera(&Camera2D {
-                zoom,
-                target,
-                ..Default::default()
-            });
-            let map_zone = make_view_rect(target, make_view_size(z) * 0.8);
-            world.generate_at(
-                Rect::new(0., 0., 0., 0.),
-                make_view_rect(target * 8., make_view_size(z) * 8.),
-            );
-            world.render_map(map_zone);
-            let (font_size, font_scale, font_aspect) = camera_font_scale(2.);
-            let text_params = TextParams {
-                font_size,
-                font_scale,
-                font_scale_aspect: font_aspect,
-                color: BLACK,
-                ..Default::default()
-            };
-            for (_, other_player) in other_players.iter_mut() {
-                if controller.is_enabled(ToggleControll::OtherAnimations) {
-                    world.update_entity(other_player, get_frame_time());
-                }
-                let pos = other_player.get_position() / 8.;
-                draw_circle(pos.x, pos.y, 0.5, RED);
-                draw_text_ex(other_player.get_name(), pos.x, pos.y, text_params);
-            }
-            let pos = player.get_position() / 8.;
-            draw_circle(pos.x, pos.y, 0.5, RED);
-            draw_text_ex("You", pos.x, pos.y, text_params);
-
-            //teleport
-            if is_mouse_button_pressed(MouseButton::Middle) {
-                let (mouse_x, mouse_y) = mouse_position();
-                let pos = (vec2(mouse_x, mouse_y) - vec2(screen_width(), screen_height()) / 2.)
-                    / vec2(screen_width(), screen_height())
-                    * (make_view_size(z))
-                    * 8.
-                    + target * 8.;
-                player.set_position(pos);
-                controller.set(ToggleControll::Map, false);
+        let record = game.controller.is_enabled(ToggleControll::Record);
+        match (record, game.is_recording()) {
+            (true, false) => {
+                info!("Recording");
+                game.start_recording(macroquad::miniquad::date::now() as _);
             }
+            (false, true) => match game.stop_recording() {
+                Some(recording) => save_recording(&recording),
+                None => (),
+            },
+            _ => (),
         }
 
-        //render on screen
+        if game.is_local()
+            && game.world.has_unsaved_changes()
+            && get_time() - last_save > AUTOSAVE_INTERVAL
         {
-            set_default_camera();
-            draw_text("WASD to move player", 10.0, 30.0, 30.0, BLACK);
-            draw_text("Q-E to zoom camera", 10.0, 60.0, 30.0, BLACK);
-            draw_text("Space to roll", 10.0, 90.0, 30.0, BLACK);
-            draw_text("J to swing sword", 10.0, 120.0, 30.0, BLACK);
-            draw_text("M to open map", 10.0, 150.0, 30.0, BLACK);
-            draw_text(
-                &format!(
-                    "{:.0}, {:.0}",
-                    player.get_position().x,
-                    player.get_position().y
-                ),
-                10.0,
-                screen_height() - 10.,
-                30.0,
-                BLACK,
-            );
-            if controller.is_enabled(ToggleControll::Touch) {
-                touch.render();
-            }
+            save_world(&mut game.world);
+            last_save = get_time();
         }
 
+        game.render();
+        if touch_mode && editor.is_open() {
+            editor.render(&layout);
+        } else if touch_mode {
+            touch.render();
+        }
+        rebind.render(&game.controller.bindings);
+
         next_frame().await
     }
 }
 
-fn make_touch_controlls() -> TouchControll {
-    let size = if screen_width() > screen_height() {
-        screen_width()
-    } else {
-        screen_height()
-    };
+//Plays a recording back with the game otherwise left alone, nothing is saved or sent
+async fn watch_replay(recording: Recording) {
+    let mut world_gen_config = WorldGenConfig::with_seed(recording.world.seed);
+    world_gen_config.tiles = load_tile_table().await;
+    let mut world = recording.world(world_gen_config);
+    world.set_atlas(load_tile_atlas().await);
+    let (textures, texture_map) = load_textures().await;
 
-    let player_joystick = Joystick::new(screen_width() * 0.7, screen_height() * 0.8, size * 0.15);
-    // let mut camera_joytstick =
-    //     Joystick::new(screen_width() * 0.2, screen_height() * 0.8, size * 0.1);
-    let attack_button = Button::circle(
-        vec2(screen_width() * 0.2, screen_height() * 0.75),
-        size * 0.1,
-    );
-    let roll_button = Button::circle(
-        vec2(screen_width() * 0.2, screen_height() * 0.85),
-        size * 0.1,
-    );
-    let zoom_out_button = Button::rectangle(
-        vec2(screen_width() * 0.4, screen_height() * 0.97),
-        vec2(size * 0.1, size * 0.05),
+    let mut game = Game::new(
+        world,
+        Box::new(DevLocalMultiplayer::new()),
+        &texture_map,
+        &textures,
+        recording.tick_rate,
     );
+    recording.prepare(&mut game);
+    let mut replay = Replay::new(recording);
 
-    let zoom_in_button = Button::rectangle(
-        vec2(screen_width() * 0.6, screen_height() * 0.97),
-        vec2(size * 0.1, size * 0.05),
-    );
-    TouchControll {
-        attack: attack_button,
-        roll: roll_button,
-        joystick: player_joystick,
-        zoom_in: zoom_in_button,
-        zoom_out: zoom_out_button,
+    loop {
+        clear_background(LIGHTGRAY);
+        replay.update(&mut game);
+        game.render();
+        replay.render();
+        next_frame().await
     }
 }
 
-fn make_view_size(z: f32) -> Vec2 {
-    1. / (vec2(z, z * (screen_width() / screen_height()))) * 2.
-}
-
-fn make_view_rect(target: Vec2, size: Vec2) -> Rect {
-    let corner = target - size / 2.;
-    Rect::new(corner.x, corner.y, size.x, size.y)
-}
-
-fn handle_player_input(
-    player: &mut Player,
-    controller: &mut Controller,
-    world_camera: &mut WorldCamera,
-    touch: &mut TouchControll,
-    multiplayer_handler: &mut Box<dyn MultiplayerHandler>,
-) {
-    match (
-        &mut world_camera.mode,
-        controller.is_enabled(ToggleControll::FreeCamera),
-    ) {
-        (CameraMode::PlayerLock, true) => {
-            world_camera.mode = CameraMode::Follow(player.get_position())
-        }
-        (CameraMode::Follow(target), true) => {
-            let size = make_view_size(world_camera.z) * 0.3;
-            let zone = make_view_rect(*target, size);
-            if !zone.contains(player.get_position()) {
-                println!("Test: {}", (player.get_position() - *target) / size);
-                *target += (player.get_position() - *target) / size;
-            }
-        }
-        (CameraMode::Follow(_), false) => world_camera.mode = CameraMode::PlayerLock,
-        _ => (),
+//Keyboard, mouse and touch state for this frame
+fn read_input(controller: &mut Controller, touch: Option<&mut TouchControll>) -> FrameInput {
+    let mut input = FrameInput {
+        screen: vec2(screen_width(), screen_height()),
+        ..Default::default()
     };
 
-    let speed = 15.;
-    let mut velocity = vec2(0., 0.);
-    if controller.is(Controll::MoveRight) {
-        velocity.x = speed;
-    }
-    if controller.is(Controll::MoveLeft) {
-        velocity.x = -speed;
-    }
-    if controller.is(Controll::MoveUp) {
-        velocity.y = -speed;
-    }
-    if controller.is(Controll::MoveDown) {
-        velocity.y = speed;
-    }
-    velocity = velocity.normalize_or_zero() * speed;
-    player.set_velocity(velocity);
-
-    //Joystick
+    input.movement = controller.walk();
 
     if controller.is(Controll::ZoomIn) {
-        world_camera.z *= 1.1;
+        input.zoom *= 1.1;
     }
     if controller.is(Controll::ZoomOut) {
-        world_camera.z *= 0.9;
+        input.zoom *= 0.9;
     }
 
-    let mut action = None;
-
-    if controller.is_enabled(ToggleControll::Touch) {
-        let joystick_event = touch.joystick.update();
-        if joystick_event.direction != JoystickDirection::Idle {
-            player.set_velocity(
-                joystick_event.direction.to_local().normalize() * joystick_event.intensity * speed,
-            );
-        }
+    //The mouse follows the first touch, so it only points and places without touch controlls
+    match touch {
+        Some(touch) => {
+            match &mut touch.joystick {
+                Some(joystick) => {
+                    let joystick_event = joystick.update();
+                    if joystick_event.direction != JoystickDirection::Idle {
+                        input.movement = joystick_event.direction.to_local().normalize()
+                            * joystick_event.intensity;
+                    }
+                }
+                None => (),
+            }
 
-        if touch.zoom_in.down() {
-            world_camera.z *= 1.03;
-        }
-        if touch.zoom_out.down() {
-            world_camera.z *= 0.97;
-        }
-        if touch.attack.down() {
-            action = Some(player::BlockingAction::Attack)
+            input.zoom *= touch.gesture.zoom;
+            input.pan = touch.gesture.pan;
+            if touch.is_down(TouchAction::Attack) {
+                input.action = Some(player::BlockingAction::Attack)
+            }
+            if touch.is_down(TouchAction::Roll) {
+                input.action = Some(player::BlockingAction::Roll)
+            }
+            if touch.is_down(TouchAction::Block) {
+                input.action = Some(player::BlockingAction::Block)
+            }
+            if touch.placing {
+                input.pointer = touch.pointer;
+                input.place = touch.pointer.is_some();
+            }
         }
-        if touch.roll.down() {
-            action = Some(player::BlockingAction::Roll)
+        None => {
+            let (mouse_x, mouse_y) = mouse_position();
+            input.pointer = Some(vec2(mouse_x, mouse_y));
+            input.place = is_mouse_button_down(MouseButton::Left);
+            input.teleport = is_mouse_button_pressed(MouseButton::Middle);
         }
     }
 
     if controller.is(Controll::Attack) {
-        action = Some(player::BlockingAction::Attack)
+        input.action = Some(player::BlockingAction::Attack)
     }
     if controller.is(Controll::Roll) {
-        action = Some(player::BlockingAction::Roll)
+        input.action = Some(player::BlockingAction::Roll)
     }
     if controller.is(Controll::Block) {
-        action = Some(player::BlockingAction::Block)
-    }
-
-    match action {
-        Some(action) => match player.try_action(action) {
-            Ok(_) => multiplayer_handler.upstream_event(Event::PlayerAction {
-                id: 0,
-                x: player.get_position().x,
-                y: player.get_position().y,
-                direction: player.get_direction(),
-                action: action,
-            }),
-            Err(_) => (),
-        },
-        None => (),
+        input.action = Some(player::BlockingAction::Block)
     }
-}
-
-fn handle_debug_player(
-    controller: &mut Controller,
-    player2: &mut Player,
-    multiplayer_handler: &mut Box<dyn MultiplayerHandler>,
-) {
-    if controller.is_enabled(ToggleControll::SecondaryPlayer) {
-        player2.update(
-            &TileInteraction::Walkable,
-            &TileAction::None,
-            get_frame_time(),
-        );
-        let speed = 20.;
-        let mut velocity = vec2(0., 0.);
-        if controller.is(Controll::MoveSecondaryRight) {
-            velocity.x = speed;
-        }
-        if controller.is(Controll::MoveSecondaryLeft) {
-            velocity.x = -speed;
-        }
-        if controller.is(Controll::MoveSecondaryUp) {
-            velocity.y = -speed;
-        }
-        if controller.is(Controll::MoveSecondaryDown) {
-            velocity.y = speed;
-        }
-        velocity = velocity.normalize_or_zero() * speed;
-        player2.set_velocity(velocity);
-        player2.set_position(player2.get_position() + player2.get_velocity() * get_frame_time());
-        let player_update = Event::PlayerUpdate {
-            name: String::from("Player2"),
-            id: 0,
-            x: player2.get_position().x,
-            y: player2.get_position().y,
-            vx: player2.get_velocity().x,
-            vy: player2.get_velocity().y,
-        };
-        multiplayer_handler.downstream_event(player_update);
-        let mut action = None;
-        if controller.is(Controll::SecondaryAttack) {
-            action = Some(player::BlockingAction::Attack)
-        }
-        if controller.is(Controll::SecondaryRoll) {
-            action = Some(player::BlockingAction::Roll)
-        }
+    input.force_render = controller.is(Controll::ForceRender);
 
-        match action {
-            Some(action) => match player2.try_action(action) {
-                Ok(_) => {
-                    let event = Event::PlayerAction {
-                        id: 0,
-                        x: player2.get_position().x,
-                        y: player2.get_position().y,
-                        direction: player2.get_direction(),
-                        action,
-                    };
-                    multiplayer_handler.downstream_event(event)
-                }
-                Err(_) => (),
-            },
-            None => (),
-        }
+    let mut secondary = vec2(0., 0.);
+    if controller.is(Controll::MoveSecondaryRight) {
+        secondary.x = 1.;
+    }
+    if controller.is(Controll::MoveSecondaryLeft) {
+        secondary.x = -1.;
+    }
+    if controller.is(Controll::MoveSecondaryUp) {
+        secondary.y = -1.;
+    }
+    if controller.is(Controll::MoveSecondaryDown) {
+        secondary.y = 1.;
+    }
+    input.secondary_movement = secondary;
+    if controller.is(Controll::SecondaryAttack) {
+        input.secondary_action = Some(player::BlockingAction::Attack)
+    }
+    if controller.is(Controll::SecondaryRoll) {
+        input.secondary_action = Some(player::BlockingAction::Roll)
     }
+    input
 }
 
 // let mut my_boolean = true;
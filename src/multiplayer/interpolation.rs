@@ -0,0 +1,214 @@
+use std::collections::VecDeque;
+
+use macroquad::prelude::*;
+
+#[derive(Debug, Clone, Copy)]
+pub struct InterpolationConfig {
+    //How far behind the newest update remote players are shown, in seconds. A couple of
+    //update intervals keeps two snapshots to interpolate between even when one is late
+    pub delay: f64,
+    //How long a player keeps moving on its last velocity once updates stop
+    pub max_extrapolation: f64,
+    //Snapshots kept per player
+    pub buffer_length: usize,
+}
+
+impl Default for InterpolationConfig {
+    fn default() -> Self {
+        InterpolationConfig {
+            delay: 0.1,
+            max_extrapolation: 0.25,
+            buffer_length: 32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Snapshot {
+    pub time: f64,
+    pub pos: Vec2,
+    pub velocity: Vec2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub pos: Vec2,
+    pub velocity: Vec2,
+    pub extrapolated: bool,
+}
+
+//Updates of one remote player stamped with when they were sent, on the local clock
+pub struct SnapshotBuffer {
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl SnapshotBuffer {
+    pub fn new() -> SnapshotBuffer {
+        SnapshotBuffer {
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, config: &InterpolationConfig, time: f64, pos: Vec2, velocity: Vec2) {
+        //Stamps only go forward, but a reset clock should not wedge the buffer
+        if let Some(last) = self.snapshots.back() {
+            if time < last.time {
+                self.snapshots.clear();
+            }
+        }
+        self.snapshots.push_back(Snapshot {
+            time,
+            pos,
+            velocity,
+        });
+        while self.snapshots.len() > config.buffer_length.max(2) {
+            self.snapshots.pop_front();
+        }
+    }
+
+    //Where the player is shown at `now`, which is `delay` seconds in the past
+    pub fn sample(&self, now: f64, config: &InterpolationConfig) -> Option<Sample> {
+        let render_time = now - config.delay;
+        let first = self.snapshots.front()?;
+        if render_time <= first.time {
+            return Some(Sample {
+                pos: first.pos,
+                velocity: first.velocity,
+                extrapolated: false,
+            });
+        }
+
+        for (from, to) in self.snapshots.iter().zip(self.snapshots.iter().skip(1)) {
+            if render_time <= to.time {
+                let t = ((render_time - from.time) / (to.time - from.time)) as f32;
+                return Some(Sample {
+                    pos: from.pos.lerp(to.pos, t),
+                    velocity: to.velocity,
+                    extrapolated: false,
+                });
+            }
+        }
+
+        let last = self.snapshots.back()?;
+        let ahead = (render_time - last.time).min(config.max_extrapolation);
+        Some(Sample {
+            pos: last.pos + last.velocity * ahead as f32,
+            velocity: if render_time - last.time > config.max_extrapolation {
+                vec2(0., 0.)
+            } else {
+                last.velocity
+            },
+            extrapolated: true,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    //Snapshots as dots joined by lines and the sampled position as a ring, in world space
+    pub fn render_debug(&self, now: f64, config: &InterpolationConfig) {
+        for (from, to) in self.snapshots.iter().zip(self.snapshots.iter().skip(1)) {
+            draw_line(from.pos.x, from.pos.y, to.pos.x, to.pos.y, 0.05, DARKBLUE);
+        }
+        for snapshot in self.snapshots.iter() {
+            draw_circle(snapshot.pos.x, snapshot.pos.y, 0.15, BLUE);
+        }
+        match self.sample(now, config) {
+            Some(sample) => {
+                let color = if sample.extrapolated { ORANGE } else { GREEN };
+                draw_circle_lines(sample.pos.x, sample.pos.y, 0.4, 0.08, color);
+            }
+            None => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: InterpolationConfig = InterpolationConfig {
+        delay: 0.1,
+        max_extrapolation: 0.25,
+        buffer_length: 4,
+    };
+
+    //Moving right at 10 tiles per second, one update every 0.1 seconds from time 1
+    fn walking(updates: usize) -> SnapshotBuffer {
+        let mut buffer = SnapshotBuffer::new();
+        for i in 0..updates {
+            let time = 1. + i as f64 * 0.1;
+            buffer.push(&CONFIG, time, vec2(i as f32, 0.), vec2(10., 0.));
+        }
+        buffer
+    }
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        a.distance(b) < 0.0001
+    }
+
+    #[test]
+    fn an_empty_buffer_has_nothing_to_show() {
+        assert_eq!(SnapshotBuffer::new().sample(1., &CONFIG), None);
+    }
+
+    #[test]
+    fn samples_between_snapshots_are_interpolated() {
+        let buffer = walking(3);
+        //Shown `delay` behind, so at 1.25 the player is a quarter of the way past the second
+        let sample = buffer.sample(1.25, &CONFIG).unwrap();
+        assert!(close(sample.pos, vec2(1.5, 0.)), "{:?}", sample);
+        assert_eq!(sample.velocity, vec2(10., 0.));
+        assert!(!sample.extrapolated);
+
+        let on_a_snapshot = buffer.sample(1.3, &CONFIG).unwrap();
+        assert!(close(on_a_snapshot.pos, vec2(2., 0.)));
+        assert!(!on_a_snapshot.extrapolated);
+    }
+
+    #[test]
+    fn samples_before_the_oldest_snapshot_stay_on_it() {
+        let buffer = walking(3);
+        for now in [0., 1., 1.1] {
+            let sample = buffer.sample(now, &CONFIG).unwrap();
+            assert_eq!(sample.pos, vec2(0., 0.));
+            assert!(!sample.extrapolated);
+        }
+    }
+
+    #[test]
+    fn extrapolation_stops_after_max_extrapolation() {
+        let buffer = walking(3);
+        //The last snapshot is at 1.2 and shown at 1.3
+        let sample = buffer.sample(1.4, &CONFIG).unwrap();
+        assert!(close(sample.pos, vec2(3., 0.)), "{:?}", sample);
+        assert_eq!(sample.velocity, vec2(10., 0.));
+        assert!(sample.extrapolated);
+
+        let stopped = buffer.sample(10., &CONFIG).unwrap();
+        assert!(close(stopped.pos, vec2(4.5, 0.)), "{:?}", stopped);
+        assert_eq!(stopped.velocity, vec2(0., 0.));
+        assert!(stopped.extrapolated);
+    }
+
+    #[test]
+    fn old_snapshots_are_dropped() {
+        let buffer = walking(10);
+        assert_eq!(buffer.len(), CONFIG.buffer_length);
+        //The oldest kept is the seventh update
+        assert_eq!(buffer.sample(0., &CONFIG).unwrap().pos, vec2(6., 0.));
+    }
+
+    #[test]
+    fn a_clock_going_back_starts_over() {
+        let mut buffer = walking(3);
+        buffer.push(&CONFIG, 0.5, vec2(-5., 0.), vec2(0., 0.));
+        assert_eq!(buffer.len(), 1);
+        assert_eq!(buffer.sample(2., &CONFIG).unwrap().pos, vec2(-5., 0.));
+    }
+}
@@ -0,0 +1,163 @@
+use macroquad::prelude::Vec2;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+    chat::Emote,
+    player::BlockingAction,
+    world::{entity::Direction, save::ChunkSave, tile::Tile},
+};
+
+pub mod interpolation;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod net;
+pub mod prediction;
+pub mod protocol;
+
+//Room players end up in when they do not ask for one
+pub const DEFAULT_ROOM: &str = "main";
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum Event {
+    PlayerUpdate {
+        name: String,
+        id: u32,
+        x: f32,
+        y: f32,
+        vx: f32,
+        vy: f32,
+        //Last movement input the position includes, 0 when it does not come from input
+        seq: u32,
+        //Seconds on the clock of whoever sent it when the position was current, 0 when the
+        //sender has no clock to go by and it is stamped when it arrives instead
+        time: f64,
+    },
+    PlayerAction {
+        id: u32,
+        x: f32,
+        y: f32,
+        direction: Direction,
+        action: BlockingAction,
+        //Seconds on the clock of whoever did the action, used to resolve hits at the right moment
+        time: f64,
+    },
+    PlayerDisconnect {
+        id: u32,
+    },
+    CommandTeleport {
+        x: f32,
+        y: f32
+    },
+    //The server decided you were hit: `health` is what you have left, you die at 0, and
+    //(kx, ky) the velocity you are knocked back with
+    CommandHit {
+        health: u32,
+        kx: f32,
+        ky: f32,
+    },
+    //Where the server put you after your update `seq`
+    CommandAck {
+        seq: u32,
+        x: f32,
+        y: f32,
+    },
+    //A tile was dug or placed, sent by whoever changed it and passed on by the server
+    //with the revision the chunk is at afterwards
+    TileChanged {
+        x: i32,
+        y: i32,
+        tile: Tile,
+        revision: u32,
+    },
+    //Sent when a chunk is generated, answered with ChunkData if the server's revision is newer
+    ChunkRequest {
+        x: i32,
+        y: i32,
+        revision: u32,
+    },
+    ChunkData {
+        chunk: ChunkSave,
+    },
+    //First thing a client sends, answered with Welcome or Refused. Neither of them may change
+    //between versions, see protocol::from_binary
+    Join {
+        name: String,
+        room: String,
+        version: u16,
+    },
+    //Your id on the server and the seed of the room's world
+    Welcome {
+        id: u32,
+        room: String,
+        seed: u32,
+    },
+    Refused {
+        reason: String,
+    },
+    //Someone is in your room, sent for everyone already there when you join
+    PlayerJoined {
+        id: u32,
+        name: String,
+    },
+    Leave,
+    Chat {
+        id: u32,
+        text: String,
+    },
+    Emote {
+        id: u32,
+        emote: Emote,
+    },
+    //Someone lost `damage` health to an attack and has `health` left, sent by the server
+    //or, without one, by whoever was hit
+    PlayerHit {
+        id: u32,
+        damage: u32,
+        health: u32,
+        blocked: bool,
+    },
+}
+
+pub trait MultiplayerHandler {
+    fn get_events(&mut self) -> Vec<Event>;
+    fn upstream_event(&mut self, event: Event);
+    fn set_your_player_pos(&mut self, pos: Vec2, v: Vec2, seq: u32, time: f64);
+    fn downstream_event(&mut self, event: Event); //Only necessary for local
+    //When true the server decides hits and tells the client when it died
+    fn is_authoritative(&self) -> bool;
+}
+
+pub struct DevLocalMultiplayer {
+    events: Vec<Event>
+}
+
+impl DevLocalMultiplayer {
+    pub fn new() -> DevLocalMultiplayer{
+        DevLocalMultiplayer {
+            events: Vec::new()
+        }
+    }
+}
+
+impl MultiplayerHandler for DevLocalMultiplayer {
+    fn get_events(&mut self) -> Vec<Event> {
+        let size = self.events.len();
+        let events = self.events.drain(0..size).collect();
+        return events;
+    }
+
+    fn set_your_player_pos(&mut self, _pos: Vec2, _v: Vec2, _seq: u32, _time: f64) {
+        
+    }
+
+    fn upstream_event(&mut self, _event: Event) {
+        
+    }
+
+    fn downstream_event(&mut self, event: Event) {
+        self.events.push(event)
+    }
+
+    fn is_authoritative(&self) -> bool {
+        false
+    }
+}
\ No newline at end of file
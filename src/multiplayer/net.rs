@@ -0,0 +1,273 @@
+//Native multiplayer over plain TCP, between the server binary and desktop clients.
+//Browsers can not open raw sockets and stay on the JS bridge in wasm.rs, so desktop and
+//browser players only share a session once the server also takes WebSocket connections,
+//which it does not yet
+
+use std::{
+    collections::HashSet,
+    io::{self, ErrorKind, Read, Write},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+use macroquad::prelude::{error, warn, Vec2};
+
+use super::{
+    protocol::{self, DecodeError, PROTOCOL_VERSION},
+    Event, MultiplayerHandler, DEFAULT_ROOM,
+};
+
+//Largest packet accepted, anything bigger means the stream is out of sync
+const MAX_PACKET: usize = 64 * 1024;
+//Bytes queued for a peer that is not reading them before it is given up on
+const MAX_BACKLOG: usize = 256 * 1024;
+
+//Binary packets from the protocol module, each prefixed with its length as a u32,
+//shared by the server and native clients
+pub struct Connection {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    //Frames the socket has not taken yet, the stream never blocks so one slow peer can not
+    //hold up everyone else
+    outgoing: Vec<u8>,
+    //The peer hung up, what it sent before that is still handed out
+    closed: bool,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> io::Result<Connection> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Connection {
+            stream,
+            buffer: Vec::new(),
+            outgoing: Vec::new(),
+            closed: false,
+        })
+    }
+
+    pub fn connect(addr: &str) -> io::Result<Connection> {
+        Connection::new(TcpStream::connect(addr)?)
+    }
+
+    //Queues the event and writes as much of the queue as the socket takes, an error means
+    //the connection is gone or the peer stopped reading
+    pub fn send(&mut self, event: &Event) -> io::Result<()> {
+        let packet = protocol::to_binary(event);
+        self.outgoing
+            .extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        self.outgoing.extend_from_slice(&packet);
+        self.flush()?;
+        if self.outgoing.len() > MAX_BACKLOG {
+            return Err(io::Error::new(
+                ErrorKind::WouldBlock,
+                "peer is not reading what it is sent",
+            ));
+        }
+        Ok(())
+    }
+
+    //Writes whatever of the queue the socket takes without waiting, called every tick
+    pub fn flush(&mut self) -> io::Result<()> {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return Err(io::Error::new(ErrorKind::WriteZero, "disconnected")),
+                Ok(written) => {
+                    self.outgoing.drain(..written);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    //Everything that has arrived since the last call, an error means the connection is gone
+    pub fn receive(&mut self) -> io::Result<Vec<Event>> {
+        let mut chunk = [0; 4096];
+        while !self.closed {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => self.closed = true,
+                Ok(read) => self.buffer.extend_from_slice(&chunk[..read]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+
+        let mut events = Vec::new();
+        while self.buffer.len() >= 4 {
+            let len = u32::from_le_bytes(self.buffer[..4].try_into().unwrap()) as usize;
+            if len > MAX_PACKET {
+                return Err(io::Error::new(ErrorKind::InvalidData, "packet too large"));
+            }
+            if self.buffer.len() < 4 + len {
+                break;
+            }
+            match protocol::from_binary(&self.buffer[4..4 + len]) {
+                Ok(event) => events.push(event),
+                //Nothing else from a peer on another version can be understood, what came
+                //before is handed out first so a Join from it can still be refused
+                Err(err @ DecodeError::UnsupportedVersion(_)) => {
+                    if events.is_empty() {
+                        return Err(io::Error::new(ErrorKind::InvalidData, err.to_string()));
+                    }
+                    break;
+                }
+                Err(err) => warn!("Dropping malformed event: {}", err),
+            }
+            self.buffer.drain(..4 + len);
+        }
+        if self.closed && events.is_empty() {
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, "disconnected"));
+        }
+        Ok(events)
+    }
+
+    pub fn peer(&self) -> String {
+        match self.stream.peer_addr() {
+            Ok(addr) => addr.to_string(),
+            Err(_) => String::from("unknown"),
+        }
+    }
+}
+
+//How often the position of your player is sent, the server ticks at 20 by default
+const UPDATE_INTERVAL: Duration = Duration::from_millis(50);
+
+pub struct ConnectOptions {
+    pub addr: String,
+    //Left empty the server picks one
+    pub name: String,
+    pub room: String,
+}
+
+impl ConnectOptions {
+    //Reads `--connect <host:port>`, `--name <name>` and `--room <room>`, None without `--connect`
+    pub fn from_args(args: impl Iterator<Item = String>) -> Option<ConnectOptions> {
+        let args: Vec<String> = args.collect();
+        let value = |flag: &str| {
+            args.windows(2)
+                .find(|pair| pair[0] == flag)
+                .map(|pair| pair[1].clone())
+        };
+        Some(ConnectOptions {
+            addr: value("--connect")?,
+            name: value("--name").unwrap_or_default(),
+            room: value("--room").unwrap_or_else(|| DEFAULT_ROOM.to_owned()),
+        })
+    }
+}
+
+//Native client for the server binary, speaks the same events as the browser bridge
+pub struct TcpMultiplayer {
+    connection: Option<Connection>,
+    events: Vec<Event>,
+    //Players seen so far, removed for everyone if the connection drops
+    known_players: HashSet<u32>,
+    last_update: Option<Instant>,
+}
+
+impl TcpMultiplayer {
+    //Joins the room right away, the server answers with Welcome or Refused
+    pub fn connect(options: &ConnectOptions) -> io::Result<TcpMultiplayer> {
+        let mut connection = Connection::connect(&options.addr)?;
+        connection.send(&Event::Join {
+            name: options.name.clone(),
+            room: options.room.clone(),
+            version: PROTOCOL_VERSION,
+        })?;
+        Ok(TcpMultiplayer {
+            connection: Some(connection),
+            events: Vec::new(),
+            known_players: HashSet::new(),
+            last_update: None,
+        })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    fn send(&mut self, event: &Event) {
+        if let Some(connection) = &mut self.connection {
+            if let Err(err) = connection.send(event) {
+                error!("Lost connection to server: {}", err);
+                self.disconnected();
+            }
+        }
+    }
+
+    fn disconnected(&mut self) {
+        self.connection = None;
+        for id in self.known_players.drain() {
+            self.events.push(Event::PlayerDisconnect { id });
+        }
+    }
+}
+
+impl MultiplayerHandler for TcpMultiplayer {
+    fn get_events(&mut self) -> Vec<Event> {
+        let received = match &mut self.connection {
+            Some(connection) => connection.flush().and_then(|_| connection.receive()),
+            None => Ok(Vec::new()),
+        };
+        match received {
+            Ok(events) => {
+                for event in events {
+                    match &event {
+                        Event::PlayerUpdate { id, .. }
+                        | Event::PlayerAction { id, .. }
+                        | Event::PlayerJoined { id, .. } => {
+                            self.known_players.insert(*id);
+                        }
+                        Event::PlayerDisconnect { id } => {
+                            self.known_players.remove(id);
+                        }
+                        _ => (),
+                    }
+                    self.events.push(event);
+                }
+            }
+            Err(err) => {
+                error!("Lost connection to server: {}", err);
+                self.disconnected();
+            }
+        }
+        self.events.drain(..).collect()
+    }
+
+    fn upstream_event(&mut self, event: Event) {
+        self.send(&event);
+    }
+
+    fn set_your_player_pos(&mut self, pos: Vec2, v: Vec2, seq: u32, time: f64) {
+        let due = match self.last_update {
+            Some(last_update) => last_update.elapsed() >= UPDATE_INTERVAL,
+            None => true,
+        };
+        if due {
+            self.last_update = Some(Instant::now());
+            //The server knows who we are from the connection, so the id and name are left empty
+            self.send(&Event::PlayerUpdate {
+                name: String::new(),
+                id: 0,
+                x: pos.x,
+                y: pos.y,
+                vx: v.x,
+                vy: v.y,
+                seq,
+                time,
+            });
+        }
+    }
+
+    fn downstream_event(&mut self, event: Event) {
+        self.events.push(event)
+    }
+
+    fn is_authoritative(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::VecDeque;
+
+use macroquad::prelude::*;
+
+use crate::world::World;
+
+//Inputs kept waiting for an acknowledgement, handlers that never acknowledge stop here
+const MAX_PENDING: usize = 240;
+//Moving further than the velocity explains within a frame is a teleport or respawn
+const JUMP_SLACK: f32 = 1.;
+//Errors smaller than this are rounding, not a disagreement with the server
+const IN_SYNC: f32 = 0.01;
+//Errors larger than this are snapped to instead of smoothed
+const SNAP_DISTANCE: f32 = 3.;
+//How fast a correction is blended in, about 95% is applied after 3 / SMOOTHING seconds
+const SMOOTHING: f32 = 10.;
+
+#[derive(Debug, Clone, Copy)]
+struct Input {
+    seq: u32,
+    velocity: Vec2,
+    dt: f32,
+    //Where the player ended up, only used on replay when the input was a jump
+    end: Vec2,
+    jump: bool,
+}
+
+//Local player movement is applied at once and numbered, when the server acknowledges a
+//number with where it put the player, the inputs after it are replayed from there
+pub struct Prediction {
+    next_seq: u32,
+    pending: VecDeque<Input>,
+    last_pos: Option<Vec2>,
+    //What is left to blend into the player position
+    correction: Vec2,
+}
+
+impl Prediction {
+    pub fn new() -> Prediction {
+        Prediction {
+            next_seq: 1,
+            pending: VecDeque::new(),
+            last_pos: None,
+            correction: vec2(0., 0.),
+        }
+    }
+
+    //Call after the player moved this frame with the velocity it had before moving,
+    //returns the sequence number to send with the position
+    pub fn record(&mut self, velocity: Vec2, dt: f32, end: Vec2) -> u32 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let jump = match self.last_pos {
+            Some(last_pos) => last_pos.distance(end) > velocity.length() * dt + JUMP_SLACK,
+            None => true,
+        };
+        self.pending.push_back(Input {
+            seq,
+            velocity,
+            dt,
+            end,
+            jump,
+        });
+        if self.pending.len() > MAX_PENDING {
+            self.pending.pop_front();
+        }
+        self.last_pos = Some(end);
+        seq
+    }
+
+    //Where the server had the player after `seq`, returns a position to snap to when
+    //the difference is too big to smooth out
+    pub fn acknowledge(
+        &mut self,
+        world: &World,
+        seq: u32,
+        server_pos: Vec2,
+        current: Vec2,
+    ) -> Option<Vec2> {
+        //Already acknowledged or dropped, nothing to compare against
+        let acknowledged = self.pending.iter().position(|input| input.seq == seq)?;
+        self.pending.drain(..=acknowledged);
+
+        let replayed = self.replay(world, server_pos);
+        let error = replayed - current;
+        if error.length() <= IN_SYNC {
+            self.correction = vec2(0., 0.);
+            None
+        } else if error.length() > SNAP_DISTANCE {
+            self.correction = vec2(0., 0.);
+            self.last_pos = Some(replayed);
+            Some(replayed)
+        } else {
+            //Replaces any correction still in progress, the replay already accounts for it
+            self.correction = error;
+            None
+        }
+    }
+
+    fn replay(&self, world: &World, from: Vec2) -> Vec2 {
+        self.pending.iter().fold(from, |pos, input| {
+            if input.jump {
+                input.end
+            } else {
+                world.move_position(pos, input.velocity, input.dt)
+            }
+        })
+    }
+
+    //The part of the outstanding correction to apply this frame
+    pub fn smooth(&mut self, dt: f32) -> Vec2 {
+        let step = self.correction * (1. - (-SMOOTHING * dt).exp());
+        self.correction -= step;
+        if let Some(last_pos) = &mut self.last_pos {
+            *last_pos += step;
+        }
+        step
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn correction(&self) -> Vec2 {
+        self.correction
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::positions::Coords;
+
+    const DT: f32 = 1. / 60.;
+    const RIGHT: Vec2 = Vec2::from_array([6., 0.]);
+
+    //Walks right `frames` times from `start`, predicting every step like Game does
+    fn walk(prediction: &mut Prediction, world: &World, start: Vec2, frames: usize) -> Vec2 {
+        (0..frames).fold(start, |pos, _| {
+            let end = world.move_position(pos, RIGHT, DT);
+            prediction.record(RIGHT, DT, end);
+            end
+        })
+    }
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        a.distance(b) < 0.0001
+    }
+
+    #[test]
+    fn an_ack_that_agrees_changes_nothing() {
+        let world = World::grass_with_walls(&[]);
+        let mut prediction = Prediction::new();
+        let start = vec2(-5., 0.);
+        let first = walk(&mut prediction, &world, start, 1);
+        let current = walk(&mut prediction, &world, first, 4);
+        assert_eq!(prediction.pending(), 5);
+
+        assert_eq!(prediction.acknowledge(&world, 1, first, current), None);
+        assert_eq!(prediction.pending(), 4);
+        assert_eq!(prediction.correction(), vec2(0., 0.));
+        assert_eq!(prediction.smooth(DT), vec2(0., 0.));
+        //Acknowledged inputs are gone, a repeat of the same ack is ignored
+        assert_eq!(prediction.acknowledge(&world, 1, start, current), None);
+        assert_eq!(prediction.pending(), 4);
+    }
+
+    #[test]
+    fn a_different_ack_replays_the_pending_inputs_from_there() {
+        let world = World::grass_with_walls(&[]);
+        let mut prediction = Prediction::new();
+        let first = walk(&mut prediction, &world, vec2(-5., 0.), 1);
+        let current = walk(&mut prediction, &world, first, 4);
+
+        //The server put the player a tile lower, the four later steps still happen from there
+        let server_pos = first + vec2(0., 1.);
+        let replayed = (0..4).fold(server_pos, |pos, _| world.move_position(pos, RIGHT, DT));
+        assert_eq!(prediction.acknowledge(&world, 1, server_pos, current), None);
+        assert!(close(prediction.correction(), replayed - current));
+        assert!(close(prediction.correction(), vec2(0., 1.)));
+
+        //Blended in over a few frames instead of all at once
+        let first_step = prediction.smooth(DT);
+        assert!(first_step.y > 0. && first_step.y < 1.);
+        let applied = (0..60).fold(first_step, |applied, _| applied + prediction.smooth(DT));
+        assert!(close(applied, vec2(0., 1.)));
+        assert!(close(prediction.correction(), vec2(0., 0.)));
+    }
+
+    #[test]
+    fn a_far_ack_is_snapped_to() {
+        let world = World::grass_with_walls(&[]);
+        let mut prediction = Prediction::new();
+        let first = walk(&mut prediction, &world, vec2(-5., 0.), 1);
+        let current = walk(&mut prediction, &world, first, 2);
+
+        let server_pos = vec2(5., 5.);
+        let replayed = (0..2).fold(server_pos, |pos, _| world.move_position(pos, RIGHT, DT));
+        let snapped = prediction
+            .acknowledge(&world, 1, server_pos, current)
+            .unwrap();
+        assert!(close(snapped, replayed));
+        assert_eq!(prediction.correction(), vec2(0., 0.));
+    }
+
+    #[test]
+    fn replays_run_into_the_walls_where_the_server_put_the_player() {
+        //A wall right of where the server has the player, but not along the predicted path
+        let world = World::grass_with_walls(&[Coords { x: 1, y: 2 }]);
+        let mut prediction = Prediction::new();
+        let first = walk(&mut prediction, &world, vec2(0., 0.), 1);
+        let current = walk(&mut prediction, &world, first, 30);
+        assert!(current.x > 2.);
+
+        let server_pos = vec2(first.x, 2.);
+        let snapped = prediction
+            .acknowledge(&world, 1, server_pos, current)
+            .unwrap();
+        assert!(snapped.x < 1., "{:?}", snapped);
+        assert!((snapped.y - 2.).abs() < 0.0001);
+    }
+
+    #[test]
+    fn jumps_are_replayed_as_jumps() {
+        let world = World::grass_with_walls(&[]);
+        let mut prediction = Prediction::new();
+        let first = walk(&mut prediction, &world, vec2(0., 0.), 1);
+        //A respawn or teleport, more than walking explains
+        let landed = vec2(-10., -10.);
+        prediction.record(RIGHT, DT, landed);
+
+        let current = landed;
+        assert_eq!(
+            prediction.acknowledge(&world, 1, first + vec2(0., 0.5), current),
+            None
+        );
+        assert_eq!(prediction.correction(), vec2(0., 0.));
+    }
+}
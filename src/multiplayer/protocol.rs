@@ -0,0 +1,746 @@
+use std::fmt;
+
+use serde_derive::{Deserialize, Serialize};
+
+use strum::IntoEnumIterator;
+
+use crate::{
+    chat::Emote,
+    player::BlockingAction,
+    world::{
+        entity::Direction,
+        save::ChunkSave,
+        tile::{Tile, TileAction, TileInteraction, TileTexture},
+    },
+};
+
+use super::Event;
+
+//Bump whenever an event is added or changed, peers on another version are refused
+pub const PROTOCOL_VERSION: u16 = 8;
+const JOIN_TAG: u8 = 9;
+const REFUSED_TAG: u8 = 11;
+
+//What actually goes over the wire, the version travels with every event
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Packet {
+    pub version: u16,
+    pub event: Event,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    UnsupportedVersion(u16),
+    UnknownEvent(u8),
+    UnknownDirection(u8),
+    UnknownAction(u8),
+    UnknownTexture(u8),
+    UnknownInteraction(u8),
+    UnknownTileAction(u8),
+    UnknownEmote(u8),
+    Truncated,
+    TrailingBytes(usize),
+    InvalidString,
+    Json(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::UnsupportedVersion(version) => write!(
+                f,
+                "protocol version {} is not supported, expected {}",
+                version, PROTOCOL_VERSION
+            ),
+            DecodeError::UnknownEvent(tag) => write!(f, "unknown event {}", tag),
+            DecodeError::UnknownDirection(tag) => write!(f, "unknown direction {}", tag),
+            DecodeError::UnknownAction(tag) => write!(f, "unknown action {}", tag),
+            DecodeError::UnknownTexture(tag) => write!(f, "unknown tile texture {}", tag),
+            DecodeError::UnknownInteraction(tag) => write!(f, "unknown tile interaction {}", tag),
+            DecodeError::UnknownTileAction(tag) => write!(f, "unknown tile action {}", tag),
+            DecodeError::UnknownEmote(tag) => write!(f, "unknown emote {}", tag),
+            DecodeError::Truncated => write!(f, "packet ended early"),
+            DecodeError::TrailingBytes(count) => write!(f, "{} bytes left after the event", count),
+            DecodeError::InvalidString => write!(f, "string is not valid utf-8"),
+            DecodeError::Json(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+pub fn to_json(event: &Event) -> String {
+    let packet = Packet {
+        version: PROTOCOL_VERSION,
+        event: event.clone(),
+    };
+    serde_json::to_string(&packet).unwrap()
+}
+
+//Like from_binary, Join and Refused are read from any version
+pub fn from_json(json: &str) -> Result<Event, DecodeError> {
+    //The version is checked first so a newer peer gets a version error, not a parse error
+    let value: serde_json::Value =
+        serde_json::from_str(json).map_err(|err| DecodeError::Json(err.to_string()))?;
+    let version = match value.get("version").and_then(|version| version.as_u64()) {
+        Some(version) => version,
+        None => return Err(DecodeError::Json(String::from("missing version"))),
+    };
+    let any_version = match value.get("event").and_then(|event| event.as_object()) {
+        Some(event) => event.contains_key("Join") || event.contains_key("Refused"),
+        None => false,
+    };
+    if version != PROTOCOL_VERSION as u64 && !any_version {
+        return Err(DecodeError::UnsupportedVersion(version as u16));
+    }
+    let packet: Packet =
+        serde_json::from_value(value).map_err(|err| DecodeError::Json(err.to_string()))?;
+    Ok(packet.event)
+}
+
+//Binary layout: version (u16), event tag (u8), then the fields in declaration order,
+//numbers little endian and strings as a u16 length followed by utf-8
+pub fn to_binary(event: &Event) -> Vec<u8> {
+    let mut writer = Writer(Vec::new());
+    writer.u16(PROTOCOL_VERSION);
+    match event {
+        Event::PlayerUpdate {
+            name,
+            id,
+            x,
+            y,
+            vx,
+            vy,
+            seq,
+            time,
+        } => {
+            writer.u8(0);
+            writer.string(name);
+            writer.u32(*id);
+            writer.f32(*x);
+            writer.f32(*y);
+            writer.f32(*vx);
+            writer.f32(*vy);
+            writer.u32(*seq);
+            writer.f64(*time);
+        }
+        Event::PlayerAction {
+            id,
+            x,
+            y,
+            direction,
+            action,
+            time,
+        } => {
+            writer.u8(1);
+            writer.u32(*id);
+            writer.f32(*x);
+            writer.f32(*y);
+            writer.u8(direction_tag(direction));
+            writer.u8(action_tag(action));
+            writer.f64(*time);
+        }
+        Event::PlayerDisconnect { id } => {
+            writer.u8(2);
+            writer.u32(*id);
+        }
+        Event::CommandTeleport { x, y } => {
+            writer.u8(3);
+            writer.f32(*x);
+            writer.f32(*y);
+        }
+        Event::CommandHit { health, kx, ky } => {
+            writer.u8(4);
+            writer.u32(*health);
+            writer.f32(*kx);
+            writer.f32(*ky);
+        }
+        Event::CommandAck { seq, x, y } => {
+            writer.u8(5);
+            writer.u32(*seq);
+            writer.f32(*x);
+            writer.f32(*y);
+        }
+        Event::TileChanged {
+            x,
+            y,
+            tile,
+            revision,
+        } => {
+            writer.u8(6);
+            writer.i32(*x);
+            writer.i32(*y);
+            writer.tile(tile);
+            writer.u32(*revision);
+        }
+        Event::ChunkRequest { x, y, revision } => {
+            writer.u8(7);
+            writer.i32(*x);
+            writer.i32(*y);
+            writer.u32(*revision);
+        }
+        Event::ChunkData { chunk } => {
+            writer.u8(8);
+            writer.i32(chunk.x);
+            writer.i32(chunk.y);
+            writer.u32(chunk.revision);
+            writer.u16(chunk.palette.len() as u16);
+            for tile in chunk.palette.iter() {
+                writer.tile(tile);
+            }
+            writer.u16(chunk.tiles.len() as u16);
+            chunk.tiles.iter().for_each(|index| writer.u8(*index));
+        }
+        Event::Join {
+            name,
+            room,
+            version,
+        } => {
+            writer.u8(JOIN_TAG);
+            writer.string(name);
+            writer.string(room);
+            writer.u16(*version);
+        }
+        Event::Welcome { id, room, seed } => {
+            writer.u8(10);
+            writer.u32(*id);
+            writer.string(room);
+            writer.u32(*seed);
+        }
+        Event::Refused { reason } => {
+            writer.u8(REFUSED_TAG);
+            writer.string(reason);
+        }
+        Event::PlayerJoined { id, name } => {
+            writer.u8(12);
+            writer.u32(*id);
+            writer.string(name);
+        }
+        Event::Leave => writer.u8(13),
+        Event::Chat { id, text } => {
+            writer.u8(14);
+            writer.u32(*id);
+            writer.string(text);
+        }
+        Event::Emote { id, emote } => {
+            writer.u8(15);
+            writer.u32(*id);
+            writer.u8(enum_tag(emote));
+        }
+        Event::PlayerHit {
+            id,
+            damage,
+            health,
+            blocked,
+        } => {
+            writer.u8(16);
+            writer.u32(*id);
+            writer.u32(*damage);
+            writer.u32(*health);
+            writer.u8(*blocked as u8);
+        }
+    }
+    writer.0
+}
+
+//Join and Refused keep their tag and layout in every version, so they are still read from
+//a peer on another version and it can be told why it is turned away
+pub fn from_binary(bytes: &[u8]) -> Result<Event, DecodeError> {
+    let mut reader = Reader(bytes);
+    let version = reader.u16()?;
+    let tag = reader.u8()?;
+    if version != PROTOCOL_VERSION && tag != JOIN_TAG && tag != REFUSED_TAG {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    let event = match tag {
+        0 => Event::PlayerUpdate {
+            name: reader.string()?,
+            id: reader.u32()?,
+            x: reader.f32()?,
+            y: reader.f32()?,
+            vx: reader.f32()?,
+            vy: reader.f32()?,
+            seq: reader.u32()?,
+            time: reader.f64()?,
+        },
+        1 => Event::PlayerAction {
+            id: reader.u32()?,
+            x: reader.f32()?,
+            y: reader.f32()?,
+            direction: direction_from_tag(reader.u8()?)?,
+            action: action_from_tag(reader.u8()?)?,
+            time: reader.f64()?,
+        },
+        2 => Event::PlayerDisconnect { id: reader.u32()? },
+        3 => Event::CommandTeleport {
+            x: reader.f32()?,
+            y: reader.f32()?,
+        },
+        4 => Event::CommandHit {
+            health: reader.u32()?,
+            kx: reader.f32()?,
+            ky: reader.f32()?,
+        },
+        5 => Event::CommandAck {
+            seq: reader.u32()?,
+            x: reader.f32()?,
+            y: reader.f32()?,
+        },
+        6 => Event::TileChanged {
+            x: reader.i32()?,
+            y: reader.i32()?,
+            tile: reader.tile()?,
+            revision: reader.u32()?,
+        },
+        7 => Event::ChunkRequest {
+            x: reader.i32()?,
+            y: reader.i32()?,
+            revision: reader.u32()?,
+        },
+        8 => {
+            let (x, y, revision) = (reader.i32()?, reader.i32()?, reader.u32()?);
+            let palette = (0..reader.u16()?)
+                .map(|_| reader.tile())
+                .collect::<Result<Vec<Tile>, DecodeError>>()?;
+            let tiles = (0..reader.u16()?)
+                .map(|_| reader.u8())
+                .collect::<Result<Vec<u8>, DecodeError>>()?;
+            Event::ChunkData {
+                chunk: ChunkSave {
+                    x,
+                    y,
+                    palette,
+                    tiles,
+                    revision,
+                },
+            }
+        }
+        JOIN_TAG => Event::Join {
+            name: reader.string()?,
+            room: reader.string()?,
+            version: reader.u16()?,
+        },
+        10 => Event::Welcome {
+            id: reader.u32()?,
+            room: reader.string()?,
+            seed: reader.u32()?,
+        },
+        REFUSED_TAG => Event::Refused {
+            reason: reader.string()?,
+        },
+        12 => Event::PlayerJoined {
+            id: reader.u32()?,
+            name: reader.string()?,
+        },
+        13 => Event::Leave,
+        14 => Event::Chat {
+            id: reader.u32()?,
+            text: reader.string()?,
+        },
+        15 => Event::Emote {
+            id: reader.u32()?,
+            emote: {
+                let tag = reader.u8()?;
+                enum_from_tag::<Emote>(tag).ok_or(DecodeError::UnknownEmote(tag))?
+            },
+        },
+        16 => Event::PlayerHit {
+            id: reader.u32()?,
+            damage: reader.u32()?,
+            health: reader.u32()?,
+            blocked: reader.u8()? != 0,
+        },
+        tag => return Err(DecodeError::UnknownEvent(tag)),
+    };
+    if !reader.0.is_empty() {
+        return Err(DecodeError::TrailingBytes(reader.0.len()));
+    }
+    Ok(event)
+}
+
+fn direction_tag(direction: &Direction) -> u8 {
+    match direction {
+        Direction::Up => 0,
+        Direction::Down => 1,
+        Direction::Left => 2,
+        Direction::Right => 3,
+    }
+}
+
+fn direction_from_tag(tag: u8) -> Result<Direction, DecodeError> {
+    match tag {
+        0 => Ok(Direction::Up),
+        1 => Ok(Direction::Down),
+        2 => Ok(Direction::Left),
+        3 => Ok(Direction::Right),
+        _ => Err(DecodeError::UnknownDirection(tag)),
+    }
+}
+
+fn action_tag(action: &BlockingAction) -> u8 {
+    match action {
+        BlockingAction::Attack => 0,
+        BlockingAction::Roll => 1,
+        BlockingAction::Block => 2,
+        BlockingAction::Dying => 3,
+    }
+}
+
+fn action_from_tag(tag: u8) -> Result<BlockingAction, DecodeError> {
+    match tag {
+        0 => Ok(BlockingAction::Attack),
+        1 => Ok(BlockingAction::Roll),
+        2 => Ok(BlockingAction::Block),
+        3 => Ok(BlockingAction::Dying),
+        _ => Err(DecodeError::UnknownAction(tag)),
+    }
+}
+
+//Tile enums and emotes are sent as their position in declaration order, so new variants go last
+fn enum_tag<T: IntoEnumIterator + PartialEq>(value: &T) -> u8 {
+    T::iter().position(|variant| variant == *value).unwrap() as u8
+}
+
+fn enum_from_tag<T: IntoEnumIterator>(tag: u8) -> Option<T> {
+    T::iter().nth(tag as usize)
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn tile(&mut self, tile: &Tile) {
+        self.u8(enum_tag(&tile.texture));
+        self.u8(enum_tag(&tile.interaction));
+        self.u8(enum_tag(&tile.action));
+    }
+
+    fn f64(&mut self, value: f64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    //Names longer than u16::MAX bytes are cut, nothing legitimate gets close
+    fn string(&mut self, value: &str) {
+        let mut end = value.len().min(u16::MAX as usize);
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.u16(end as u16);
+        self.0.extend_from_slice(&value.as_bytes()[..end]);
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        if self.0.len() < N {
+            return Err(DecodeError::Truncated);
+        }
+        let (bytes, rest) = self.0.split_at(N);
+        self.0 = rest;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, DecodeError> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, DecodeError> {
+        Ok(i32::from_le_bytes(self.take()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, DecodeError> {
+        Ok(f32::from_le_bytes(self.take()?))
+    }
+
+    fn tile(&mut self) -> Result<Tile, DecodeError> {
+        let (texture, interaction, action) = (self.u8()?, self.u8()?, self.u8()?);
+        Ok(Tile {
+            texture: enum_from_tag::<TileTexture>(texture)
+                .ok_or(DecodeError::UnknownTexture(texture))?,
+            interaction: enum_from_tag::<TileInteraction>(interaction)
+                .ok_or(DecodeError::UnknownInteraction(interaction))?,
+            action: enum_from_tag::<TileAction>(action)
+                .ok_or(DecodeError::UnknownTileAction(action))?,
+        })
+    }
+
+    fn f64(&mut self) -> Result<f64, DecodeError> {
+        Ok(f64::from_le_bytes(self.take()?))
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let len = self.u16()? as usize;
+        if self.0.len() < len {
+            return Err(DecodeError::Truncated);
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidString)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    //One of each, with values that do not survive a lossy encoding
+    fn every_event() -> Vec<Event> {
+        let stone = Tile {
+            texture: TileTexture::Stone,
+            interaction: TileInteraction::Block,
+            action: TileAction::Death,
+        };
+        vec![
+            Event::PlayerUpdate {
+                name: String::from("Åsa"),
+                id: 7,
+                x: -1.25,
+                y: 3.5,
+                vx: 0.1,
+                vy: -30.,
+                seq: u32::MAX,
+                time: 1234.000001,
+            },
+            Event::PlayerAction {
+                id: 2,
+                x: 0.5,
+                y: -0.5,
+                direction: Direction::Left,
+                action: BlockingAction::Block,
+                time: 0.25,
+            },
+            Event::PlayerDisconnect { id: 9 },
+            Event::CommandTeleport { x: 100., y: -100. },
+            Event::CommandHit {
+                health: 66,
+                kx: 12.,
+                ky: -3.,
+            },
+            Event::CommandAck {
+                seq: 41,
+                x: 2.,
+                y: 4.,
+            },
+            Event::TileChanged {
+                x: -3,
+                y: i32::MAX,
+                tile: stone,
+                revision: 3,
+            },
+            Event::ChunkRequest {
+                x: -1,
+                y: 2,
+                revision: 0,
+            },
+            Event::ChunkData {
+                chunk: ChunkSave {
+                    x: 4,
+                    y: -4,
+                    palette: vec![Tile::default(), stone],
+                    tiles: vec![0, 1, 1, 0],
+                    revision: 12,
+                },
+            },
+            Event::Join {
+                name: String::from("Bob"),
+                room: String::from("main"),
+                version: PROTOCOL_VERSION,
+            },
+            Event::Welcome {
+                id: 3,
+                room: String::from("main"),
+                seed: 99,
+            },
+            Event::Refused {
+                reason: String::from("full"),
+            },
+            Event::PlayerJoined {
+                id: 5,
+                name: String::new(),
+            },
+            Event::Leave,
+            Event::Chat {
+                id: 1,
+                text: String::from("hello 👋"),
+            },
+            Event::Emote {
+                id: 1,
+                emote: Emote::Heart,
+            },
+            Event::PlayerHit {
+                id: 8,
+                damage: 34,
+                health: 0,
+                blocked: true,
+            },
+        ]
+    }
+
+    #[test]
+    fn every_event_survives_json() {
+        for event in every_event() {
+            assert_eq!(from_json(&to_json(&event)), Ok(event));
+        }
+    }
+
+    #[test]
+    fn every_event_survives_binary() {
+        let events = every_event();
+        for event in events.iter() {
+            assert_eq!(from_binary(&to_binary(event)).as_ref(), Ok(event));
+        }
+        //Every tag in use is covered, so a new event is not left out here
+        let tags: HashSet<u8> = events.iter().map(|event| to_binary(event)[2]).collect();
+        assert_eq!(tags, (0..=16).collect());
+    }
+
+    #[test]
+    fn a_truncated_packet_is_refused() {
+        for event in every_event() {
+            let bytes = to_binary(&event);
+            for end in 0..bytes.len() {
+                assert_eq!(
+                    from_binary(&bytes[..end]),
+                    Err(DecodeError::Truncated),
+                    "{:?} cut at {}",
+                    event,
+                    end
+                );
+            }
+        }
+        let mut bytes = to_binary(&Event::Leave);
+        bytes.push(0);
+        assert_eq!(from_binary(&bytes), Err(DecodeError::TrailingBytes(1)));
+    }
+
+    #[test]
+    fn unknown_tags_are_named() {
+        let mut bytes = to_binary(&Event::Leave);
+        bytes[2] = 200;
+        assert_eq!(from_binary(&bytes), Err(DecodeError::UnknownEvent(200)));
+
+        let action = to_binary(&every_event()[1]);
+        //Version, tag, id, x and y come before the direction and the action
+        let direction_at = 2 + 1 + 4 + 4 + 4;
+        let mut bytes = action.clone();
+        bytes[direction_at] = 4;
+        assert_eq!(from_binary(&bytes), Err(DecodeError::UnknownDirection(4)));
+        let mut bytes = action;
+        bytes[direction_at + 1] = 9;
+        assert_eq!(from_binary(&bytes), Err(DecodeError::UnknownAction(9)));
+
+        let changed = to_binary(&every_event()[6]);
+        let tile_at = 2 + 1 + 4 + 4;
+        let errors = [
+            DecodeError::UnknownTexture(77),
+            DecodeError::UnknownInteraction(77),
+            DecodeError::UnknownTileAction(77),
+        ];
+        for (offset, error) in errors.into_iter().enumerate() {
+            let mut bytes = changed.clone();
+            bytes[tile_at + offset] = 77;
+            assert_eq!(from_binary(&bytes), Err(error));
+        }
+
+        let mut bytes = to_binary(&every_event()[15]);
+        *bytes.last_mut().unwrap() = 4;
+        assert_eq!(from_binary(&bytes), Err(DecodeError::UnknownEmote(4)));
+
+        let json = to_json(&Event::Leave).replace("Leave", "Jump");
+        assert!(matches!(from_json(&json), Err(DecodeError::Json(_))));
+    }
+
+    #[test]
+    fn another_version_is_refused() {
+        let old = PROTOCOL_VERSION - 1;
+        let event = Event::PlayerDisconnect { id: 1 };
+        let mut bytes = to_binary(&event);
+        bytes[..2].copy_from_slice(&old.to_le_bytes());
+        assert_eq!(
+            from_binary(&bytes),
+            Err(DecodeError::UnsupportedVersion(old))
+        );
+
+        let json = to_json(&event).replace(
+            &format!("\"version\":{}", PROTOCOL_VERSION),
+            &format!("\"version\":{}", old),
+        );
+        assert_eq!(from_json(&json), Err(DecodeError::UnsupportedVersion(old)));
+        let json = r#"{"event":{"PlayerDisconnect":{"id":1}}}"#;
+        assert!(matches!(from_json(json), Err(DecodeError::Json(_))));
+    }
+
+    #[test]
+    fn join_and_refused_are_read_from_another_version() {
+        let old = PROTOCOL_VERSION - 1;
+        let join = Event::Join {
+            name: String::from("Old"),
+            room: String::from("main"),
+            version: old,
+        };
+        let refused = Event::Refused {
+            reason: format!("version {} is not supported", old),
+        };
+        for event in [join, refused] {
+            let mut bytes = to_binary(&event);
+            bytes[..2].copy_from_slice(&old.to_le_bytes());
+            assert_eq!(from_binary(&bytes).as_ref(), Ok(&event));
+
+            let json = to_json(&event).replace(
+                &format!("\"version\":{}", PROTOCOL_VERSION),
+                &format!("\"version\":{}", old),
+            );
+            assert!(
+                json.starts_with(&format!("{{\"version\":{},", old)),
+                "{}",
+                json
+            );
+            assert_eq!(from_json(&json), Ok(event));
+        }
+    }
+
+    #[test]
+    fn lengths_past_the_end_are_refused() {
+        let mut chat = to_binary(&Event::Chat {
+            id: 1,
+            text: String::from("hi"),
+        });
+        //The text length sits after version, tag and id
+        chat[7..9].copy_from_slice(&u16::MAX.to_le_bytes());
+        assert_eq!(from_binary(&chat), Err(DecodeError::Truncated));
+
+        let mut chunk = to_binary(&every_event()[8]);
+        //Palette length after version, tag, x, y and revision
+        chunk[15..17].copy_from_slice(&u16::MAX.to_le_bytes());
+        assert_eq!(from_binary(&chunk), Err(DecodeError::Truncated));
+    }
+}
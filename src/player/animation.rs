@@ -44,13 +44,13 @@ impl fmt::Display for Direction {
 }
 
 #[derive(Debug, Deserialize, Serialize)]
-struct Spritesheet {
+pub struct Spritesheet {
     #[serde(rename = "frames")]
     pub frames: HashMap<String, Frame>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
-struct Frame {
+pub struct Frame {
     #[serde(rename = "frame")]
     pub pos: PixelPos,
 }
@@ -64,12 +64,17 @@ pub struct PixelPos {
 }
 
 pub async fn load_textures() -> (Texture2D, HashMap<(Interaction, Direction), Animation>) {
-    let mut texture_map: HashMap<(Interaction, Direction), Animation> = HashMap::new();
-
     let texture = load_texture("textures/spritesheet.png").await.unwrap();
     texture.set_filter(FilterMode::Nearest);
     let spritesheet = load_string("textures/spritesheet.json").await.unwrap();
-    let spritesheet: Spritesheet = serde_json::from_str(&spritesheet).unwrap();
+
+    (texture, load_animations(&spritesheet))
+}
+
+//Only needs the spritesheet json, so it also works without a window (the server uses it for timing)
+pub fn load_animations(spritesheet: &str) -> HashMap<(Interaction, Direction), Animation> {
+    let mut texture_map: HashMap<(Interaction, Direction), Animation> = HashMap::new();
+    let spritesheet: Spritesheet = serde_json::from_str(spritesheet).unwrap();
 
     for action in Interaction::iter() {
         match action {
@@ -129,5 +134,5 @@ pub async fn load_textures() -> (Texture2D, HashMap<(Interaction, Direction), An
         }
     }
 
-    (texture, texture_map)
+    texture_map
 }
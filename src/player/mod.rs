@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 
 use macroquad::prelude::*;
+use serde_derive::{Deserialize, Serialize};
 use strum_macros::EnumIter;
 
 use crate::world::entity::{Direction, EntityWorldEvent, WorldResource};
@@ -11,6 +12,13 @@ pub mod player;
 pub mod animation;
 
 const ATTACK_COOLDOWN: f32 = 1.0;
+pub const MAX_HEALTH: u32 = 100;
+//Where new players start, before the respawn jump
+pub const SPAWN_POINT: Vec2 = Vec2::from_array([-3., -10.]);
+//Furthest a respawn moves a player along either axis
+pub const RESPAWN_RANGE: i32 = 50;
+//Seconds it takes a knockback to wear off
+const KNOCKBACK_TIME: f32 = 0.25;
 
 pub struct Player<'a> {
     name: String,
@@ -24,10 +32,13 @@ pub struct Player<'a> {
     cooldowns: HashMap<BlockingAction, f32>,
     world_events: Vec<EntityWorldEvent>,
     local_player: bool,
-    resources: HashMap<WorldResource, u32>
+    resources: HashMap<WorldResource, u32>,
+    health: u32,
+    //Added to the walking velocity until it wears off
+    knockback: Vec2,
 }
 
-#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy, EnumIter)]
+#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy, EnumIter, Deserialize, Serialize)]
 pub enum BlockingAction {
     Attack,
     Roll,
@@ -2,9 +2,15 @@ use std::collections::HashMap;
 
 use macroquad::prelude::*;
 
-use crate::world::{entity::*, tile::*};
+use crate::{
+    combat::{Attack, Hit, PlayerState, BLOCK_FACTOR, KNOCKBACK_SPEED},
+    world::{entity::*, tile::*},
+};
 
-use super::{animation::*, BlockingAction, Interaction, Player, ATTACK_COOLDOWN};
+use super::{
+    animation::*, BlockingAction, Interaction, Player, ATTACK_COOLDOWN, KNOCKBACK_TIME, MAX_HEALTH,
+    RESPAWN_RANGE,
+};
 
 impl BlockingAction {
     pub fn to_interaction(&self) -> Interaction {
@@ -65,6 +71,8 @@ impl Player<'_> {
             world_events: Vec::new(),
             local_player: true,
             resources: HashMap::new(),
+            health: MAX_HEALTH,
+            knockback: vec2(0., 0.),
         }
     }
 
@@ -90,6 +98,8 @@ impl Player<'_> {
             world_events: Vec::new(),
             local_player: false,
             resources: HashMap::new(),
+            health: MAX_HEALTH,
+            knockback: vec2(0., 0.),
         }
     }
 
@@ -114,16 +124,109 @@ impl Player<'_> {
         self.direction
     }
 
+    pub fn set_direction(&mut self, direction: Direction) {
+        self.direction = direction;
+    }
+
+    pub fn get_resources(&self) -> &HashMap<WorldResource, u32> {
+        &self.resources
+    }
+
+    pub fn set_resources(&mut self, resources: HashMap<WorldResource, u32>) {
+        self.resources = resources;
+    }
+
+    //Standing still with no action or cooldown in progress, so a replay can start from here
+    pub fn reset_state(&mut self) {
+        self.keyframe = KeyFrame::Free(0, Interaction::Idle);
+        self.keyframe_timer = 0.;
+        self.cooldowns.clear();
+        self.world_events.clear();
+        self.knockback = vec2(0., 0.);
+    }
+
+    //For copies of players whose edits reach the world some other way
+    pub fn clear_world_events(&mut self) {
+        self.world_events.clear();
+    }
+
     pub fn kill(&mut self) {
         self.set_action(BlockingAction::Dying)
     }
 
+    pub fn get_health(&self) -> u32 {
+        self.health
+    }
+
+    pub fn set_health(&mut self, health: u32) {
+        self.health = health.min(MAX_HEALTH);
+    }
+
+    fn is_doing(&self, action: BlockingAction) -> bool {
+        match self.keyframe {
+            KeyFrame::Blocking(_, current) => current == action,
+            KeyFrame::Free(_, _) => false,
+        }
+    }
+
+    pub fn state(&self) -> PlayerState {
+        let action = match self.keyframe {
+            KeyFrame::Blocking(_, action) => Some(action),
+            KeyFrame::Free(_, _) => None,
+        };
+        PlayerState {
+            pos: self.pos,
+            direction: self.direction,
+            action,
+        }
+    }
+
+    //Rolling dodges the attack, blocking while facing the attacker takes the edge off it.
+    //None when nothing happened, either from rolling or being dead already
+    pub fn take_hit(&mut self, attack: &Attack, damage: u32) -> Option<Hit> {
+        self.take_hit_as(attack, damage, self.state())
+    }
+
+    //Same as take_hit, but rolling and blocking are judged by `past`, what the player was
+    //doing when the attack happened. Being dead already is judged by now
+    pub fn take_hit_as(&mut self, attack: &Attack, damage: u32, past: PlayerState) -> Option<Hit> {
+        if past.action == Some(BlockingAction::Roll) || self.is_doing(BlockingAction::Dying) {
+            return None;
+        }
+        let away = past.pos - attack.pos;
+        let blocked =
+            past.action == Some(BlockingAction::Block) && past.direction.to_vec2().dot(-away) > 0.;
+        let factor = if blocked { BLOCK_FACTOR } else { 1. };
+        let direction = match away.try_normalize() {
+            Some(direction) => direction,
+            None => attack.direction.to_vec2(),
+        };
+        let damage = (damage as f32 * factor).round() as u32;
+        let hit = Hit {
+            damage,
+            blocked,
+            knockback: direction * KNOCKBACK_SPEED * factor,
+            health: self.health.saturating_sub(damage),
+        };
+        self.apply_hit(hit.health, hit.knockback);
+        Some(hit)
+    }
+
+    //A hit someone else worked out, like the server
+    pub fn apply_hit(&mut self, health: u32, knockback: Vec2) {
+        self.set_health(health);
+        self.knockback = knockback;
+        if self.health == 0 && !self.is_doing(BlockingAction::Dying) {
+            self.kill();
+        }
+    }
+
     pub fn get_name(&self) -> &str {
         &self.name
     }
 
     pub fn respawn(&mut self) {
-        let size = 50;
+        let size = RESPAWN_RANGE;
         let (x, y) = (rand::gen_range(-size, size), rand::gen_range(-size, size));
         self.pos += vec2(x as f32, y as f32);
     }
@@ -136,17 +239,20 @@ impl Player<'_> {
     }
 
     pub fn try_action(&mut self, action: BlockingAction) -> Result<(), ()> {
+        if self.can_action(action) {
+            self.set_action(action);
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
+    pub fn can_action(&self, action: BlockingAction) -> bool {
         match self.keyframe {
-            KeyFrame::Blocking(_, _) => Err(()),
+            KeyFrame::Blocking(_, _) => false,
             KeyFrame::Free(_, interaction) => match interaction {
-                Interaction::Walk | Interaction::Idle => match self.cooldowns.get(&action) {
-                    Some(_) => Err(()),
-                    None => {
-                        self.set_action(action);
-                        Ok(())
-                    }
-                },
-                _ => Err(()),
+                Interaction::Walk | Interaction::Idle => !self.cooldowns.contains_key(&action),
+                _ => false,
             },
         }
     }
@@ -179,6 +285,7 @@ impl Player<'_> {
                     self.cooldowns.insert(action, 1.);
                 }
                 BlockingAction::Dying => {
+                    self.health = MAX_HEALTH;
                     if self.local_player {
                         self.respawn()
                     }
@@ -202,13 +309,18 @@ impl Player<'_> {
     }
 
     pub fn render(&mut self, text_params: &TextParams, debug: bool) {
+        self.render_at(self.pos, text_params, debug);
+    }
+
+    //Drawn somewhere other than where it is, for blending between simulation steps
+    pub fn render_at(&mut self, pos: Vec2, text_params: &TextParams, debug: bool) {
         let interaction = match self.keyframe {
             KeyFrame::Blocking(_, action) => action.to_interaction(),
             KeyFrame::Free(_, interaction) => interaction,
         };
 
-        let x = self.pos.x;
-        let y = self.pos.y;
+        let x = pos.x;
+        let y = pos.y;
 
         match self.animations.get(&(interaction, self.direction)) {
             Some(texture_data) => {
@@ -219,8 +331,8 @@ impl Player<'_> {
 
                 draw_texture_ex(
                     *self.textures,
-                    self.pos.x - 8.,
-                    self.pos.y - 8.,
+                    pos.x - 8.,
+                    pos.y - 8.,
                     WHITE,
                     DrawTextureParams {
                         dest_size: Some(vec2(16., 16.)),
@@ -235,7 +347,7 @@ impl Player<'_> {
                 );
             }
             None => {
-                draw_circle(self.pos.x, self.pos.y, 1., RED);
+                draw_circle(pos.x, pos.y, 1., RED);
             }
         }
 
@@ -252,12 +364,24 @@ impl Player<'_> {
             }
             None => (),
         };
+        //Only shown once hurt
+        if self.health < MAX_HEALTH {
+            let health = self.health as f32 / MAX_HEALTH as f32;
+            draw_rectangle(x - 2., y - 1.8, 4., 0.5, color_u8!(60, 0, 0, 200));
+            draw_rectangle(
+                x - 2.,
+                y - 1.8,
+                4. * health,
+                0.5,
+                color_u8!(220, 30, 30, 220),
+            );
+        }
         if debug {
-            draw_circle(self.pos.x, self.pos.y, 0.1, RED);
-            draw_circle_lines(self.pos.x, self.pos.y, 2.5, 0.1, RED);
+            draw_circle(pos.x, pos.y, 0.1, RED);
+            draw_circle_lines(pos.x, pos.y, 2.5, 0.1, RED);
         }
 
-        draw_text_ex(&self.name, self.pos.x + 1., self.pos.y - 2., *text_params);
+        draw_text_ex(&self.name, pos.x + 1., pos.y - 2., *text_params);
     }
     pub fn set_velocity(&mut self, velocity: Vec2) {
         match self.keyframe {
@@ -271,7 +395,8 @@ impl Player<'_> {
             Some(amount) => {
                 if *amount > 0 {
                     *amount -= 1;
-                    self.world_events.push(EntityWorldEvent::Place(WorldResource::Dirt,pos));
+                    self.world_events
+                        .push(EntityWorldEvent::Place(WorldResource::Dirt, pos));
                 }
             }
             None => (),
@@ -281,7 +406,7 @@ impl Player<'_> {
 
 impl WorldEntity for Player<'_> {
     fn get_velocity(&self) -> Vec2 {
-        self.v
+        self.v + self.knockback
     }
 
     fn get_position(&self) -> Vec2 {
@@ -372,6 +497,11 @@ impl WorldEntity for Player<'_> {
             },
         }
 
+        self.knockback *= (1. - time / KNOCKBACK_TIME).max(0.);
+        if self.knockback.length() < 0.1 {
+            self.knockback = vec2(0., 0.);
+        }
+
         //Cooldown timer
         let mut to_be_removed = Vec::new();
         for (action, cooldown) in self.cooldowns.iter_mut() {
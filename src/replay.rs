@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+
+use macroquad::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+
+use crate::{
+    chat::ChatInput,
+    controlls::ToggleControll,
+    game::{FrameInput, Game},
+    multiplayer::Event,
+    player::BlockingAction,
+    world::{
+        entity::{Direction, WorldEntity, WorldResource},
+        generation::WorldGenConfig,
+        save::WorldSave,
+        World,
+    },
+};
+
+pub const RECORDING_VERSION: u32 = 2;
+//Storage key recordings are saved under
+pub const REPLAY_KEY: &str = "replay";
+const MAX_SPEED: f32 = 16.;
+const MIN_SPEED: f32 = 0.125;
+
+//These drive the recorder and the debug player, neither should be played back
+fn is_recorded(toggle: ToggleControll) -> bool {
+    !matches!(
+        toggle,
+        ToggleControll::Record | ToggleControll::SecondaryPlayer
+    )
+}
+
+//Everything needed to play a session back: the world and player as they were when recording
+//started, the random seed and the input and network events of every frame since
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Recording {
+    pub version: u32,
+    pub world: WorldSave,
+    pub rand_seed: u64,
+    pub tick_rate: f32,
+    pub x: f32,
+    pub y: f32,
+    pub vx: f32,
+    pub vy: f32,
+    pub direction: Direction,
+    pub resources: HashMap<WorldResource, u32>,
+    pub health: u32,
+    pub zoom: f32,
+    //Toggles that were on
+    pub toggles: Vec<ToggleControll>,
+    pub frames: Vec<RecordedFrame>,
+}
+
+//FrameInput without the debug player, vectors as [x, y] since they have no serde
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RecordedFrame {
+    //Frames are kept instead of steps. Input is only read once a frame, and from a reset clock
+    //FixedTimestep turns the same frame times into the same steps, so the replay takes them too
+    pub dt: f32,
+    pub screen: [f32; 2],
+    pub movement: [f32; 2],
+    pub action: Option<BlockingAction>,
+    pub zoom: f32,
+    #[serde(default)]
+    pub pan: [f32; 2],
+    pub pointer: Option<[f32; 2]>,
+    pub place: bool,
+    pub teleport: bool,
+    pub force_render: bool,
+    pub toggles: Vec<ToggleControll>,
+    pub chat: Option<ChatInput>,
+    //What arrived from the server or the debug player during the frame
+    pub events: Vec<Event>,
+}
+
+impl RecordedFrame {
+    pub fn new(input: &FrameInput, dt: f32, events: Vec<Event>) -> RecordedFrame {
+        RecordedFrame {
+            dt,
+            screen: input.screen.to_array(),
+            movement: input.movement.to_array(),
+            action: input.action,
+            zoom: input.zoom,
+            pan: input.pan.to_array(),
+            pointer: input.pointer.map(|pointer| pointer.to_array()),
+            place: input.place,
+            teleport: input.teleport,
+            force_render: input.force_render,
+            toggles: input
+                .toggles
+                .iter()
+                .copied()
+                .filter(|toggle| is_recorded(*toggle))
+                .collect(),
+            chat: input.chat.clone(),
+            events,
+        }
+    }
+
+    pub fn input(&self) -> FrameInput {
+        FrameInput {
+            screen: Vec2::from(self.screen),
+            movement: Vec2::from(self.movement),
+            action: self.action,
+            zoom: self.zoom,
+            pan: Vec2::from(self.pan),
+            pointer: self.pointer.map(Vec2::from),
+            place: self.place,
+            teleport: self.teleport,
+            force_render: self.force_render,
+            toggles: self.toggles.clone(),
+            chat: self.chat.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+impl Recording {
+    //Seeds the random numbers so the recorded game and its replay draw the same ones
+    pub fn start(game: &Game, rand_seed: u64) -> Recording {
+        rand::srand(rand_seed);
+        let pos = game.player().get_position();
+        let velocity = game.player().get_velocity();
+        Recording {
+            version: RECORDING_VERSION,
+            world: game.world.to_save(),
+            rand_seed,
+            tick_rate: game.tick_rate(),
+            x: pos.x,
+            y: pos.y,
+            vx: velocity.x,
+            vy: velocity.y,
+            direction: game.player().get_direction(),
+            resources: game.player().get_resources().clone(),
+            health: game.player().get_health(),
+            zoom: game.camera.z,
+            toggles: ToggleControll::iter()
+                .filter(|toggle| is_recorded(*toggle) && game.controller.is_enabled(*toggle))
+                .collect(),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Recording, String> {
+        let recording: Recording = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        if recording.version != RECORDING_VERSION {
+            return Err(format!(
+                "recording version {} is not supported, expected {}",
+                recording.version, RECORDING_VERSION
+            ));
+        }
+        Ok(recording)
+    }
+
+    //The world as it was when recording started, `config` brings the tile table
+    pub fn world(&self, mut config: WorldGenConfig) -> World {
+        config.seed = self.world.seed;
+        let mut world = World::generate(config);
+        world.load_save(&self.world);
+        world
+    }
+
+    //Puts a new game in the state the recording started from
+    pub fn prepare(&self, game: &mut Game) {
+        rand::srand(self.rand_seed);
+        game.player_mut().set_position(vec2(self.x, self.y));
+        game.player_mut().set_velocity(vec2(self.vx, self.vy));
+        game.player_mut().set_direction(self.direction);
+        game.player_mut().set_resources(self.resources.clone());
+        game.player_mut().set_health(self.health);
+        game.player_mut().reset_state();
+        game.camera.z = self.zoom;
+        for toggle in ToggleControll::iter() {
+            game.controller.set(toggle, self.toggles.contains(&toggle));
+        }
+        game.reset_clock();
+    }
+}
+
+//Plays a recording back into a game, frame by frame
+pub struct Replay {
+    recording: Recording,
+    frame: usize,
+    pub paused: bool,
+    //Recorded frames played per drawn frame
+    pub speed: f32,
+    //Part of a frame left over when playing slower or faster than recorded
+    progress: f32,
+}
+
+impl Replay {
+    pub fn new(recording: Recording) -> Replay {
+        Replay {
+            recording,
+            frame: 0,
+            paused: false,
+            speed: 1.,
+            progress: 0.,
+        }
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.recording.frames.len()
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = (self.speed * 2.).min(MAX_SPEED);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = (self.speed / 2.).max(MIN_SPEED);
+    }
+
+    //Plays as many frames as the speed asks for, nothing while paused
+    pub fn advance(&mut self, game: &mut Game) {
+        if self.paused {
+            return;
+        }
+        self.progress += self.speed;
+        while self.progress >= 1. && !self.is_finished() {
+            self.progress -= 1.;
+            self.step(game);
+        }
+    }
+
+    //Plays exactly one recorded frame
+    pub fn step(&mut self, game: &mut Game) {
+        let frame = match self.recording.frames.get(self.frame) {
+            Some(frame) => frame,
+            None => return,
+        };
+        for event in frame.events.iter() {
+            game.receive(event.clone());
+        }
+        game.update(frame.input(), frame.dt);
+        self.frame += 1;
+    }
+
+    //Space pauses, the right arrow steps while paused, up and down change the speed
+    pub fn update(&mut self, game: &mut Game) {
+        if is_key_pressed(KeyCode::Space) {
+            self.paused = !self.paused;
+        }
+        if is_key_pressed(KeyCode::Up) {
+            self.faster();
+        }
+        if is_key_pressed(KeyCode::Down) {
+            self.slower();
+        }
+        if self.paused && is_key_pressed(KeyCode::Right) {
+            self.step(game);
+        } else {
+            self.advance(game);
+        }
+    }
+
+    pub fn render(&self) {
+        let state = if self.is_finished() {
+            "finished"
+        } else if self.paused {
+            "paused"
+        } else {
+            "playing"
+        };
+        draw_text(
+            &format!(
+                "Replay {}/{} x{} {}",
+                self.frame,
+                self.recording.frames.len(),
+                self.speed,
+                state
+            ),
+            10.,
+            screen_height() - 20.,
+            30.,
+            BLACK,
+        );
+        draw_text(
+            "Space pause, Right step, Up/Down speed",
+            10.,
+            screen_height() - 50.,
+            20.,
+            DARKGRAY,
+        );
+    }
+}
@@ -0,0 +1,286 @@
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    net::{SocketAddr, TcpListener},
+    thread,
+    time::{Duration, Instant},
+};
+
+use macroquad::prelude::*;
+
+use crate::{
+    multiplayer::{net::Connection, protocol::PROTOCOL_VERSION, Event, DEFAULT_ROOM},
+    player::{
+        animation::{load_animations, Animation},
+        Interaction, Player,
+    },
+    world::{
+        entity::Direction, generation::WorldGenConfig, residency::ResidencyPolicy,
+        tile::TileTable, World,
+    },
+};
+
+use room::Room;
+
+pub mod room;
+
+//Connections that have not sent Join by then are dropped
+const JOIN_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_NAME_LENGTH: usize = 24;
+const MAX_ROOM_LENGTH: usize = 32;
+
+pub struct ServerConfig {
+    pub bind: String,
+    pub seed: u32,
+    pub tick_rate: f32,
+    pub residency: ResidencyPolicy,
+}
+
+impl ServerConfig {
+    //Reads `--bind <addr>`, `--seed <u32>` and `--tick-rate <hz>`, and the residency policy
+    //of every room (see ResidencyPolicy::from_args)
+    pub fn from_args(args: impl Iterator<Item = String>) -> ServerConfig {
+        let mut config = ServerConfig::default();
+        let args: Vec<String> = args.collect();
+        config.residency = ResidencyPolicy::from_args(args.iter().cloned());
+        for pair in args.windows(2) {
+            match (pair[0].as_str(), pair[1].as_str()) {
+                ("--bind", bind) => config.bind = bind.to_owned(),
+                ("--seed", seed) => config.seed = seed.parse().unwrap_or(config.seed),
+                ("--tick-rate", rate) => {
+                    config.tick_rate = rate.parse().unwrap_or(config.tick_rate)
+                }
+                _ => (),
+            }
+        }
+        config
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind: String::from("0.0.0.0:7878"),
+            seed: 0,
+            tick_rate: 20.,
+            residency: ResidencyPolicy::default(),
+        }
+    }
+}
+
+pub struct Server<'a> {
+    listener: TcpListener,
+    //Every room gets a world of its own generated from this
+    world_gen_config: WorldGenConfig,
+    residency: ResidencyPolicy,
+    rooms: HashMap<String, Room<'a>>,
+    //Connected but not yet in a room, with when they connected
+    pending: HashMap<u32, (Connection, Instant)>,
+    next_id: u32,
+    animations: &'a HashMap<(Interaction, Direction), Animation>,
+    texture: &'a Texture2D,
+    started: Instant,
+}
+
+//Blocks forever, owns the world and runs it at the configured tick rate
+pub fn run(config: ServerConfig) -> std::io::Result<()> {
+    let spritesheet = std::fs::read_to_string("textures/spritesheet.json")?;
+    let animations = load_animations(&spritesheet);
+    //Never drawn, players only need it to exist
+    let texture = Texture2D::empty();
+
+    let mut world_gen_config = WorldGenConfig::with_seed(config.seed);
+    if let Ok(json) = std::fs::read_to_string("textures/tiles.json") {
+        match TileTable::from_json(&json) {
+            Ok(table) => world_gen_config.tiles = table,
+            Err(err) => warn!("textures/tiles.json: {}, using built in tiles", err),
+        }
+    }
+
+    let mut server = Server::new(&config.bind, world_gen_config, &animations, &texture)?;
+    server.set_residency_policy(config.residency);
+    info!("Listening on {} with seed {}", config.bind, config.seed);
+
+    let tick = Duration::from_secs_f32(1. / config.tick_rate);
+    loop {
+        let start = Instant::now();
+        server.tick(tick.as_secs_f32());
+        let elapsed = start.elapsed();
+        if elapsed < tick {
+            thread::sleep(tick - elapsed);
+        }
+    }
+}
+
+impl<'a> Server<'a> {
+    pub fn new(
+        bind: &str,
+        world_gen_config: WorldGenConfig,
+        animations: &'a HashMap<(Interaction, Direction), Animation>,
+        texture: &'a Texture2D,
+    ) -> std::io::Result<Server<'a>> {
+        let listener = TcpListener::bind(bind)?;
+        listener.set_nonblocking(true)?;
+        Ok(Server {
+            listener,
+            world_gen_config,
+            residency: ResidencyPolicy::default(),
+            rooms: HashMap::new(),
+            pending: HashMap::new(),
+            next_id: 1,
+            animations,
+            texture,
+            started: Instant::now(),
+        })
+    }
+
+    //Where clients connect, useful when bound to port 0
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    //Used by rooms opened from now on
+    pub fn set_residency_policy(&mut self, policy: ResidencyPolicy) {
+        self.residency = policy;
+    }
+
+    pub fn tick(&mut self, dt: f32) {
+        self.accept();
+        self.handshake();
+        for room in self.rooms.values_mut() {
+            room.tick(dt);
+        }
+        self.rooms.retain(|name, room| {
+            if room.is_empty() {
+                info!("Room {} is empty, closing it", name);
+            }
+            !room.is_empty()
+        });
+    }
+
+    fn accept(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => match Connection::new(stream) {
+                    Ok(connection) => {
+                        let id = self.next_id;
+                        self.next_id += 1;
+                        info!("Player {} connected from {}", id, connection.peer());
+                        self.pending.insert(id, (connection, Instant::now()));
+                    }
+                    Err(err) => warn!("Could not set up connection: {}", err),
+                },
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    warn!("Could not accept connection: {}", err);
+                    break;
+                }
+            }
+        }
+    }
+
+    //The first thing a client sends has to be Join, anything else closes the connection
+    fn handshake(&mut self) {
+        let mut joined = Vec::new();
+        let mut dropped = Vec::new();
+        for (id, (connection, connected)) in self.pending.iter_mut() {
+            match connection.receive() {
+                Ok(events) if events.is_empty() => {
+                    if connected.elapsed() > JOIN_TIMEOUT {
+                        info!("Player {} never joined", id);
+                        dropped.push(*id);
+                    }
+                }
+                Ok(mut events) => match events.remove(0) {
+                    Event::Join {
+                        name,
+                        room,
+                        version,
+                    } => joined.push((*id, name, room, version, events)),
+                    _ => {
+                        warn!("Player {} did not start with Join", id);
+                        dropped.push(*id);
+                    }
+                },
+                Err(_) => dropped.push(*id),
+            }
+        }
+
+        for id in dropped {
+            self.pending.remove(&id);
+        }
+        for (id, name, room, version, rest) in joined {
+            let (connection, _) = match self.pending.remove(&id) {
+                Some(pending) => pending,
+                None => continue,
+            };
+            self.join(id, connection, name, room, version, rest);
+        }
+    }
+
+    fn join(
+        &mut self,
+        id: u32,
+        mut connection: Connection,
+        name: String,
+        room: String,
+        version: u16,
+        rest: Vec<Event>,
+    ) {
+        if version != PROTOCOL_VERSION {
+            let reason = format!(
+                "server runs protocol version {}, you have {}",
+                PROTOCOL_VERSION, version
+            );
+            info!("Player {} refused: {}", id, reason);
+            let _ = connection.send(&Event::Refused { reason });
+            return;
+        }
+
+        let name = match clean(&name, MAX_NAME_LENGTH) {
+            Some(name) => name,
+            None => format!("Player{}", id),
+        };
+        let room_name = clean(&room, MAX_ROOM_LENGTH).unwrap_or_else(|| DEFAULT_ROOM.to_owned());
+        let started = self.started;
+        let world_gen_config = &self.world_gen_config;
+        let residency = self.residency;
+        let room = self.rooms.entry(room_name.clone()).or_insert_with(|| {
+            info!("Opening room {}", room_name);
+            let mut world = World::generate(world_gen_config.clone());
+            world.set_residency_policy(residency);
+            Room::new(world, started)
+        });
+
+        let welcome = Event::Welcome {
+            id,
+            room: room_name.clone(),
+            seed: room.seed(),
+        };
+        if connection.send(&welcome).is_err() {
+            return;
+        }
+        info!("Player {} joined {} as {}", id, room_name, name);
+        let player = Player::new_other(name, 0., 0., 0., 0., self.animations, self.texture);
+        room.join(id, connection, player);
+        //Whatever arrived together with Join
+        for event in rest {
+            room.handle_event(id, event);
+        }
+    }
+}
+
+//Trimmed and cut to `max` characters, None when nothing is left
+fn clean(value: &str, max: usize) -> Option<String> {
+    let value: String = value
+        .trim()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(max)
+        .collect();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
@@ -0,0 +1,580 @@
+use std::{collections::HashMap, time::Instant};
+
+use macroquad::prelude::*;
+
+use crate::{
+    chat::{self, Emote, RateLimiter},
+    combat::{self, Attack, ClockOffset, PlayerState, PositionHistory},
+    game::MAX_RENDER,
+    multiplayer::{net::Connection, Event},
+    player::{BlockingAction, Player, MAX_HEALTH, RESPAWN_RANGE, SPAWN_POINT},
+    world::{
+        entity::{Direction, WorldEntity},
+        positions::{ChunkPosition, Coords},
+        tile::{Tile, TileAction},
+        World,
+    },
+};
+
+//Fastest a player can legitimately move, a roll is 30 units per second
+const MAX_SPEED: f32 = 30.;
+//Allowed difference between where a client says it is and where the server thinks it can be
+const POSITION_SLACK: f32 = 2.;
+//Tiles around each player the server keeps generated
+const SIMULATION_RADIUS: f32 = 32.;
+//Furthest from a player a tile can be dug or placed, placing reaches 16 tiles from the camera
+const EDIT_REACH: f32 = 17.;
+//Chunk requests a client can send in a row, everything in render distance fits
+const CHUNK_REQUEST_BURST: f32 = 1200.;
+//Chunk requests per second once the burst is used up, rolling diagonally past the edge of
+//render distance needs about 260
+const CHUNK_REQUEST_RATE: f32 = 300.;
+
+struct Client<'a> {
+    connection: Connection,
+    player: Player<'a>,
+    last_update: Instant,
+    //Where the player joined or died, its next update may jump anywhere within respawn range
+    //of it. Only set by the server
+    respawn_from: Option<Vec2>,
+    history: PositionHistory,
+    clock: ClockOffset,
+    //Shared by chat messages and emotes
+    chat: RateLimiter,
+    chunk_requests: RateLimiter,
+}
+
+//One session, players only see and affect others in the same room
+pub struct Room<'a> {
+    world: World,
+    clients: HashMap<u32, Client<'a>>,
+    started: Instant,
+}
+
+impl<'a> Room<'a> {
+    pub fn new(world: World, started: Instant) -> Room<'a> {
+        Room {
+            world,
+            clients: HashMap::new(),
+            started,
+        }
+    }
+
+    //Seconds since the server started, the clock attacks are resolved against
+    fn now(&self) -> f64 {
+        self.started.elapsed().as_secs_f64()
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.world.get_seed()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+
+    //The new player is told who is already here and everyone else about the new player
+    pub fn join(&mut self, id: u32, mut connection: Connection, player: Player<'a>) {
+        let name = player.get_name().to_owned();
+        for (other, client) in self.clients.iter() {
+            let joined = Event::PlayerJoined {
+                id: *other,
+                name: client.player.get_name().to_owned(),
+            };
+            if connection.send(&joined).is_err() {
+                return;
+            }
+        }
+        self.broadcast(id, Event::PlayerJoined { id, name });
+        self.clients.insert(
+            id,
+            Client {
+                connection,
+                player,
+                last_update: Instant::now(),
+                respawn_from: Some(SPAWN_POINT),
+                history: PositionHistory::new(),
+                clock: ClockOffset::new(),
+                chat: RateLimiter::chat(),
+                chunk_requests: RateLimiter::new(CHUNK_REQUEST_BURST, CHUNK_REQUEST_RATE),
+            },
+        );
+    }
+
+    pub fn tick(&mut self, dt: f32) {
+        self.receive();
+        self.broadcast_tile_changes();
+        self.simulate(dt);
+        self.broadcast_positions();
+        self.flush();
+    }
+
+    fn receive(&mut self) {
+        let mut received = Vec::new();
+        let mut disconnected = Vec::new();
+        for (id, client) in self.clients.iter_mut() {
+            match client.connection.receive() {
+                Ok(events) => received.extend(events.into_iter().map(|event| (*id, event))),
+                Err(_) => disconnected.push(*id),
+            }
+        }
+
+        for (id, event) in received {
+            self.handle_event(id, event);
+        }
+        for id in disconnected {
+            self.disconnect(id);
+        }
+    }
+
+    //Whatever did not fit into a socket earlier, clients that fall too far behind were already
+    //dropped by send
+    fn flush(&mut self) {
+        let failed: Vec<u32> = self
+            .clients
+            .iter_mut()
+            .filter_map(|(id, client)| client.connection.flush().err().map(|_| *id))
+            .collect();
+        for id in failed {
+            self.disconnect(id);
+        }
+    }
+
+    fn disconnect(&mut self, id: u32) {
+        if self.clients.remove(&id).is_some() {
+            info!("Player {} left", id);
+            self.broadcast(id, Event::PlayerDisconnect { id });
+        }
+    }
+
+    //Clients always send their own id as 0, the id of the connection is used instead
+    pub fn handle_event(&mut self, id: u32, event: Event) {
+        match event {
+            Event::PlayerUpdate {
+                x,
+                y,
+                vx,
+                vy,
+                seq,
+                time,
+                ..
+            } => self.handle_player_update(id, vec2(x, y), vec2(vx, vy), seq, time),
+            Event::PlayerAction {
+                x,
+                y,
+                direction,
+                action,
+                time,
+                ..
+            } => self.handle_player_action(id, vec2(x, y), direction, action, time),
+            Event::TileChanged { x, y, tile, .. } => {
+                self.handle_tile_changed(id, Coords { x, y }, tile)
+            }
+            Event::ChunkRequest { x, y, revision } => {
+                self.handle_chunk_request(id, ChunkPosition { x, y }, revision)
+            }
+            Event::Chat { text, .. } => self.handle_chat(id, text),
+            Event::Emote { emote, .. } => self.handle_emote(id, emote),
+            Event::Leave => self.disconnect(id),
+            //Already in this room, switching rooms takes a new connection
+            Event::Join { .. } => (),
+            //Only the server sends these
+            Event::PlayerDisconnect { .. } | Event::CommandTeleport { .. } => (),
+            Event::CommandHit { .. } | Event::CommandAck { .. } | Event::ChunkData { .. } => (),
+            Event::Welcome { .. } | Event::Refused { .. } | Event::PlayerJoined { .. } => (),
+            //Hits are decided here, not by the clients
+            Event::PlayerHit { .. } => (),
+        }
+    }
+
+    //Every update is acknowledged with where the server has the player, which is the
+    //position sent unless it was rejected
+    fn handle_player_update(&mut self, id: u32, pos: Vec2, velocity: Vec2, seq: u32, time: f64) {
+        let now = self.now();
+        let client = match self.clients.get_mut(&id) {
+            Some(client) => client,
+            None => return,
+        };
+        //Updates come far more often than actions, so the clock settles before the first attack
+        client.clock.observe(time, now);
+        let elapsed = client.last_update.elapsed().as_secs_f32();
+        let max_distance = MAX_SPEED * elapsed + POSITION_SLACK;
+        //Respawns land wherever the client rolled, the tile is not checked
+        let respawn_distance = RESPAWN_RANGE as f32 + POSITION_SLACK;
+        let valid = match client.respawn_from {
+            Some(from) => (pos - from).abs().max_element() <= respawn_distance,
+            None => {
+                velocity.length() <= MAX_SPEED + 0.01
+                    && client.player.get_position().distance(pos) <= max_distance
+                    && self.world.can_move_entity_to_tile(pos)
+            }
+        };
+
+        if valid {
+            //The copy here may still be playing the death the client already got up from
+            if client.respawn_from.is_some() {
+                client.player.reset_state();
+                client.player.set_health(MAX_HEALTH);
+            }
+            client.player.set_position(pos);
+            client.player.set_velocity(velocity);
+            client.last_update = Instant::now();
+            client.respawn_from = None;
+        }
+
+        let pos = client.player.get_position();
+        let ack = Event::CommandAck {
+            seq,
+            x: pos.x,
+            y: pos.y,
+        };
+        if client.connection.send(&ack).is_err() {
+            self.disconnect(id);
+        }
+    }
+
+    fn handle_player_action(
+        &mut self,
+        id: u32,
+        pos: Vec2,
+        direction: Direction,
+        action: BlockingAction,
+        time: f64,
+    ) {
+        let now = self.now();
+        let client = match self.clients.get_mut(&id) {
+            Some(client) => client,
+            None => return,
+        };
+        client.clock.observe(time, now);
+        let attack_time = client.clock.to_local_time(time, now);
+        if client.player.get_position().distance(pos) > POSITION_SLACK {
+            return;
+        }
+        match action {
+            //Clients may report walking into something deadly, which only counts if the server
+            //agrees the tile kills and has not seen the death in its own simulation already
+            BlockingAction::Dying => {
+                let deadly = match self.world.get_tile(&Coords::from_vec2(pos)) {
+                    Some(tile) => tile.action == TileAction::Death,
+                    None => false,
+                };
+                if !deadly || client.respawn_from.is_some() {
+                    return;
+                }
+                client.player.force_action(action, pos, direction);
+                client.respawn_from = Some(pos);
+                client.history.clear();
+            }
+            _ => {
+                if !client.player.can_action(action) {
+                    return;
+                }
+                client.player.force_action(action, pos, direction);
+            }
+        }
+        self.broadcast(
+            id,
+            Event::PlayerAction {
+                id,
+                x: pos.x,
+                y: pos.y,
+                direction,
+                action,
+                time: now,
+            },
+        );
+
+        if action == BlockingAction::Attack {
+            self.resolve_attack(id, Attack { pos, direction }, attack_time);
+        }
+    }
+
+    //Every other player is checked where they were and what they were doing when the
+    //attacker swung
+    fn resolve_attack(&mut self, attacker: u32, attack: Attack, time: f64) {
+        let hits: Vec<(u32, PlayerState)> = self
+            .clients
+            .iter()
+            .filter(|(id, client)| **id != attacker && client.respawn_from.is_none())
+            .map(|(id, client)| {
+                let state = client
+                    .history
+                    .state_at(time)
+                    .unwrap_or_else(|| client.player.state());
+                (*id, state)
+            })
+            .filter(|(_, state)| combat::resolve_attack(&self.world, &attack, state.pos).is_ok())
+            .collect();
+        for (id, state) in hits {
+            self.hit(id, &attack, state);
+        }
+    }
+
+    //The player hears how much health it has left and how it is knocked back, everyone
+    //else only sees the damage, and the death if that was the last of it
+    fn hit(&mut self, id: u32, attack: &Attack, state: PlayerState) {
+        let now = self.now();
+        let client = match self.clients.get_mut(&id) {
+            Some(client) => client,
+            None => return,
+        };
+        //Rolled through it
+        let hit = match client
+            .player
+            .take_hit_as(attack, combat::ATTACK_DAMAGE, state)
+        {
+            Some(hit) => hit,
+            None => return,
+        };
+        let command = Event::CommandHit {
+            health: hit.health,
+            kx: hit.knockback.x,
+            ky: hit.knockback.y,
+        };
+        if client.connection.send(&command).is_err() {
+            self.disconnect(id);
+            return;
+        }
+        let killed = hit.health == 0;
+        if killed {
+            client.respawn_from = Some(client.player.get_position());
+            client.history.clear();
+        }
+        let pos = client.player.get_position();
+        let direction = client.player.get_direction();
+        self.broadcast(
+            id,
+            Event::PlayerHit {
+                id,
+                damage: hit.damage,
+                health: hit.health,
+                blocked: hit.blocked,
+            },
+        );
+        if killed {
+            self.broadcast(
+                id,
+                Event::PlayerAction {
+                    id,
+                    x: pos.x,
+                    y: pos.y,
+                    direction,
+                    action: BlockingAction::Dying,
+                    time: now,
+                },
+            );
+        }
+    }
+
+    //Accepted edits go out to everyone, the one who made it included so it learns the
+    //revision, rejected ones get the server's tile sent back to undo them
+    fn handle_tile_changed(&mut self, id: u32, coords: Coords, tile: Tile) {
+        let client = match self.clients.get_mut(&id) {
+            Some(client) => client,
+            None => return,
+        };
+        let center = coords.to_vec2() + vec2(0.5, 0.5);
+        let in_reach = client.respawn_from.is_none()
+            && client.player.get_position().distance(center) <= EDIT_REACH;
+        if in_reach && self.world.change_tile(&coords, tile) {
+            return;
+        }
+
+        let current = match self.world.get_tile(&coords) {
+            Some(current) => *current,
+            None => return,
+        };
+        let revision = self
+            .world
+            .chunk_revision(&ChunkPosition::from_coords(&coords))
+            .unwrap_or(0);
+        let undo = Event::TileChanged {
+            x: coords.x,
+            y: coords.y,
+            tile: current,
+            revision,
+        };
+        if client.connection.send(&undo).is_err() {
+            self.disconnect(id);
+        }
+    }
+
+    //Only chunks the client could have generated around where the server has it are answered,
+    //anything else would have the server generate the world for whoever asks
+    fn handle_chunk_request(&mut self, id: u32, pos: ChunkPosition, revision: u32) {
+        let now = self.now();
+        let client = match self.clients.get_mut(&id) {
+            Some(client) => client,
+            None => return,
+        };
+        //Until the player respawned it can be anywhere within respawn range
+        let (center, slack) = match client.respawn_from {
+            Some(from) => (from, RESPAWN_RANGE as f32 + POSITION_SLACK),
+            None => (client.player.get_position(), POSITION_SLACK),
+        };
+        let size = MAX_RENDER + slack * 2.;
+        let (min, max) = ChunkPosition::from_rect(Rect::new(
+            center.x - size / 2.,
+            center.y - size / 2.,
+            size,
+            size,
+        ));
+        let in_reach = pos.x >= min.x && pos.x < max.x && pos.y >= min.y && pos.y < max.y;
+        if !in_reach || !client.chunk_requests.try_take(now) {
+            return;
+        }
+
+        let corner = Coords::from_position(&pos).to_vec2();
+        //The chunk may be outside what the server simulates, so it is generated on demand
+        self.world.generate_at(
+            Rect::new(corner.x, corner.y, 1., 1.),
+            Rect::new(0., 0., 0., 0.),
+        );
+        let chunk = match self.world.chunk_data(&pos) {
+            Some(chunk) if chunk.revision > revision => chunk,
+            _ => return,
+        };
+        let client = match self.clients.get_mut(&id) {
+            Some(client) => client,
+            None => return,
+        };
+        if client.connection.send(&Event::ChunkData { chunk }).is_err() {
+            self.disconnect(id);
+        }
+    }
+
+    //Messages over the rate limit are dropped, the client warns about them before sending
+    fn handle_chat(&mut self, id: u32, text: String) {
+        let now = self.now();
+        let allowed = match self.clients.get_mut(&id) {
+            Some(client) => client.chat.try_take(now),
+            None => return,
+        };
+        match chat::clean_message(&text) {
+            Some(text) if allowed => self.broadcast(id, Event::Chat { id, text }),
+            _ => (),
+        }
+    }
+
+    fn handle_emote(&mut self, id: u32, emote: Emote) {
+        let now = self.now();
+        let allowed = match self.clients.get_mut(&id) {
+            Some(client) => client.chat.try_take(now),
+            None => return,
+        };
+        if allowed {
+            self.broadcast(id, Event::Emote { id, emote });
+        }
+    }
+
+    fn broadcast_tile_changes(&mut self) {
+        for change in self.world.take_tile_changes() {
+            //No client has id 0, so this reaches all of them
+            self.broadcast(
+                0,
+                Event::TileChanged {
+                    x: change.coords.x,
+                    y: change.coords.y,
+                    tile: change.tile,
+                    revision: change.revision,
+                },
+            );
+        }
+    }
+
+    fn simulate(&mut self, dt: f32) {
+        for client in self.clients.values() {
+            let pos = client.player.get_position();
+            let zone = Rect::new(
+                pos.x - SIMULATION_RADIUS,
+                pos.y - SIMULATION_RADIUS,
+                SIMULATION_RADIUS * 2.,
+                SIMULATION_RADIUS * 2.,
+            );
+            self.world.generate_at(zone, Rect::new(0., 0., 0., 0.));
+        }
+        let now = self.now();
+        let mut died = Vec::new();
+        for (id, client) in self.clients.iter_mut() {
+            self.world.update_entity(&mut client.player, dt);
+            //Clients report their own edits as TileChanged, the copies here only need their
+            //events cleared
+            client.player.clear_world_events();
+            let state = client.player.state();
+            match client.respawn_from {
+                //Walked onto a deadly tile, the client sees that for itself and respawns
+                None if state.action == Some(BlockingAction::Dying) => {
+                    client.respawn_from = Some(state.pos);
+                    client.history.clear();
+                    died.push((*id, state));
+                }
+                None => client.history.record(now, state),
+                Some(_) => (),
+            }
+        }
+        for (id, state) in died {
+            self.broadcast(
+                id,
+                Event::PlayerAction {
+                    id,
+                    x: state.pos.x,
+                    y: state.pos.y,
+                    direction: state.direction,
+                    action: BlockingAction::Dying,
+                    time: now,
+                },
+            );
+        }
+        let positions: Vec<Vec2> = self
+            .clients
+            .values()
+            .map(|client| client.player.get_position())
+            .collect();
+        self.world.evict(&positions);
+        //Nothing to tell anyone about chunks the server generates for itself
+        self.world.take_new_chunks();
+    }
+
+    //Stamped with the server clock, which every client maps onto its own
+    fn broadcast_positions(&mut self) {
+        let now = self.now();
+        let updates: Vec<(u32, Event)> = self
+            .clients
+            .iter()
+            .map(|(id, client)| {
+                let pos = client.player.get_position();
+                let velocity = client.player.get_velocity();
+                (
+                    *id,
+                    Event::PlayerUpdate {
+                        name: client.player.get_name().to_owned(),
+                        id: *id,
+                        x: pos.x,
+                        y: pos.y,
+                        vx: velocity.x,
+                        vy: velocity.y,
+                        seq: 0,
+                        time: now,
+                    },
+                )
+            })
+            .collect();
+        for (id, update) in updates {
+            self.broadcast(id, update);
+        }
+    }
+
+    //Sends to everyone except `from`
+    fn broadcast(&mut self, from: u32, event: Event) {
+        let mut failed = Vec::new();
+        for (id, client) in self.clients.iter_mut() {
+            if *id != from && client.connection.send(&event).is_err() {
+                failed.push(*id);
+            }
+        }
+        for id in failed {
+            self.disconnect(id);
+        }
+    }
+}
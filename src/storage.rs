@@ -0,0 +1,44 @@
+//Small key value store for things that should survive a restart.
+//Native builds write files to the saves folder, wasm builds go through localStorage.
+
+#[cfg(not(target_arch = "wasm32"))]
+const SAVE_DIRECTORY: &str = "saves";
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save(key: &str, value: &str) -> Result<(), String> {
+    std::fs::create_dir_all(SAVE_DIRECTORY).map_err(|err| err.to_string())?;
+    std::fs::write(format!("{}/{}.json", SAVE_DIRECTORY, key), value).map_err(|err| err.to_string())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load(key: &str) -> Option<String> {
+    std::fs::read_to_string(format!("{}/{}.json", SAVE_DIRECTORY, key)).ok()
+}
+
+//The host page has to register these next to the miniquad loader:
+//storage_set(key, value) stores the strings in localStorage,
+//storage_get(key) returns the stored string or null
+#[cfg(target_arch = "wasm32")]
+extern "C" {
+    fn storage_set(key: sapp_jsutils::JsObject, value: sapp_jsutils::JsObject);
+    fn storage_get(key: sapp_jsutils::JsObject) -> sapp_jsutils::JsObject;
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn save(key: &str, value: &str) -> Result<(), String> {
+    use sapp_jsutils::JsObject;
+    unsafe { storage_set(JsObject::string(key), JsObject::string(value)) };
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn load(key: &str) -> Option<String> {
+    use sapp_jsutils::JsObject;
+    let value = unsafe { storage_get(JsObject::string(key)) };
+    if value.is_nil() {
+        return None;
+    }
+    let mut buffer = String::new();
+    value.to_string(&mut buffer);
+    Some(buffer)
+}
@@ -0,0 +1,136 @@
+use macroquad::prelude::*;
+
+//Simulation steps per second unless `--tick-rate` says otherwise
+pub const DEFAULT_TICK_RATE: f32 = 60.;
+//Longer frames are cut to this so a stall does not queue up a flood of steps
+const MAX_FRAME: f32 = 0.25;
+//Moving further than this in one step is a teleport or respawn, not something to blend
+const SNAP_DISTANCE: f32 = 2.;
+//Frame times hardly ever add up to exactly a step, one that is short by less than this share
+//of a step is taken anyway so rounding does not cost a step at some frame rates
+const STEP_TOLERANCE: f32 = 0.001;
+
+//Turns variable frame times into a whole number of equal steps, what is left over is
+//carried to the next frame and used to blend between the last two steps when drawing
+pub struct FixedTimestep {
+    step: f32,
+    accumulator: f32,
+    ticks: u64,
+}
+
+impl FixedTimestep {
+    pub fn new(tick_rate: f32) -> FixedTimestep {
+        FixedTimestep {
+            step: 1. / tick_rate.max(1.),
+            accumulator: 0.,
+            ticks: 0,
+        }
+    }
+
+    //Reads `--tick-rate <hz>` from the command line
+    pub fn tick_rate_from_args(args: impl Iterator<Item = String>) -> Option<f32> {
+        let mut args = args.skip_while(|arg| arg != "--tick-rate").skip(1);
+        match args.next() {
+            Some(rate) => rate.parse::<f32>().ok().filter(|rate| *rate > 0.),
+            None => None,
+        }
+    }
+
+    //Seconds simulated by every step
+    pub fn step(&self) -> f32 {
+        self.step
+    }
+
+    pub fn tick_rate(&self) -> f32 {
+        1. / self.step
+    }
+
+    //Adds the time the last frame took, returns how many steps to simulate
+    pub fn advance(&mut self, frame_time: f32) -> u32 {
+        self.accumulator += frame_time.clamp(0., MAX_FRAME);
+        let mut steps = 0;
+        while self.accumulator >= self.step * (1. - STEP_TOLERANCE) {
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+        self.ticks += steps as u64;
+        steps
+    }
+
+    //How far into the next step the frame is drawn, from 0 to 1
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.step).max(0.)
+    }
+
+    //Steps simulated since the start
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    //Where to draw something that was at `previous` before the last step and is at `current`
+    pub fn blend(&self, previous: Vec2, current: Vec2) -> Vec2 {
+        if previous.distance(current) > SNAP_DISTANCE {
+            current
+        } else {
+            previous.lerp(current, self.alpha())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //A power of two, so steps and the frame times below add up without rounding
+    const TICK_RATE: f32 = 64.;
+
+    fn total_steps(frames: &[f32]) -> u32 {
+        let mut timestep = FixedTimestep::new(TICK_RATE);
+        frames.iter().map(|frame| timestep.advance(*frame)).sum()
+    }
+
+    #[test]
+    fn frame_splits_do_not_change_the_step_count() {
+        //Two seconds every time
+        let even = total_steps(&[1. / 64.; 128]);
+        assert_eq!(even, 128);
+        assert_eq!(total_steps(&[1. / 256.; 512]), even);
+        assert_eq!(total_steps(&[1. / 8.; 16]), even);
+        let uneven: Vec<f32> = (0..32)
+            .flat_map(|_| [1. / 128., 3. / 128., 1. / 256., 7. / 256.])
+            .collect();
+        assert_eq!(total_steps(&uneven), even);
+    }
+
+    #[test]
+    fn stalls_are_cut_to_max_frame() {
+        let mut timestep = FixedTimestep::new(TICK_RATE);
+        assert_eq!(timestep.advance(10.), (MAX_FRAME * TICK_RATE) as u32);
+        assert_eq!(timestep.alpha(), 0.);
+        assert_eq!(timestep.advance(-1.), 0);
+        assert_eq!(timestep.ticks(), (MAX_FRAME * TICK_RATE) as u64);
+    }
+
+    #[test]
+    fn alpha_stays_below_one() {
+        let mut timestep = FixedTimestep::new(60.);
+        for frame in [1. / 144., 1. / 30., 0.0123, 1. / 60., 0.2, 0.3, 0.] {
+            for _ in 0..50 {
+                timestep.advance(frame);
+                let alpha = timestep.alpha();
+                assert!((0. ..1.).contains(&alpha), "{} after {}", alpha, frame);
+            }
+        }
+    }
+
+    #[test]
+    fn blend_snaps_beyond_snap_distance() {
+        let mut timestep = FixedTimestep::new(TICK_RATE);
+        timestep.advance(0.5 / TICK_RATE);
+        let previous = vec2(0., 0.);
+        let near = vec2(SNAP_DISTANCE - 0.5, 0.);
+        assert_eq!(timestep.blend(previous, near), near / 2.);
+        let far = vec2(SNAP_DISTANCE + 0.5, 0.);
+        assert_eq!(timestep.blend(previous, far), far);
+    }
+}
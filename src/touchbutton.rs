@@ -1,7 +1,7 @@
 use macroquad::{
     prelude::{
         color_u8, draw_circle, is_mouse_button_down, mouse_position, touches, vec2, Color,
-        MouseButton, TouchPhase, Vec2,
+        MouseButton, Touch, TouchPhase, Vec2,
     },
     shapes::draw_rectangle,
     time::get_frame_time,
@@ -11,8 +11,10 @@ pub struct Button {
     pos: Vec2,
     size: Vec2,
     event: ButtonEvent,
-    // touch_id: Option<u64>,
-    drawable: Box<dyn Fn(f32, f32, f32, f32)>
+    //The touch holding the button down, no other touch can press or release it meanwhile
+    touch_id: Option<u64>,
+    color: Color,
+    drawable: Box<dyn Fn(f32, f32, f32, f32, Color)>
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -24,26 +26,28 @@ pub enum ButtonEvent {
 
 impl Button {
     pub fn rectangle(pos: Vec2, size: Vec2) -> Button {
-        let background_fn = Box::new(|x: f32, y: f32, w: f32, h: f32| {
-            draw_rectangle(x, y, w, h, color_u8!(96, 128, 144, 128))
+        let background_fn = Box::new(|x: f32, y: f32, w: f32, h: f32, color: Color| {
+            draw_rectangle(x, y, w, h, color)
         });
         Button {
             pos: pos - size/2.,
             size,
             event: ButtonEvent::Inactive,
-            // touch_id: None,
+            touch_id: None,
+            color: color_u8!(96, 128, 144, 128),
             drawable: background_fn
         }
     }
     pub fn circle(pos: Vec2, r: f32) -> Button {
-        let background_fn = Box::new(|x: f32, y: f32, w: f32, _h: f32| {
-            draw_circle(x+w/2., y+w/2., w/2., color_u8!(96, 128, 144, 128));
+        let background_fn = Box::new(|x: f32, y: f32, w: f32, _h: f32, color: Color| {
+            draw_circle(x+w/2., y+w/2., w/2., color);
         });
         Button {
             pos: pos - r/2.,
             size: vec2(r, r),
             event: ButtonEvent::Inactive,
-            // touch_id: None,
+            touch_id: None,
+            color: color_u8!(96, 128, 144, 128),
             drawable: background_fn
         }
     }
@@ -52,11 +56,55 @@ impl Button {
         if touches().is_empty() {
             self.update_mouse();
         } else {
-            self.update_touch();
+            self.update_with(&touches(), get_frame_time());
         }
         self.event
     }
 
+    //Same as update but with the touches given, so it does not need a window
+    pub fn update_with(&mut self, touches: &[Touch], dt: f32) -> ButtonEvent {
+        //Pressed only lasts for the frame the touch was let go, after that a new touch is needed
+        self.event = match (self.touch_id, self.event) {
+            (Some(id), ButtonEvent::Hold(time)) => {
+                match touches.iter().find(|touch| touch.id == id) {
+                    Some(touch) => match touch.phase {
+                        TouchPhase::Ended | TouchPhase::Cancelled => ButtonEvent::Pressed(time),
+                        _ => ButtonEvent::Hold(time + dt),
+                    },
+                    //Missed the end of the touch, let go all the same
+                    None => ButtonEvent::Pressed(time),
+                }
+            }
+            _ => {
+                let started = touches.iter().find(|touch| {
+                    touch.phase == TouchPhase::Started
+                        && in_box(self.pos, self.size, touch.position)
+                });
+                match started {
+                    Some(touch) => {
+                        self.touch_id = Some(touch.id);
+                        ButtonEvent::Hold(0.)
+                    }
+                    None => ButtonEvent::Inactive,
+                }
+            }
+        };
+        if !matches!(self.event, ButtonEvent::Hold(_)) {
+            self.touch_id = None;
+        }
+        self.event
+    }
+
+    //From 0 for invisible to 1 for solid
+    pub fn set_opacity(&mut self, opacity: f32) {
+        self.color.a = opacity.clamp(0., 1.);
+    }
+
+    //The touch that is holding the button, if any
+    pub fn touch_id(&self) -> Option<u64> {
+        self.touch_id
+    }
+
     pub fn pressed(&mut self) -> bool {
         match self.event {
             ButtonEvent::Pressed(_) => true,
@@ -72,7 +120,7 @@ impl Button {
     }
 
     pub fn render(&self) {
-        (self.drawable)(self.pos.x, self.pos.y, self.size.x,self.size.y);
+        (self.drawable)(self.pos.x, self.pos.y, self.size.x,self.size.y, self.color);
     }
 
     fn update_mouse(&mut self) {
@@ -87,37 +135,6 @@ impl Button {
             _ => ButtonEvent::Inactive,
         };
     }
-
-    fn update_touch(&mut self) {
-        for touch in touches() {
-            let in_box = in_box(self.pos, self.size, touch.position);
-            let touch_id: Option<u64> = None;
-            match (touch.phase, touch_id, in_box, self.event) {
-                (_, _, _, ButtonEvent::Pressed(_)) => {
-                    // self.touch_id = None;
-                    self.event = ButtonEvent::Inactive;
-                }
-                (TouchPhase::Started, None, true, _) => {
-                    // self.touch_id = Some(touch.id);
-                    self.event = ButtonEvent::Hold(0.)
-                }
-                (TouchPhase::Stationary, _, true, ButtonEvent::Hold(time))
-                | (TouchPhase::Moved, _, true, ButtonEvent::Hold(time)) => {
-                    // if touch_id == touch.id {
-                        self.event = ButtonEvent::Hold(time + get_frame_time());
-                    // }
-                }
-                (TouchPhase::Ended, _, _, ButtonEvent::Hold(time))
-                | (TouchPhase::Cancelled, _, _, ButtonEvent::Hold(time)) => {
-                    // if touch_id == touch.id {
-                        self.event = ButtonEvent::Pressed(time);
-                        // self.touch_id = None;
-                    // }
-                }
-                _ => (),
-            }
-        }
-    }
 }
 
 fn in_box(pos: Vec2, size: Vec2, point: Vec2) -> bool {
@@ -125,3 +142,122 @@ fn in_box(pos: Vec2, size: Vec2, point: Vec2) -> bool {
     let y_in_range = point.y >= pos.y && point.y <= pos.y + size.y;
     x_in_range && y_in_range
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 0.1;
+
+    fn touch(id: u64, phase: TouchPhase, x: f32, y: f32) -> Touch {
+        Touch {
+            id,
+            phase,
+            position: vec2(x, y),
+        }
+    }
+
+    //A button around (100, 100) and one around (300, 100)
+    fn attack_and_roll() -> (Button, Button) {
+        (
+            Button::rectangle(vec2(100., 100.), vec2(50., 50.)),
+            Button::rectangle(vec2(300., 100.), vec2(50., 50.)),
+        )
+    }
+
+    #[test]
+    fn a_button_is_held_while_the_joystick_is_used() {
+        let (mut attack, _) = attack_and_roll();
+        let frames = [
+            vec![touch(1, TouchPhase::Started, 20., 400.)],
+            vec![
+                touch(1, TouchPhase::Moved, 30., 390.),
+                touch(2, TouchPhase::Started, 100., 100.),
+            ],
+            vec![
+                touch(1, TouchPhase::Moved, 40., 380.),
+                touch(2, TouchPhase::Stationary, 100., 100.),
+            ],
+            vec![
+                touch(1, TouchPhase::Moved, 50., 370.),
+                touch(2, TouchPhase::Moved, 105., 95.),
+            ],
+        ];
+        let events: Vec<ButtonEvent> = frames
+            .iter()
+            .map(|touches| attack.update_with(touches, DT))
+            .collect();
+        assert!(matches!(events[0], ButtonEvent::Inactive));
+        assert!(matches!(events[1], ButtonEvent::Hold(time) if time == 0.));
+        assert!(matches!(events[3], ButtonEvent::Hold(time) if (time - 2. * DT).abs() < 0.0001));
+        assert_eq!(attack.touch_id(), Some(2));
+        assert!(attack.down());
+    }
+
+    #[test]
+    fn another_touch_ending_does_not_let_go() {
+        let (mut attack, mut roll) = attack_and_roll();
+        let both = [
+            touch(1, TouchPhase::Started, 100., 100.),
+            touch(2, TouchPhase::Started, 300., 100.),
+        ];
+        attack.update_with(&both, DT);
+        roll.update_with(&both, DT);
+
+        //The roll finger lifts, a third one lands on the attack button and lifts again
+        let frames = [
+            vec![
+                touch(1, TouchPhase::Stationary, 100., 100.),
+                touch(2, TouchPhase::Ended, 300., 100.),
+            ],
+            vec![
+                touch(1, TouchPhase::Stationary, 100., 100.),
+                touch(3, TouchPhase::Started, 110., 110.),
+            ],
+            vec![
+                touch(1, TouchPhase::Stationary, 100., 100.),
+                touch(3, TouchPhase::Ended, 110., 110.),
+            ],
+        ];
+        assert!(matches!(
+            roll.update_with(&frames[0], DT),
+            ButtonEvent::Pressed(_)
+        ));
+        for touches in frames.iter() {
+            assert!(matches!(
+                attack.update_with(touches, DT),
+                ButtonEvent::Hold(_)
+            ));
+            assert_eq!(attack.touch_id(), Some(1));
+        }
+        assert!(matches!(
+            roll.update_with(&frames[1], DT),
+            ButtonEvent::Inactive
+        ));
+        assert!(!roll.down());
+    }
+
+    #[test]
+    fn a_touch_that_vanished_presses_the_button() {
+        let (mut attack, _) = attack_and_roll();
+        attack.update_with(&[touch(1, TouchPhase::Started, 100., 100.)], DT);
+        attack.update_with(&[touch(1, TouchPhase::Stationary, 100., 100.)], DT);
+
+        //No Ended for touch 1, it is just gone
+        let event = attack.update_with(&[touch(2, TouchPhase::Started, 300., 100.)], DT);
+        assert!(matches!(event, ButtonEvent::Pressed(time) if time == DT));
+        assert!(attack.pressed());
+        assert_eq!(attack.touch_id(), None);
+        assert!(matches!(attack.update_with(&[], DT), ButtonEvent::Inactive));
+    }
+
+    #[test]
+    fn touches_starting_outside_do_not_press() {
+        let (mut attack, _) = attack_and_roll();
+        attack.update_with(&[touch(1, TouchPhase::Started, 200., 100.)], DT);
+        //Sliding onto the button is not pressing it
+        let event = attack.update_with(&[touch(1, TouchPhase::Moved, 100., 100.)], DT);
+        assert!(matches!(event, ButtonEvent::Inactive));
+        assert!(!attack.down());
+    }
+}
@@ -0,0 +1,572 @@
+use macroquad::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::storage;
+
+//Storage key the layout is saved under, storage is kept per device
+pub const LAYOUT_KEY: &str = "touch_layout";
+pub const LAYOUT_VERSION: u32 = 1;
+const MIN_SIZE: f32 = 0.05;
+const MAX_SIZE: f32 = 0.8;
+const MIN_OPACITY: f32 = 0.1;
+const OPACITY_STEP: f32 = 0.1;
+//Toolbar of the editor, in pixels
+const TOOL_WIDTH: f32 = 110.;
+const TOOL_HEIGHT: f32 = 50.;
+//Dragged to resize an element, in pixels
+const HANDLE_SIZE: f32 = 24.;
+
+//Everything that can be put on the touch screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum TouchAction {
+    Joystick,
+    Attack,
+    Roll,
+    Block,
+    Place,
+    Chat,
+}
+
+impl TouchAction {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TouchAction::Joystick => "Move",
+            TouchAction::Attack => "Attack",
+            TouchAction::Roll => "Roll",
+            TouchAction::Block => "Block",
+            TouchAction::Place => "Place",
+            TouchAction::Chat => "Chat",
+        }
+    }
+}
+
+//The screen corner an element keeps its distance to when the screen changes size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Anchor {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Anchor {
+    fn point(&self, screen: Vec2) -> Vec2 {
+        match self {
+            Anchor::TopLeft => vec2(0., 0.),
+            Anchor::TopRight => vec2(screen.x, 0.),
+            Anchor::BottomLeft => vec2(0., screen.y),
+            Anchor::BottomRight => screen,
+        }
+    }
+
+    fn mirrored(&self) -> Anchor {
+        match self {
+            Anchor::TopLeft => Anchor::TopRight,
+            Anchor::TopRight => Anchor::TopLeft,
+            Anchor::BottomLeft => Anchor::BottomRight,
+            Anchor::BottomRight => Anchor::BottomLeft,
+        }
+    }
+
+    fn nearest(pos: Vec2, screen: Vec2) -> Anchor {
+        match (pos.x < screen.x / 2., pos.y < screen.y / 2.) {
+            (true, true) => Anchor::TopLeft,
+            (false, true) => Anchor::TopRight,
+            (true, false) => Anchor::BottomLeft,
+            (false, false) => Anchor::BottomRight,
+        }
+    }
+}
+
+//Offsets and sizes are in parts of the short side of the screen, so the controlls keep
+//their shape when the screen is turned or resized
+fn unit(screen: Vec2) -> f32 {
+    screen.x.min(screen.y)
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TouchElement {
+    pub action: TouchAction,
+    pub anchor: Anchor,
+    //From the anchor to the center
+    pub offset: [f32; 2],
+    //Width, or diameter for round ones
+    pub size: f32,
+    pub enabled: bool,
+}
+
+impl TouchElement {
+    fn new(action: TouchAction, anchor: Anchor, offset: [f32; 2], size: f32) -> TouchElement {
+        TouchElement {
+            action,
+            anchor,
+            offset,
+            size,
+            enabled: true,
+        }
+    }
+
+    //Left handed layouts swap left and right
+    pub fn center(&self, screen: Vec2, left_handed: bool) -> Vec2 {
+        let (anchor, offset) = if left_handed {
+            (
+                self.anchor.mirrored(),
+                vec2(-self.offset[0], self.offset[1]),
+            )
+        } else {
+            (self.anchor, Vec2::from(self.offset))
+        };
+        anchor.point(screen) + offset * unit(screen)
+    }
+
+    pub fn size(&self, screen: Vec2) -> f32 {
+        self.size * unit(screen)
+    }
+
+    //Moves the center to `center`, anchored to the closest corner
+    fn move_to(&mut self, center: Vec2, screen: Vec2, left_handed: bool) {
+        let anchor = Anchor::nearest(center, screen);
+        let offset = (center - anchor.point(screen)) / unit(screen);
+        if left_handed {
+            self.anchor = anchor.mirrored();
+            self.offset = [-offset.x, offset.y];
+        } else {
+            self.anchor = anchor;
+            self.offset = offset.to_array();
+        }
+    }
+}
+
+//Where the touch controlls go, how see-through they are and which hand they are made for
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TouchLayout {
+    pub version: u32,
+    pub elements: Vec<TouchElement>,
+    pub opacity: f32,
+    pub left_handed: bool,
+}
+
+impl TouchLayout {
+    //Block and Place are there but off, the editor can turn them on
+    pub fn default() -> TouchLayout {
+        let mut block =
+            TouchElement::new(TouchAction::Block, Anchor::BottomLeft, [0.42, -0.33], 0.15);
+        block.enabled = false;
+        let mut place =
+            TouchElement::new(TouchAction::Place, Anchor::BottomLeft, [0.42, -0.1], 0.15);
+        place.enabled = false;
+        TouchLayout {
+            version: LAYOUT_VERSION,
+            elements: vec![
+                TouchElement::new(
+                    TouchAction::Joystick,
+                    Anchor::BottomRight,
+                    [-0.3, -0.3],
+                    0.4,
+                ),
+                TouchElement::new(TouchAction::Attack, Anchor::BottomLeft, [0.2, -0.45], 0.16),
+                TouchElement::new(TouchAction::Roll, Anchor::BottomLeft, [0.2, -0.2], 0.16),
+                block,
+                place,
+                TouchElement::new(TouchAction::Chat, Anchor::TopRight, [-0.14, 0.55], 0.11),
+            ],
+            opacity: 0.5,
+            left_handed: false,
+        }
+    }
+
+    //Turned on elements only
+    pub fn elements(&self) -> impl Iterator<Item = &TouchElement> {
+        self.elements.iter().filter(|element| element.enabled)
+    }
+
+    fn element_mut(&mut self, action: TouchAction) -> Option<&mut TouchElement> {
+        self.elements
+            .iter_mut()
+            .find(|element| element.action == action)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    //Sizes and opacity out of what the editor allows are clamped back into it, a layout
+    //with the same action twice or numbers that are not finite is refused
+    pub fn from_json(json: &str) -> Result<TouchLayout, String> {
+        let mut layout: TouchLayout = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        if layout.version != LAYOUT_VERSION {
+            return Err(format!(
+                "layout version {} is not supported, expected {}",
+                layout.version, LAYOUT_VERSION
+            ));
+        }
+        if !layout.opacity.is_finite() {
+            return Err(String::from("layout opacity is not a number"));
+        }
+        layout.opacity = layout.opacity.clamp(MIN_OPACITY, 1.);
+        for (i, element) in layout.elements.iter().enumerate() {
+            if layout.elements[..i]
+                .iter()
+                .any(|other| other.action == element.action)
+            {
+                return Err(format!("{} is in the layout twice", element.action.label()));
+            }
+        }
+        for element in layout.elements.iter_mut() {
+            if !element.size.is_finite() || element.offset.iter().any(|x| !x.is_finite()) {
+                return Err(format!(
+                    "{} has a size or offset that is not a number",
+                    element.action.label()
+                ));
+            }
+            element.size = element.size.clamp(MIN_SIZE, MAX_SIZE);
+        }
+        Ok(layout)
+    }
+
+    //The saved layout, or the default one when there is none or it can not be read
+    pub fn load() -> TouchLayout {
+        match storage::load(LAYOUT_KEY).map(|json| TouchLayout::from_json(&json)) {
+            Some(Ok(layout)) => layout,
+            Some(Err(err)) => {
+                warn!("Ignoring touch layout: {}", err);
+                TouchLayout::default()
+            }
+            None => TouchLayout::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::save(LAYOUT_KEY, &self.to_json())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Tool {
+    Done,
+    Mirror,
+    Fainter,
+    Stronger,
+    Reset,
+    Toggle(TouchAction),
+}
+
+impl Tool {
+    fn all() -> [Tool; 7] {
+        [
+            Tool::Done,
+            Tool::Mirror,
+            Tool::Fainter,
+            Tool::Stronger,
+            Tool::Reset,
+            Tool::Toggle(TouchAction::Block),
+            Tool::Toggle(TouchAction::Place),
+        ]
+    }
+
+    //In rows along the top, as many to a row as fit the screen
+    fn rect(index: usize) -> Rect {
+        let per_row = ((screen_width() - 10.) / (TOOL_WIDTH + 10.)).max(1.) as usize;
+        Rect::new(
+            10. + (index % per_row) as f32 * (TOOL_WIDTH + 10.),
+            10. + (index / per_row) as f32 * (TOOL_HEIGHT + 10.),
+            TOOL_WIDTH,
+            TOOL_HEIGHT,
+        )
+    }
+
+    fn label(&self, layout: &TouchLayout) -> String {
+        match self {
+            Tool::Done => String::from("Done"),
+            Tool::Mirror => String::from("Mirror"),
+            Tool::Fainter => String::from("Fainter"),
+            Tool::Stronger => String::from("Stronger"),
+            Tool::Reset => String::from("Reset"),
+            Tool::Toggle(action) => {
+                let enabled = layout
+                    .elements
+                    .iter()
+                    .any(|element| element.action == *action && element.enabled);
+                format!("{} {}", if enabled { "-" } else { "+" }, action.label())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Drag {
+    //Where the element was grabbed, from its center
+    Move(TouchAction, Vec2),
+    Resize(TouchAction),
+}
+
+//Drag elements to move them and their corner handle to resize them, the toolbar at the top
+//mirrors the layout, changes the opacity and adds or removes the optional buttons.
+//Works with the mouse too, macroquad turns the first touch into the mouse
+pub struct LayoutEditor {
+    open: bool,
+    drag: Option<Drag>,
+}
+
+impl LayoutEditor {
+    pub fn new() -> LayoutEditor {
+        LayoutEditor {
+            open: false,
+            drag: None,
+        }
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+        self.drag = None;
+    }
+
+    //While open, touches should not reach the game
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    //Returns true when the layout changed
+    pub fn update(&mut self, layout: &mut TouchLayout) -> bool {
+        if !self.open {
+            return false;
+        }
+        let screen = vec2(screen_width(), screen_height());
+        let (x, y) = mouse_position();
+        let pointer = vec2(x, y);
+
+        if is_mouse_button_pressed(MouseButton::Left) {
+            let tool = Tool::all()
+                .iter()
+                .enumerate()
+                .find(|(i, _)| Tool::rect(*i).contains(pointer))
+                .map(|(_, tool)| *tool);
+            match tool {
+                Some(tool) => return self.use_tool(tool, layout),
+                None => self.drag = grab(layout, pointer, screen),
+            }
+        }
+        if !is_mouse_button_down(MouseButton::Left) {
+            self.drag = None;
+            return false;
+        }
+
+        let left_handed = layout.left_handed;
+        match self.drag {
+            Some(Drag::Move(action, grabbed)) => match layout.element_mut(action) {
+                Some(element) => {
+                    element.move_to(pointer - grabbed, screen, left_handed);
+                    true
+                }
+                None => false,
+            },
+            Some(Drag::Resize(action)) => match layout.element_mut(action) {
+                Some(element) => {
+                    let center = element.center(screen, left_handed);
+                    let size = (pointer - center).abs().max_element() * 2. / unit(screen);
+                    element.size = size.clamp(MIN_SIZE, MAX_SIZE);
+                    true
+                }
+                None => false,
+            },
+            None => false,
+        }
+    }
+
+    fn use_tool(&mut self, tool: Tool, layout: &mut TouchLayout) -> bool {
+        match tool {
+            Tool::Done => {
+                self.open = false;
+                return false;
+            }
+            Tool::Mirror => layout.left_handed = !layout.left_handed,
+            Tool::Fainter => layout.opacity = (layout.opacity - OPACITY_STEP).max(MIN_OPACITY),
+            Tool::Stronger => layout.opacity = (layout.opacity + OPACITY_STEP).min(1.),
+            Tool::Reset => *layout = TouchLayout::default(),
+            Tool::Toggle(action) => match layout.element_mut(action) {
+                Some(element) => element.enabled = !element.enabled,
+                None => (),
+            },
+        }
+        true
+    }
+
+    pub fn render(&self, layout: &TouchLayout) {
+        if !self.open {
+            return;
+        }
+        let screen = vec2(screen_width(), screen_height());
+        draw_rectangle(0., 0., screen.x, screen.y, Color::new(1., 1., 1., 0.3));
+        for element in layout.elements() {
+            let center = element.center(screen, layout.left_handed);
+            let size = element.size(screen);
+            let color = Color::new(0.37, 0.5, 0.56, layout.opacity);
+            match element.action {
+                TouchAction::Chat => draw_rectangle(
+                    center.x - size / 2.,
+                    center.y - size * 0.3,
+                    size,
+                    size * 0.6,
+                    color,
+                ),
+                _ => draw_circle(center.x, center.y, size / 2., color),
+            }
+            draw_rectangle_lines(
+                center.x - size / 2.,
+                center.y - size / 2.,
+                size,
+                size,
+                2.,
+                BLACK,
+            );
+            let handle = handle_rect(center, size);
+            draw_rectangle(handle.x, handle.y, handle.w, handle.h, BLACK);
+            draw_centered(element.action.label(), center);
+        }
+        for (i, tool) in Tool::all().iter().enumerate() {
+            let rect = Tool::rect(i);
+            draw_rectangle(rect.x, rect.y, rect.w, rect.h, Color::new(0., 0., 0., 0.6));
+            draw_centered(&tool.label(layout), rect.center());
+        }
+    }
+}
+
+fn handle_rect(center: Vec2, size: f32) -> Rect {
+    Rect::new(
+        center.x + size / 2. - HANDLE_SIZE / 2.,
+        center.y + size / 2. - HANDLE_SIZE / 2.,
+        HANDLE_SIZE,
+        HANDLE_SIZE,
+    )
+}
+
+//Handles are checked first so small elements can still be resized
+fn grab(layout: &TouchLayout, pointer: Vec2, screen: Vec2) -> Option<Drag> {
+    let boxes: Vec<(TouchAction, Vec2, f32)> = layout
+        .elements()
+        .map(|element| {
+            let center = element.center(screen, layout.left_handed);
+            (element.action, center, element.size(screen))
+        })
+        .collect();
+    let resize = boxes
+        .iter()
+        .find(|(_, center, size)| handle_rect(*center, *size).contains(pointer))
+        .map(|(action, _, _)| Drag::Resize(*action));
+    resize.or_else(|| {
+        boxes
+            .iter()
+            .find(|(_, center, size)| (pointer - *center).abs().max_element() < size / 2.)
+            .map(|(action, center, _)| Drag::Move(*action, pointer - *center))
+    })
+}
+
+fn draw_centered(label: &str, center: Vec2) {
+    let size = measure_text(label, None, 24, 1.);
+    draw_text(
+        label,
+        center.x - size.width / 2.,
+        center.y + size.height / 2.,
+        24.,
+        BLACK,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCREEN: Vec2 = Vec2::from_array([1600., 1000.]);
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        a.distance(b) < 0.001
+    }
+
+    #[test]
+    fn layouts_are_read_back_as_saved() {
+        let mut layout = TouchLayout::default();
+        layout.left_handed = true;
+        layout.opacity = 0.8;
+        layout.elements[3].enabled = true;
+        layout.elements[0].size = 0.3;
+        assert_eq!(TouchLayout::from_json(&layout.to_json()), Ok(layout));
+        let default = TouchLayout::default();
+        assert_eq!(TouchLayout::from_json(&default.to_json()), Ok(default));
+    }
+
+    #[test]
+    fn sizes_and_opacity_are_clamped() {
+        let mut layout = TouchLayout::default();
+        layout.opacity = -0.5;
+        layout.elements[0].size = 5.;
+        layout.elements[1].size = -1.;
+        let read = TouchLayout::from_json(&layout.to_json()).unwrap();
+        assert_eq!(read.opacity, MIN_OPACITY);
+        assert_eq!(read.elements[0].size, MAX_SIZE);
+        assert_eq!(read.elements[1].size, MIN_SIZE);
+
+        layout.opacity = 3.;
+        assert_eq!(
+            TouchLayout::from_json(&layout.to_json()).unwrap().opacity,
+            1.
+        );
+    }
+
+    #[test]
+    fn broken_layouts_are_refused() {
+        let mut twice = TouchLayout::default();
+        let copy = twice.elements[1].clone();
+        twice.elements.push(copy);
+        assert!(TouchLayout::from_json(&twice.to_json()).is_err());
+
+        let mut old = TouchLayout::default();
+        old.version = LAYOUT_VERSION + 1;
+        assert!(TouchLayout::from_json(&old.to_json()).is_err());
+
+        //Too big for an f32, read as infinity
+        let huge = TouchLayout::default()
+            .to_json()
+            .replacen("\"size\":0.4", "\"size\":1e300", 1);
+        assert!(huge.contains("1e300"));
+        assert!(TouchLayout::from_json(&huge).is_err());
+
+        assert!(TouchLayout::from_json("{}").is_err());
+    }
+
+    #[test]
+    fn left_handed_layouts_are_mirrored() {
+        let layout = TouchLayout::default();
+        for element in layout.elements.iter() {
+            let right = element.center(SCREEN, false);
+            let left = element.center(SCREEN, true);
+            assert!(
+                close(left, vec2(SCREEN.x - right.x, right.y)),
+                "{:?}",
+                element
+            );
+        }
+    }
+
+    #[test]
+    fn moved_elements_end_up_where_they_were_dropped() {
+        for left_handed in [false, true] {
+            let mut element = TouchLayout::default().elements[0].clone();
+            for target in [vec2(100., 100.), vec2(1500., 200.), vec2(300., 900.)] {
+                element.move_to(target, SCREEN, left_handed);
+                assert!(close(element.center(SCREEN, left_handed), target));
+                //Flipping hands afterwards mirrors the drop point
+                let mirrored = vec2(SCREEN.x - target.x, target.y);
+                assert!(close(element.center(SCREEN, !left_handed), mirrored));
+            }
+        }
+    }
+
+    #[test]
+    fn moved_elements_keep_to_their_corner_when_the_screen_changes() {
+        let mut element = TouchLayout::default().elements[0].clone();
+        element.move_to(vec2(1500., 900.), SCREEN, false);
+        assert_eq!(element.anchor, Anchor::BottomRight);
+        let wider = SCREEN + vec2(400., 0.);
+        assert!(close(element.center(wider, false), vec2(1900., 900.)));
+    }
+}
@@ -3,14 +3,14 @@ use sapp_jsutils::JsObject;
 use std::{collections::VecDeque, sync::Mutex};
 
 use crate::multiplayer::Event;
+use crate::multiplayer::protocol;
 use crate::multiplayer::MultiplayerHandler;
-use crate::player::BlockingAction;
-use crate::world::entity::Direction;
 
 #[derive(Debug, Clone, Copy)]
 struct ThisPlayerData {
     pos: Vec2,
     v: Vec2,
+    seq: u32,
 }
 
 impl ThisPlayerData {
@@ -18,6 +18,7 @@ impl ThisPlayerData {
         ThisPlayerData {
             pos: vec2(0., 0.),
             v: vec2(0., 0.),
+            seq: 0,
         }
     }
 }
@@ -26,6 +27,15 @@ lazy_static! {
     static ref EVENT_DOWNSTREAM: Mutex<VecDeque<Event>> = Mutex::new(VecDeque::new());
     static ref EVENT_UPSTREAM: Mutex<VecDeque<Event>> = Mutex::new(VecDeque::new());
     static ref THIS_PLAYER: Mutex<ThisPlayerData> = Mutex::new(ThisPlayerData::new());
+    static ref WORLD_SEED: Mutex<Option<u32>> = Mutex::new(None);
+}
+
+//Uses the seed set by the host if any, otherwise falls back and remembers it so the host can read it back
+pub fn world_seed(fallback_seed: u32) -> u32 {
+    let mut seed = WORLD_SEED.lock().unwrap();
+    let world_seed = seed.unwrap_or(fallback_seed);
+    *seed = Some(world_seed);
+    world_seed
 }
 
 pub struct WasmEventHandler {}
@@ -37,9 +47,10 @@ impl MultiplayerHandler for WasmEventHandler {
         return events;
     }
 
-    fn set_your_player_pos(&self, pos: Vec2, v: Vec2) {
+    fn set_your_player_pos(&mut self, pos: Vec2, v: Vec2, seq: u32, _time: f64) {
         THIS_PLAYER.lock().unwrap().pos = pos;
         THIS_PLAYER.lock().unwrap().v = v;
+        THIS_PLAYER.lock().unwrap().seq = seq;
     }
 
     fn upstream_event(&mut self, event: Event) {
@@ -50,12 +61,22 @@ impl MultiplayerHandler for WasmEventHandler {
                 y,
                 direction,
                 action,
+                ..
             } => match action {
                 crate::player::BlockingAction::Attack | crate::player::BlockingAction::Roll | crate::player::BlockingAction::Dying  => {
                     EVENT_UPSTREAM.lock().unwrap().push_back(event);
                 }
                 _ => (),
             },
+            Event::TileChanged { .. }
+            | Event::PlayerHit { .. }
+            | Event::ChunkRequest { .. }
+            | Event::Join { .. }
+            | Event::Leave
+            | Event::Chat { .. }
+            | Event::Emote { .. } => {
+                EVENT_UPSTREAM.lock().unwrap().push_back(event);
+            }
             _ => (),
         }
     }
@@ -63,46 +84,22 @@ impl MultiplayerHandler for WasmEventHandler {
     fn downstream_event(&mut self, event: Event) {
         EVENT_DOWNSTREAM.lock().unwrap().push_back(event);
     }
+
+    //The page only relays events between browsers
+    fn is_authoritative(&self) -> bool {
+        false
+    }
 }
 
+//The next queued event as a JSON packet (see multiplayer::protocol), an empty string when there is none
 #[no_mangle]
 pub extern "C" fn get_upstream_event() -> JsObject {
-    let js_object = JsObject::object();
-    let event = EVENT_UPSTREAM.lock().unwrap().pop_front();
-    match event {
-        Some(event) => match event {
-            Event::PlayerAction {
-                id,
-                x,
-                y,
-                direction,
-                action,
-            } => {
-                debug!("Upstream {:?}", event);
-
-                let action = match action {
-                    BlockingAction::Attack => 1.,
-                    BlockingAction::Roll => 2.,
-                    BlockingAction::Block => 3.,
-                    BlockingAction::Dying => 4.,
-                };
-
-                let direction = match direction {
-                    crate::world::entity::Direction::Up => 8,
-                    crate::world::entity::Direction::Down => 2,
-                    crate::world::entity::Direction::Left => 4,
-                    crate::world::entity::Direction::Right => 6,
-                };
-
-                js_object.set_field_f32("action", action);
-                js_object.set_field_f32("direction", direction as f32);
-                js_object.set_field_f32("x", x);
-                js_object.set_field_f32("y", y);
-                js_object
-            }
-            _ => js_object,
-        },
-        None => js_object,
+    match EVENT_UPSTREAM.lock().unwrap().pop_front() {
+        Some(event) => {
+            debug!("Upstream {:?}", event);
+            JsObject::string(&protocol::to_json(&event))
+        }
+        None => JsObject::string(""),
     }
 }
 
@@ -114,6 +111,7 @@ pub extern "C" fn get_player_update() -> JsObject {
     js_object.set_field_f32("y", player.pos.y);
     js_object.set_field_f32("vx", player.v.x);
     js_object.set_field_f32("vy", player.v.y);
+    js_object.set_field_u32("seq", player.seq);
     js_object
 }
 
@@ -129,6 +127,8 @@ pub extern "C" fn update_player(js_object: JsObject, id: u32, x: f32, y: f32, vx
         y,
         vx,
         vy,
+        seq: 0,
+        time: 0.,
     };
     EVENT_DOWNSTREAM.lock().unwrap().push_back(event);
 }
@@ -142,40 +142,29 @@ pub extern "C" fn teleport(x: f32, y:f32) {
     EVENT_DOWNSTREAM.lock().unwrap().push_back(event);
 }
 
+//Takes any event as a JSON packet, packets that do not decode are logged and dropped
 #[no_mangle]
-pub extern "C" fn downstream_player_action(id: u32, x: f32, y: f32, action: f32, direction: f32) {
-    let mut d = Direction::Up;
-    let mut a = BlockingAction::Attack;
-
-    if direction == 8. {
-        d = Direction::Up;
-    } else if direction == 2. {
-        d = Direction::Down;
-    } else if direction == 4. {
-        d = Direction::Left;
-    } else if direction == 6. {
-        d = Direction::Right;
-    }
-
-    if action == 1. {
-        a = BlockingAction::Attack;
-    } else if action == 2. {
-        a = BlockingAction::Roll;
-    } else if action == 4. {
-        a = BlockingAction::Dying;
+pub extern "C" fn downstream_event(js_object: JsObject) {
+    let mut json = String::new();
+    js_object.to_string(&mut json);
+
+    match protocol::from_json(&json) {
+        Ok(event) => {
+            debug!("Downstream {:?}", event);
+            EVENT_DOWNSTREAM.lock().unwrap().push_back(event);
+        }
+        Err(err) => error!("Dropping downstream event: {}", err),
     }
+}
 
-    let event = Event::PlayerAction {
-        id,
-        x,
-        y,
-        direction: d,
-        action: a,
-    };
-
-    debug!("Downstream {:?}", event);
+#[no_mangle]
+pub extern "C" fn set_world_seed(seed: u32) {
+    *WORLD_SEED.lock().unwrap() = Some(seed);
+}
 
-    EVENT_DOWNSTREAM.lock().unwrap().push_back(event);
+#[no_mangle]
+pub extern "C" fn get_world_seed() -> u32 {
+    WORLD_SEED.lock().unwrap().unwrap_or(0)
 }
 
 #[no_mangle]
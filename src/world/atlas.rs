@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use macroquad::prelude::*;
+
+use crate::player::animation::{PixelPos, Spritesheet};
+
+use super::tile::Tile;
+
+//Pixels per tile in a baked chunk when the atlas is loaded
+pub const TILE_PIXELS: u32 = 16;
+
+//Neighbours in the order of the mask bits, starting north and going clockwise
+pub const NEIGHBOURS: [(i32, i32); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+
+//Frames are looked up as "<texture> 8-<mask>.png", then "<texture> 4-<mask>.png" and
+//last "<texture>.png" (texture as in TileTexture::name), tiles without any frame keep their colour
+pub struct TileAtlas {
+    image: Image,
+    frames: HashMap<String, PixelPos>,
+}
+
+//None, after saying why, when the atlas is missing or broken, chunks are then drawn in colour
+pub async fn load_tile_atlas() -> Option<TileAtlas> {
+    let image = match load_image("textures/tiles_atlas.png").await {
+        Ok(image) => image,
+        Err(err) => {
+            warn!("No tile atlas, textures/tiles_atlas.png: {}", err);
+            return None;
+        }
+    };
+    let spritesheet = match load_string("textures/tiles_atlas.json").await {
+        Ok(spritesheet) => spritesheet,
+        Err(err) => {
+            warn!("No tile atlas, textures/tiles_atlas.json: {}", err);
+            return None;
+        }
+    };
+    match TileAtlas::new(image, &spritesheet) {
+        Ok(atlas) => Some(atlas),
+        Err(err) => {
+            error!("Broken tile atlas, textures/tiles_atlas.json: {}", err);
+            None
+        }
+    }
+}
+
+impl TileAtlas {
+    //Every frame has to lie inside the image, painting reads its pixels without checking
+    pub fn new(image: Image, spritesheet: &str) -> Result<TileAtlas, String> {
+        let spritesheet: Spritesheet =
+            serde_json::from_str(spritesheet).map_err(|err| err.to_string())?;
+        let (width, height) = (image.width() as f32, image.height() as f32);
+        for (name, frame) in spritesheet.frames.iter() {
+            let pos = frame.pos;
+            if pos.x < 0. || pos.y < 0. || pos.x + pos.w > width || pos.y + pos.h > height {
+                return Err(format!(
+                    "frame {} at {},{} {}x{} is outside the {}x{} image",
+                    name, pos.x, pos.y, pos.w, pos.h, width, height
+                ));
+            }
+        }
+        let frames = spritesheet
+            .frames
+            .into_iter()
+            .map(|(name, frame)| (name, frame.pos))
+            .collect();
+        Ok(TileAtlas { image, frames })
+    }
+
+    fn frame(&self, tile: &Tile, neighbours: &[bool; 8]) -> Option<&PixelPos> {
+        let name = tile.texture.name();
+        self.frames
+            .get(&format!("{} 8-{}.png", name, mask8(neighbours)))
+            .or_else(|| {
+                self.frames
+                    .get(&format!("{} 4-{}.png", name, mask4(neighbours)))
+            })
+            .or_else(|| self.frames.get(&format!("{}.png", name)))
+    }
+
+    //Draws the tile into a TILE_PIXELS square of the image with its top left at (x, y)
+    pub fn paint(&self, image: &mut Image, x: u32, y: u32, tile: &Tile, neighbours: &[bool; 8]) {
+        match self.frame(tile, neighbours) {
+            Some(frame) => {
+                for px in 0..TILE_PIXELS {
+                    for py in 0..TILE_PIXELS {
+                        let source_x = frame.x + px as f32 * frame.w / TILE_PIXELS as f32;
+                        let source_y = frame.y + py as f32 * frame.h / TILE_PIXELS as f32;
+                        let color = self.image.get_pixel(source_x as u32, source_y as u32);
+                        image.set_pixel(x + px, y + py, color);
+                    }
+                }
+            }
+            None => paint_color(image, x, y, TILE_PIXELS, tile.texture.to_color()),
+        }
+    }
+}
+
+pub fn paint_color(image: &mut Image, x: u32, y: u32, size: u32, color: Color) {
+    for px in 0..size {
+        for py in 0..size {
+            image.set_pixel(x + px, y + py, color);
+        }
+    }
+}
+
+//North, east, south and west as bits 1, 2, 4 and 8, set when the neighbour is the same terrain
+pub fn mask4(neighbours: &[bool; 8]) -> u8 {
+    let mut mask = 0;
+    for (bit, side) in [0, 2, 4, 6].iter().enumerate() {
+        if neighbours[*side] {
+            mask |= 1 << bit;
+        }
+    }
+    mask
+}
+
+//All eight neighbours in NEIGHBOURS order, a corner only counts when both sides next to it do,
+//which leaves the 47 combinations a blob tileset has frames for
+pub fn mask8(neighbours: &[bool; 8]) -> u8 {
+    let mut mask = 0;
+    for (bit, joined) in neighbours.iter().enumerate() {
+        let counts = if bit % 2 == 0 {
+            *joined
+        } else {
+            *joined && neighbours[bit - 1] && neighbours[(bit + 1) % 8]
+        };
+        if counts {
+            mask |= 1 << bit;
+        }
+    }
+    mask
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+    use crate::world::tile::TileTexture;
+
+    fn shipped_image() -> Image {
+        Image::from_file_with_format(include_bytes!("../../textures/tiles_atlas.png"), None)
+    }
+
+    #[test]
+    fn shipped_atlas_has_a_frame_for_every_texture() {
+        let atlas = TileAtlas::new(
+            shipped_image(),
+            include_str!("../../textures/tiles_atlas.json"),
+        )
+        .unwrap();
+        for texture in TileTexture::iter() {
+            let tile = Tile {
+                texture,
+                ..Tile::default()
+            };
+            assert!(atlas.frame(&tile, &[true; 8]).is_some(), "{:?}", texture);
+            assert!(atlas.frame(&tile, &[false; 8]).is_some(), "{:?}", texture);
+        }
+    }
+
+    #[test]
+    fn frames_outside_the_image_are_refused() {
+        let spritesheet =
+            r#"{"frames": {"grass.png": {"frame": {"x": 300, "y": 0, "w": 16, "h": 16}}}}"#;
+        let image = Image::gen_image_color(306, 234, WHITE);
+        assert!(TileAtlas::new(image, spritesheet).is_err());
+    }
+}
@@ -0,0 +1,57 @@
+use strum_macros::EnumIter;
+
+use super::tile::{Tile, TileTable, TileTexture};
+
+//Climate values are noise scaled to 0..1, most of the world lands between 0.3 and 0.7
+const COLD: f64 = 0.36;
+const HOT: f64 = 0.6;
+const DRY: f64 = 0.45;
+const WET: f64 = 0.55;
+const SOAKED: f64 = 0.62;
+
+#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy, EnumIter)]
+pub enum Biome {
+    Plains,
+    Beach,
+    Desert,
+    Forest,
+    Swamp,
+    Tundra,
+}
+
+impl Biome {
+    //Elevation decides the base tile, moisture and temperature only matter on land
+    pub fn classify(base: &Tile, moisture: f64, temperature: f64) -> Biome {
+        match base.texture {
+            TileTexture::Sand | TileTexture::Grass => {
+                if temperature < COLD {
+                    Biome::Tundra
+                } else if temperature > HOT && moisture < DRY {
+                    Biome::Desert
+                } else if base.texture == TileTexture::Sand {
+                    Biome::Beach
+                } else if moisture > SOAKED && temperature > DRY {
+                    Biome::Swamp
+                } else if moisture > WET {
+                    Biome::Forest
+                } else {
+                    Biome::Plains
+                }
+            }
+            _ => Biome::Plains,
+        }
+    }
+
+    //How the biome's tile behaves comes from the tile table, so it can be tuned with the rest
+    pub fn apply(&self, base: Tile, table: &TileTable) -> Tile {
+        let texture = match (self, base.texture) {
+            (Biome::Beach, TileTexture::Sand) => TileTexture::Beach,
+            (Biome::Desert, TileTexture::Sand | TileTexture::Grass) => TileTexture::Desert,
+            (Biome::Tundra, TileTexture::Sand | TileTexture::Grass) => TileTexture::Tundra,
+            (Biome::Forest, TileTexture::Grass) => TileTexture::Forest,
+            (Biome::Swamp, TileTexture::Grass) => TileTexture::Swamp,
+            _ => return base,
+        };
+        table.biome_tile(texture, base)
+    }
+}
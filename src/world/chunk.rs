@@ -1,12 +1,22 @@
 use macroquad::prelude::*;
-use noise::{OpenSimplex, Fbm, NoiseFn};
 
-use super::{tile::Tile, positions::{ChunkPosition, Coords}, CHUNK_SIZE};
+use super::{tile::Tile, positions::{ChunkPosition, Coords}, generation::Generator, CHUNK_SIZE};
 
 #[derive(Debug)]
 pub struct Chunk {
     pub tiles: [[Tile; CHUNK_SIZE as usize]; CHUNK_SIZE as usize],
     pub pos: ChunkPosition,
+    //Set when a tile no longer matches what was generated
+    pub modified: bool,
+    //The tiles that no longer match, `modified` is set while any of them do
+    pub changed: [[bool; CHUNK_SIZE as usize]; CHUNK_SIZE as usize],
+    //World tick of the last frame the chunk was inside the render zone
+    pub last_used: u64,
+    //Bumped on every tile change, in multiplayer the server's count is the one that holds
+    pub revision: u32,
+    //Baked by the world on first render and thrown away whenever a tile changes
+    texture: Option<Texture2D>,
+    lazy_color: Option<Color>,
 }
 
 impl Chunk {
@@ -22,34 +32,114 @@ impl Chunk {
         );
     }
 
+    //`generated` is what the seed puts there, a tile set back to it is no longer a change
+    pub fn set_tile(&mut self, (x, y): (usize, usize), tile: Tile, generated: Tile) {
+        self.tiles[x][y] = tile;
+        self.mark_changed((x, y), tile != generated);
+        self.revision += 1;
+        self.invalidate();
+    }
+
+    pub fn mark_changed(&mut self, (x, y): (usize, usize), changed: bool) {
+        self.changed[x][y] = changed;
+        self.modified = self.changed.iter().flatten().any(|changed| *changed);
+    }
+
+    pub fn invalidate(&mut self) {
+        if let Some(texture) = self.texture.take() {
+            texture.delete();
+        }
+        self.lazy_color = None;
+    }
+
+    pub fn needs_texture(&self) -> bool {
+        self.texture.is_none()
+    }
+
+    pub fn set_texture(&mut self, texture: Texture2D) {
+        self.invalidate();
+        self.texture = Some(texture);
+    }
+
     pub fn render(&self) {
         let coords = Coords::from_position(&self.pos);
-
-        for x in 0..CHUNK_SIZE {
-            for y in 0..CHUNK_SIZE {
-                let tile = &self.tiles[x as usize][y as usize];
-                let color = tile.texture.to_color();
-                draw_rectangle((coords.x + x) as f32, (coords.y + y) as f32, 1., 1., color);
+        match self.texture {
+            Some(texture) => draw_texture_ex(
+                texture,
+                coords.x as f32,
+                coords.y as f32,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(vec2(CHUNK_SIZE as f32, CHUNK_SIZE as f32)),
+                    ..Default::default()
+                },
+            ),
+            None => {
+                for x in 0..CHUNK_SIZE {
+                    for y in 0..CHUNK_SIZE {
+                        let tile = &self.tiles[x as usize][y as usize];
+                        let color = tile.texture.to_color();
+                        draw_rectangle((coords.x + x) as f32, (coords.y + y) as f32, 1., 1., color);
+                    }
+                }
             }
         }
     }
 
-    pub fn render_lazy(&self) {}
+    //Whole chunk as the colour of its most common tile, for when tiles are only a pixel or two
+    pub fn render_lazy(&mut self) {
+        let coords = Coords::from_position(&self.pos);
+        let color = match self.lazy_color {
+            Some(color) => color,
+            None => {
+                let color = self.most_common_tile().texture.to_color();
+                self.lazy_color = Some(color);
+                color
+            }
+        };
+        draw_rectangle(
+            coords.x as f32,
+            coords.y as f32,
+            CHUNK_SIZE as f32,
+            CHUNK_SIZE as f32,
+            color,
+        );
+    }
+
+    fn most_common_tile(&self) -> Tile {
+        let mut counts: Vec<(Tile, u32)> = Vec::new();
+        for tile in self.tiles.iter().flatten() {
+            match counts.iter_mut().find(|(known, _)| known == tile) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((*tile, 1)),
+            }
+        }
+        counts
+            .into_iter()
+            .max_by_key(|(_, count)| *count)
+            .map(|(tile, _)| tile)
+            .unwrap_or_default()
+    }
 
-    pub fn generate(chunk_pos: ChunkPosition, noise: &Fbm<OpenSimplex>) -> Chunk {
+    pub fn generate(chunk_pos: ChunkPosition, generator: &Generator) -> Chunk {
         let mut tiles: [[Tile; CHUNK_SIZE as usize]; CHUNK_SIZE as usize] = Default::default();
 
         for x in 0..CHUNK_SIZE {
             for y in 0..CHUNK_SIZE {
                 let pos = Coords::from_position_at(&chunk_pos, (x, y)).to_vec2();
-                let n = (noise.get([(pos.x) as f64, (pos.y) as f64]) + 1.) * 0.5;
-                tiles[x as usize][y as usize] = Tile::generate(n);
+                tiles[x as usize][y as usize] = generator.tile_at(pos);
             }
         }
 
         return Chunk {
             tiles: tiles,
             pos: chunk_pos,
+            modified: false,
+            changed: Default::default(),
+            last_used: 0,
+            revision: 0,
+            texture: None,
+            lazy_color: None,
         };
     }
 
@@ -65,16 +155,21 @@ impl Chunk {
     }
 }
 
+impl Drop for Chunk {
+    fn drop(&mut self) {
+        self.invalidate();
+    }
+}
+
 pub struct LazyChunk {
     tile: Tile,
     pos: ChunkPosition,
 }
 
 impl LazyChunk {
-    pub fn generate(chunk_pos: ChunkPosition, noise: &Fbm<OpenSimplex>) -> LazyChunk {
+    pub fn generate(chunk_pos: ChunkPosition, generator: &Generator) -> LazyChunk {
         let pos = Coords::from_position_at(&chunk_pos, (0, 0)).to_vec2();
-        let n = (noise.get([(pos.x) as f64, (pos.y) as f64]) + 1.) * 0.5;
-        let tile = Tile::generate(n);
+        let tile = generator.tile_at(pos);
         return LazyChunk {
             tile: tile,
             pos: chunk_pos,
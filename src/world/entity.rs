@@ -1,4 +1,5 @@
 use macroquad::prelude::*;
+use serde_derive::{Deserialize, Serialize};
 use strum_macros::EnumIter;
 use super::tile::*;
 
@@ -12,7 +13,7 @@ pub trait WorldEntity {
     fn update(&mut self, tile_interaction: &TileInteraction, tile_action: &TileAction, time: f32);
 }
 
-#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy, EnumIter)]
+#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy, EnumIter, Deserialize, Serialize)]
 pub enum Direction {
     Up,
     Down,
@@ -26,7 +27,7 @@ pub enum EntityWorldEvent {
     None,
 }
 
-#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy, EnumIter)]
+#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy, EnumIter, Deserialize, Serialize)]
 pub enum WorldResource {
     Dirt
 }
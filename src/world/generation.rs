@@ -0,0 +1,136 @@
+use macroquad::prelude::*;
+use noise::{Fbm, MultiFractal, NoiseFn, OpenSimplex};
+use serde_derive::{Deserialize, Serialize};
+
+use super::{
+    biome::Biome,
+    tile::{Tile, TileTable},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct NoiseConfig {
+    pub frequency: f64,
+    pub persistence: f64,
+    pub lacunarity: f64,
+    pub octaves: usize,
+}
+
+impl NoiseConfig {
+    pub fn build(&self, seed: u32) -> Fbm<OpenSimplex> {
+        Fbm::<OpenSimplex>::new(seed)
+            .set_frequency(self.frequency)
+            .set_persistence(self.persistence)
+            .set_lacunarity(self.lacunarity)
+            .set_octaves(self.octaves)
+    }
+}
+
+impl NoiseConfig {
+    //Climate changes slower than the terrain so biomes span several islands
+    pub fn climate() -> NoiseConfig {
+        NoiseConfig {
+            frequency: 0.004,
+            persistence: 0.5,
+            lacunarity: 2.,
+            octaves: 3,
+        }
+    }
+}
+
+impl Default for NoiseConfig {
+    fn default() -> Self {
+        NoiseConfig {
+            frequency: 0.01,
+            persistence: 0.6,
+            lacunarity: 2.,
+            octaves: 5,
+        }
+    }
+}
+
+//Everything needed to reproduce a world, the seed is enough if the rest is left at default
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct WorldGenConfig {
+    pub seed: u32,
+    pub elevation: NoiseConfig,
+    pub moisture: NoiseConfig,
+    pub temperature: NoiseConfig,
+    //Chunks generated in every direction from the origin before the game starts
+    pub pregenerate_radius: i32,
+    pub tiles: TileTable,
+}
+
+impl WorldGenConfig {
+    pub fn with_seed(seed: u32) -> WorldGenConfig {
+        WorldGenConfig {
+            seed,
+            ..Default::default()
+        }
+    }
+
+    //Reads `--seed <u32>` from the command line
+    pub fn seed_from_args(args: impl Iterator<Item = String>) -> Option<u32> {
+        let mut args = args.skip_while(|arg| arg != "--seed").skip(1);
+        match args.next() {
+            Some(seed) => seed.parse::<u32>().ok(),
+            None => None,
+        }
+    }
+}
+
+impl Default for WorldGenConfig {
+    fn default() -> Self {
+        WorldGenConfig {
+            seed: 0,
+            elevation: NoiseConfig::default(),
+            moisture: NoiseConfig::climate(),
+            temperature: NoiseConfig::climate(),
+            pregenerate_radius: 10,
+            tiles: TileTable::default(),
+        }
+    }
+}
+
+//Offsets the seed of each field, Fbm seeds its octaves with seed + octave so these have to be far apart
+const MOISTURE_SEED_OFFSET: u32 = 1_000_003;
+const TEMPERATURE_SEED_OFFSET: u32 = 2_000_029;
+
+pub struct Generator {
+    config: WorldGenConfig,
+    elevation: Fbm<OpenSimplex>,
+    moisture: Fbm<OpenSimplex>,
+    temperature: Fbm<OpenSimplex>,
+}
+
+impl Generator {
+    pub fn new(config: WorldGenConfig) -> Generator {
+        Generator {
+            elevation: config.elevation.build(config.seed),
+            moisture: config
+                .moisture
+                .build(config.seed.wrapping_add(MOISTURE_SEED_OFFSET)),
+            temperature: config
+                .temperature
+                .build(config.seed.wrapping_add(TEMPERATURE_SEED_OFFSET)),
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &WorldGenConfig {
+        &self.config
+    }
+
+    pub fn tile_at(&self, pos: Vec2) -> Tile {
+        let base = Tile::generate(sample(&self.elevation, pos), &self.config.tiles);
+        let biome = Biome::classify(
+            &base,
+            sample(&self.moisture, pos),
+            sample(&self.temperature, pos),
+        );
+        biome.apply(base, &self.config.tiles)
+    }
+}
+
+fn sample(noise: &Fbm<OpenSimplex>, pos: Vec2) -> f64 {
+    (noise.get([(pos.x) as f64, (pos.y) as f64]) + 1.) * 0.5
+}
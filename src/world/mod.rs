@@ -1,61 +1,242 @@
 use std::collections::HashMap;
 
 use macroquad::prelude::*;
-use noise::{Fbm, MultiFractal, OpenSimplex};
 
-use self::{chunk::*, entity::*, positions::*, tile::*};
+use self::{
+    atlas::*, chunk::*, entity::*, generation::*, positions::*, residency::*, save::*, shapes::*,
+    tile::*,
+};
 
+pub mod atlas;
+pub mod biome;
 pub mod chunk;
 pub mod entity;
+pub mod generation;
 pub mod positions;
+pub mod residency;
+pub mod save;
+pub mod shapes;
 pub mod tile;
 
 const CHUNK_SIZE: i32 = 8;
+//Tiles an attack digs out, a half circle in front of whoever swung
+const DIG_AREA: Shape = Shape::Cone {
+    length: 3.,
+    angle: std::f32::consts::PI,
+};
+//Below this many pixels per tile each chunk is drawn as a single colour
+const LAZY_RENDER_PIXELS_PER_TILE: f32 = 1.5;
 
 pub struct World {
     chunks: HashMap<ChunkPosition, Chunk>,
     map_chunks: HashMap<ChunkPosition, LazyChunk>,
-    noise: Fbm<OpenSimplex>,
+    generator: Generator,
+    unsaved_changes: bool,
+    residency: ResidencyPolicy,
+    //Modified chunks evicted from memory, restored when they are generated again
+    archived: HashMap<ChunkPosition, ChunkSave>,
+    discovered: DiscoveredChunks,
+    tick: u64,
+    evicted: usize,
+    atlas: Option<TileAtlas>,
+    //Local edits and chunks generated since they were last taken, for multiplayer to send on
+    tile_changes: Vec<TileChange>,
+    new_chunks: Vec<(ChunkPosition, u32)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileChange {
+    pub coords: Coords,
+    pub tile: Tile,
+    //Revision of the chunk once the change is applied
+    pub revision: u32,
 }
 
 impl World {
-    pub fn generate() -> World {
-        let noise = Fbm::<OpenSimplex>::new(0)
-            .set_frequency(0.01)
-            .set_persistence(0.6)
-            .set_lacunarity(2.)
-            .set_octaves(5);
-
-        let x1 = -10;
-        let y1 = -10;
-        let x2 = 10;
-        let y2 = 10;
+    pub fn generate(config: WorldGenConfig) -> World {
+        let x1 = -config.pregenerate_radius;
+        let y1 = -config.pregenerate_radius;
+        let x2 = config.pregenerate_radius;
+        let y2 = config.pregenerate_radius;
+
+        let generator = Generator::new(config);
 
         let mut chunks: HashMap<ChunkPosition, Chunk> = HashMap::new();
         let mut map_chunks: HashMap<ChunkPosition, LazyChunk> = HashMap::new();
+        let mut discovered = DiscoveredChunks::new();
+        let mut new_chunks = Vec::new();
 
         for x in x1..x2 {
             for y in y1..y2 {
                 let pos = ChunkPosition { x, y };
-                let chunk = Chunk::generate(pos, &noise);
+                let chunk = Chunk::generate(pos, &generator);
                 chunks.insert(pos, chunk);
-                let map_chunk = LazyChunk::generate(pos, &noise);
+                discovered.insert(pos);
+                new_chunks.push((pos, 0));
+                let map_chunk = LazyChunk::generate(pos, &generator);
                 map_chunks.insert(pos, map_chunk);
             }
         }
         return World {
             chunks,
-            noise,
+            generator,
             map_chunks,
+            unsaved_changes: false,
+            residency: ResidencyPolicy::default(),
+            archived: HashMap::new(),
+            discovered,
+            tick: 0,
+            evicted: 0,
+            atlas: None,
+            tile_changes: Vec::new(),
+            new_chunks,
         };
     }
 
+    pub fn set_atlas(&mut self, atlas: Option<TileAtlas>) {
+        self.atlas = atlas;
+        for chunk in self.chunks.values_mut() {
+            chunk.invalidate();
+        }
+    }
+
+    pub fn set_residency_policy(&mut self, policy: ResidencyPolicy) {
+        self.residency = policy;
+    }
+
+    pub fn residency_stats(&self) -> ResidencyStats {
+        ResidencyStats {
+            chunks: self.chunks.len(),
+            map_chunks: self.map_chunks.len(),
+            modified: self.chunks.values().filter(|chunk| chunk.modified).count(),
+            archived: self.archived.len(),
+            evicted: self.evicted,
+            discovered: self.discovered.len(),
+        }
+    }
+
+    //Drops chunks far away from every player, least recently used first, until under budget
+    pub fn evict(&mut self, players: &[Vec2]) {
+        let player_chunks: Vec<ChunkPosition> = players
+            .iter()
+            .map(|pos| ChunkPosition::from_coords(&Coords::from_vec2(*pos)))
+            .collect();
+        let keep_distance = self.residency.keep_distance;
+        let is_far = |pos: &ChunkPosition| {
+            player_chunks.iter().all(|player| {
+                (player.x - pos.x).abs() > keep_distance || (player.y - pos.y).abs() > keep_distance
+            })
+        };
+
+        if self.chunks.len() > self.residency.max_chunks {
+            let target = ResidencyPolicy::low_water_mark(self.residency.max_chunks);
+            let mut candidates: Vec<(u64, ChunkPosition)> = self
+                .chunks
+                .values()
+                .filter(|chunk| is_far(&chunk.pos))
+                .filter(|chunk| {
+                    !chunk.modified || self.residency.modified == ModifiedChunks::Serialize
+                })
+                .map(|chunk| (chunk.last_used, chunk.pos))
+                .collect();
+            candidates.sort_by_key(|(key, _)| *key);
+            for (_, pos) in candidates {
+                if self.chunks.len() <= target {
+                    break;
+                }
+                if let Some(chunk) = self.chunks.remove(&pos) {
+                    if chunk.modified {
+                        self.archived.insert(pos, ChunkSave::from_chunk(&chunk));
+                    }
+                    self.evicted += 1;
+                }
+            }
+        }
+
+        if self.map_chunks.len() > self.residency.max_map_chunks {
+            let target = ResidencyPolicy::low_water_mark(self.residency.max_map_chunks);
+            let distance = |pos: &ChunkPosition| {
+                player_chunks
+                    .iter()
+                    .map(|player| (player.x - pos.x).abs().max((player.y - pos.y).abs()))
+                    .min()
+                    .unwrap_or(0)
+            };
+            let mut candidates: Vec<(i32, ChunkPosition)> = self
+                .map_chunks
+                .keys()
+                .filter(|pos| is_far(pos))
+                .map(|pos| (distance(pos), *pos))
+                .collect();
+            candidates.sort_by_key(|(key, _)| *key);
+            while self.map_chunks.len() > target {
+                match candidates.pop() {
+                    Some((_, pos)) => {
+                        self.map_chunks.remove(&pos);
+                    }
+                    None => break,
+                }
+            }
+        }
+    }
+
+    pub fn get_seed(&self) -> u32 {
+        self.generator.config().seed
+    }
+
+    //Starts over with another seed, the rest of the config, the atlas and residency are kept
+    pub fn reseed(&mut self, seed: u32) {
+        let mut config = self.generator.config().clone();
+        config.seed = seed;
+        let atlas = self.atlas.take();
+        let residency = self.residency;
+        *self = World::generate(config);
+        self.atlas = atlas;
+        self.residency = residency;
+    }
+
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.unsaved_changes
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.unsaved_changes = false;
+    }
+
+    pub fn to_save(&self) -> WorldSave {
+        let mut chunks: Vec<ChunkSave> = self.archived.values().cloned().collect();
+        for chunk in self.chunks.values() {
+            if chunk.modified {
+                chunks.push(ChunkSave::from_chunk(chunk));
+            }
+        }
+        WorldSave {
+            version: SAVE_VERSION,
+            seed: self.get_seed(),
+            chunks,
+        }
+    }
+
+    pub fn load_save(&mut self, save: &WorldSave) {
+        for chunk_save in save.chunks.iter() {
+            let pos = chunk_save.pos();
+            let chunk = self
+                .chunks
+                .entry(pos)
+                .or_insert_with(|| Chunk::generate(pos, &self.generator));
+            if chunk_save.apply(chunk).is_none() {
+                warn!("Skipping malformed saved chunk {:?}", pos);
+            }
+            self.discovered.insert(pos);
+        }
+    }
+
     pub fn render_map(&mut self, view: Rect) {
         for x in (view.x as i32)..(view.x + view.w) as i32 {
             for y in (view.y as i32)..(view.y + view.h) as i32 {
                 let pos = ChunkPosition { x, y };
-                match (self.map_chunks.get(&pos), self.chunks.get(&pos)) {
-                    (None, _) => {
+                match self.map_chunks.get(&pos) {
+                    None => {
                         draw_rectangle(
                             pos.x as f32,
                             pos.y as f32,
@@ -64,20 +245,40 @@ impl World {
                             color_u8!(100, 100, 100, 100),
                         );
                     }
-                    (Some(map), None) => map.render_small(false),
-                    (Some(map), Some(_)) => map.render_small(true),
+                    Some(map) => map.render_small(self.discovered.contains(&pos)),
                 }
             }
         }
     }
 
-    pub fn render(&self, rect: Rect) {
+    pub fn render(&mut self, rect: Rect) {
         let area = ChunkPosition::from_rect(rect);
+        let lazy = screen_width() / rect.w < LAZY_RENDER_PIXELS_PER_TILE;
+
+        if !lazy {
+            let mut unbaked = Vec::new();
+            for x in area.0.x..area.1.x {
+                for y in area.0.y..area.1.y {
+                    let pos = ChunkPosition { x, y };
+                    match self.chunks.get(&pos) {
+                        Some(chunk) if chunk.needs_texture() => unbaked.push(pos),
+                        _ => (),
+                    }
+                }
+            }
+            for pos in unbaked {
+                let texture = self.bake_chunk(&pos);
+                if let Some(chunk) = self.chunks.get_mut(&pos) {
+                    chunk.set_texture(texture);
+                }
+            }
+        }
 
         for x in area.0.x..area.1.x {
             for y in area.0.y..area.1.y {
                 let pos = ChunkPosition { x, y };
-                match self.chunks.get(&pos) {
+                match self.chunks.get_mut(&pos) {
+                    Some(chunk) if lazy => chunk.render_lazy(),
                     Some(chunk) => chunk.render(),
                     None => match self.map_chunks.get(&pos) {
                         Some(chunk) => chunk.render(),
@@ -88,13 +289,24 @@ impl World {
         }
     }
     pub fn generate_at(&mut self, render_zone: Rect, map_zone: Rect) {
+        self.tick += 1;
         let area = ChunkPosition::from_rect(render_zone);
         for x in area.0.x..area.1.x {
             for y in area.0.y..area.1.y {
                 let pos = ChunkPosition { x, y };
-                if !self.chunks.contains_key(&pos) {
-                    let chunk = Chunk::generate(pos, &self.noise);
-                    self.chunks.insert(pos, chunk);
+                match self.chunks.get_mut(&pos) {
+                    Some(chunk) => chunk.last_used = self.tick,
+                    None => {
+                        let mut chunk = Chunk::generate(pos, &self.generator);
+                        if let Some(chunk_save) = self.archived.remove(&pos) {
+                            chunk_save.apply(&mut chunk);
+                        }
+                        chunk.last_used = self.tick;
+                        self.new_chunks.push((pos, chunk.revision));
+                        self.chunks.insert(pos, chunk);
+                        self.discovered.insert(pos);
+                        self.invalidate_around(&Coords::from_position(&pos), CHUNK_SIZE);
+                    }
                 }
             }
         }
@@ -107,54 +319,230 @@ impl World {
             for y in area.0.y..area.1.y {
                 let pos = ChunkPosition { x, y };
                 if !self.map_chunks.contains_key(&pos) {
-                    let chunk = LazyChunk::generate(pos, &self.noise);
+                    let chunk = LazyChunk::generate(pos, &self.generator);
                     self.map_chunks.insert(pos, chunk);
                 }
             }
         }
     }
-    fn get_tile_mut(&mut self, coords: &Coords) -> Option<&mut Tile> {
+    pub fn get_tile(&self, coords: &Coords) -> Option<&Tile> {
         let chunk_pos = ChunkPosition::from_coords(coords);
         let index = (
             (coords.x.rem_euclid(CHUNK_SIZE) as usize),
             (coords.y.rem_euclid(CHUNK_SIZE) as usize),
         );
-        match self.chunks.get_mut(&chunk_pos) {
-            Some(chunk) => Some(&mut chunk.tiles[index.0][index.1]),
+        match self.chunks.get(&chunk_pos) {
+            Some(chunk) => Some(&chunk.tiles[index.0][index.1]),
             None => None,
         }
     }
-    fn get_tile(&self, coords: &Coords) -> Option<&Tile> {
+
+    fn set_tile(&mut self, coords: &Coords, tile: Tile) {
         let chunk_pos = ChunkPosition::from_coords(coords);
         let index = (
             (coords.x.rem_euclid(CHUNK_SIZE) as usize),
             (coords.y.rem_euclid(CHUNK_SIZE) as usize),
         );
-        match self.chunks.get(&chunk_pos) {
-            Some(chunk) => Some(&chunk.tiles[index.0][index.1]),
-            None => None,
+        match self.chunks.get_mut(&chunk_pos) {
+            Some(chunk) => {
+                chunk.set_tile(index, tile, self.generator.tile_at(coords.to_vec2()));
+                self.tile_changes.push(TileChange {
+                    coords: *coords,
+                    tile,
+                    revision: chunk.revision,
+                });
+                self.unsaved_changes = true;
+                self.invalidate_around(coords, 1);
+            }
+            None => (),
         }
     }
 
-    fn set_tile(&mut self, coords: &Coords, tile: Tile) {
-        let chunk_pos = ChunkPosition::from_coords(coords);
+    pub fn take_tile_changes(&mut self) -> Vec<TileChange> {
+        self.tile_changes.drain(..).collect()
+    }
+
+    //Chunks generated or restored since the last call, with the revision they came back with
+    pub fn take_new_chunks(&mut self) -> Vec<(ChunkPosition, u32)> {
+        self.new_chunks.drain(..).collect()
+    }
+
+    //A change made somewhere else, it is not reported back through take_tile_changes.
+    //Chunks that are not loaded pick the change up from the server when they are generated
+    pub fn apply_tile_change(&mut self, change: &TileChange) {
+        let chunk_pos = ChunkPosition::from_coords(&change.coords);
         let index = (
-            (coords.x.rem_euclid(CHUNK_SIZE) as usize),
-            (coords.y.rem_euclid(CHUNK_SIZE) as usize),
+            (change.coords.x.rem_euclid(CHUNK_SIZE) as usize),
+            (change.coords.y.rem_euclid(CHUNK_SIZE) as usize),
         );
         match self.chunks.get_mut(&chunk_pos) {
             Some(chunk) => {
-                chunk.tiles[index.0][index.1] = tile;
+                let generated = self.generator.tile_at(change.coords.to_vec2());
+                chunk.set_tile(index, change.tile, generated);
+                chunk.revision = change.revision;
+                self.unsaved_changes = true;
+                self.invalidate_around(&change.coords, 1);
             }
             None => (),
         }
     }
 
+    //The rules update_world_by_entity follows, for checking edits reported by someone else
+    pub fn can_change_tile(&self, coords: &Coords, tile: &Tile) -> bool {
+        match self.get_tile(coords) {
+            Some(current) => {
+                let dig = current.action == TileAction::Destroyable && *tile == Tile::DUG;
+                let place = matches!(
+                    current.interaction,
+                    TileInteraction::Walkable | TileInteraction::Crawl
+                ) && *tile == WorldResource::Dirt.to_tile();
+                dig || place
+            }
+            None => false,
+        }
+    }
+
+    //Validated change that is reported through take_tile_changes like any local edit
+    pub fn change_tile(&mut self, coords: &Coords, tile: Tile) -> bool {
+        if self.can_change_tile(coords, &tile) {
+            self.set_tile(coords, tile);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn chunk_revision(&self, pos: &ChunkPosition) -> Option<u32> {
+        match self.chunks.get(pos) {
+            Some(chunk) => Some(chunk.revision),
+            None => self.archived.get(pos).map(|chunk_save| chunk_save.revision),
+        }
+    }
+
+    pub fn chunk_data(&self, pos: &ChunkPosition) -> Option<ChunkSave> {
+        match self.chunks.get(pos) {
+            Some(chunk) => Some(ChunkSave::from_chunk(chunk)),
+            None => self.archived.get(pos).cloned(),
+        }
+    }
+
+    //The full chunk from the server, replacing whatever is here. Not loaded chunks are
+    //archived so they come back like this when generated
+    pub fn apply_chunk_data(&mut self, chunk_save: ChunkSave) {
+        let pos = chunk_save.pos();
+        match self.chunks.get_mut(&pos) {
+            Some(chunk) => {
+                if chunk_save.apply(chunk).is_some() {
+                    self.unsaved_changes = true;
+                    self.invalidate_around(&Coords::from_position(&pos), CHUNK_SIZE);
+                }
+            }
+            None => {
+                self.archived.insert(pos, chunk_save);
+                self.unsaved_changes = true;
+            }
+        }
+    }
+
+    //Autotiled chunks depend on the tiles around them, so neighbouring chunks touching
+    //the `size` square at `corner` have to be baked again
+    fn invalidate_around(&mut self, corner: &Coords, size: i32) {
+        if self.atlas.is_none() {
+            return;
+        }
+        let inner = ChunkPosition::from_coords(corner);
+        let first = ChunkPosition::from_coords(&Coords {
+            x: corner.x - 1,
+            y: corner.y - 1,
+        });
+        let last = ChunkPosition::from_coords(&Coords {
+            x: corner.x + size,
+            y: corner.y + size,
+        });
+        for x in first.x..=last.x {
+            for y in first.y..=last.y {
+                let pos = ChunkPosition { x, y };
+                if pos == inner {
+                    continue;
+                }
+                if let Some(chunk) = self.chunks.get_mut(&pos) {
+                    chunk.invalidate();
+                }
+            }
+        }
+    }
+
+    fn bake_chunk(&self, pos: &ChunkPosition) -> Texture2D {
+        let tile_pixels = match self.atlas {
+            Some(_) => TILE_PIXELS,
+            None => 1,
+        };
+        let size = (CHUNK_SIZE as u32 * tile_pixels) as u16;
+        let mut image = Image::gen_image_color(size, size, BLANK);
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                let coords = Coords::from_position_at(pos, (x, y));
+                let tile = match self.get_tile(&coords) {
+                    Some(tile) => tile,
+                    None => continue,
+                };
+                let (px, py) = (x as u32 * tile_pixels, y as u32 * tile_pixels);
+                match &self.atlas {
+                    Some(atlas) => {
+                        atlas.paint(&mut image, px, py, tile, &self.joined_neighbours(&coords))
+                    }
+                    None => image.set_pixel(px, py, tile.texture.to_color()),
+                }
+            }
+        }
+        let texture = Texture2D::from_image(&image);
+        texture.set_filter(FilterMode::Nearest);
+        texture
+    }
+
+    //Neighbours that are not generated yet count as joined so no edge is drawn towards them
+    fn joined_neighbours(&self, coords: &Coords) -> [bool; 8] {
+        let texture = self.get_tile(coords).map(|tile| tile.texture);
+        let mut joined = [true; 8];
+        for (i, (dx, dy)) in NEIGHBOURS.iter().enumerate() {
+            let neighbour = Coords {
+                x: coords.x + dx,
+                y: coords.y + dy,
+            };
+            if let Some(tile) = self.get_tile(&neighbour) {
+                joined[i] = Some(tile.texture) == texture;
+            }
+        }
+        joined
+    }
+
     pub fn update_entity(&self, entity: &mut dyn WorldEntity, time: f32) {
         let current_coords = Coords::from_vec2(entity.get_position());
-        let mut velocity = entity.get_velocity();
 
         match self.get_tile(&current_coords) {
+            Some(tile) => {
+                let velocity = entity.get_velocity();
+                let new_pos = self.move_position(entity.get_position(), velocity, time);
+                entity.set_position(new_pos);
+                entity.update(&tile.interaction, &tile.action, time);
+            }
+            None => (),
+        };
+    }
+
+    //For entities moved from elsewhere, like remote players, runs the tile they stand on and
+    //their animation without moving them
+    pub fn animate_entity(&self, entity: &mut dyn WorldEntity, time: f32) {
+        match self.get_tile(&Coords::from_vec2(entity.get_position())) {
+            Some(tile) => entity.update(&tile.interaction, &tile.action, time),
+            None => (),
+        }
+    }
+
+    //Only the movement of update_entity, so movement can be replayed without animating anything
+    pub fn move_position(&self, pos: Vec2, velocity: Vec2, time: f32) -> Vec2 {
+        let mut velocity = velocity;
+        match self.get_tile(&Coords::from_vec2(pos)) {
             Some(tile) => {
                 match tile.interaction {
                     TileInteraction::Swimmable => velocity *= 0.6,
@@ -162,26 +550,26 @@ impl World {
                     _ => (),
                 }
 
-                let new_pos = entity.get_position() + velocity * time;
+                let new_pos = pos + velocity * time;
 
                 if self.can_move_entity_to_tile(new_pos) {
-                    entity.set_position(new_pos);
+                    new_pos
                 } else {
-                    let new_pos = entity.get_position() + vec2(velocity.x, 0.) * time;
+                    let new_pos = pos + vec2(velocity.x, 0.) * time;
                     if self.can_move_entity_to_tile(new_pos) {
-                        entity.set_position(new_pos);
+                        new_pos
                     } else {
-                        let new_pos = entity.get_position() + vec2(0., velocity.y) * time;
+                        let new_pos = pos + vec2(0., velocity.y) * time;
                         if self.can_move_entity_to_tile(new_pos) {
-                            entity.set_position(new_pos);
+                            new_pos
+                        } else {
+                            pos
                         }
                     }
                 }
-
-                entity.update(&tile.interaction, &tile.action, time);
             }
-            None => (),
-        };
+            None => pos,
+        }
     }
 
     pub fn update_world_by_entity(&mut self, entity: &mut dyn WorldEntity) {
@@ -190,20 +578,13 @@ impl World {
             EntityWorldEvent::Destroy(direction) => {
                 let current_coords = Coords::from_vec2(entity.get_position());
 
-                let tiles_coords = get_tiles_in_half_circle(current_coords, direction, 3.);
+                let tiles_coords = DIG_AREA.tiles(current_coords, direction);
                 for tile_coords in tiles_coords {
                     match self.get_tile(&tile_coords) {
                         Some(tile) => match tile.action {
                             TileAction::Destroyable => {
                                 entity.give_resource(WorldResource::Dirt);
-                                self.set_tile(
-                                    &tile_coords,
-                                    Tile {
-                                        texture: TileTexture::Grass,
-                                        interaction: TileInteraction::Walkable,
-                                        action: TileAction::None,
-                                    },
-                                );
+                                self.set_tile(&tile_coords, Tile::DUG);
                             }
                             _ => (),
                         },
@@ -213,11 +594,11 @@ impl World {
             }
             EntityWorldEvent::Place(resource, pos) => {
                 let place_coords = Coords::from_vec2(pos);
-                match self.get_tile_mut(&place_coords) {
+                match self.get_tile(&place_coords) {
                     Some(tile) => {
                         match tile.interaction {
                             TileInteraction::Walkable | TileInteraction::Crawl => {
-                                *tile = resource.to_tile();
+                                self.set_tile(&place_coords, resource.to_tile());
                             }
                             _ => ()
                         }
@@ -229,7 +610,7 @@ impl World {
         }
     }
 
-    fn can_move_entity_to_tile(&self, new_pos: Vec2) -> bool {
+    pub fn can_move_entity_to_tile(&self, new_pos: Vec2) -> bool {
         let next_coords = Coords::from_vec2(new_pos);
         match self.get_tile(&next_coords) {
             Some(tile) => match tile.interaction {
@@ -242,3 +623,30 @@ impl World {
         }
     }
 }
+
+#[cfg(test)]
+impl World {
+    //Grass from -16 to 16 on both axes with Block tiles at `walls`, for tests that move or
+    //look around without depending on what a seed generates
+    pub fn grass_with_walls(walls: &[Coords]) -> World {
+        let mut config = WorldGenConfig::with_seed(1);
+        config.pregenerate_radius = 0;
+        let mut world = World::generate(config);
+        world.generate_at(Rect::new(-16., -16., 32., 32.), Rect::new(0., 0., 0., 0.));
+        let wall = Tile {
+            texture: TileTexture::Stone,
+            interaction: TileInteraction::Block,
+            action: TileAction::None,
+        };
+        for chunk in world.chunks.values_mut() {
+            for x in 0..CHUNK_SIZE {
+                for y in 0..CHUNK_SIZE {
+                    let coords = Coords::from_position_at(&chunk.pos, (x, y));
+                    let tile = if walls.contains(&coords) { wall } else { Tile::DUG };
+                    chunk.tiles[x as usize][y as usize] = tile;
+                }
+            }
+        }
+        world
+    }
+}
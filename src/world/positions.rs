@@ -1,6 +1,6 @@
 use macroquad::prelude::*;
 
-use super::{CHUNK_SIZE, entity::Direction};
+use super::CHUNK_SIZE;
 
 #[derive(Debug, Eq, Hash, PartialEq, Clone, Copy)]
 pub struct Coords {
@@ -55,37 +55,3 @@ impl ChunkPosition {
         )
     }
 }
-
-pub fn get_tiles_in_half_circle(center: Coords, _direction: Direction, radius: f32) -> Vec<Coords> {
-    let mut tiles = Vec::new();
-
-    // let facing_vec = direction.to_vec2();
-    // let facing_angle = facing_vec.y.atan2(facing_vec.x);
-    // let half_circle_angle = std::f32::consts::PI / 2.0;
-
-    for x in (center.x - radius as i32)..=(center.x + radius as i32) {
-        for y in (center.y - radius as i32)..=(center.y + radius as i32) {
-            let tile_coords = Coords { x, y };
-
-            // Check if tile is within radius
-            let distance_squared =
-                (tile_coords.x - center.x).pow(2) + (tile_coords.y - center.y).pow(2);
-            let radius_squared = radius.powf(2.);
-            if distance_squared as f32 <= radius_squared {
-                // Check if tile is within half circle facing in player direction
-                // let tile_vec = Vec2 {
-                //     x: (tile_coords.x - center.x) as f32,
-                //     y: (tile_coords.y - center.y) as f32,
-                // };
-                // let tile_angle = tile_vec.y.atan2(tile_vec.x);
-                // let angle_diff = (tile_angle - facing_angle).abs();
-                // let within_half_circle = angle_diff <= half_circle_angle;
-                // if within_half_circle {
-                    tiles.push(tile_coords);
-                // }
-            }
-        }
-    }
-
-    tiles
-}
\ No newline at end of file
@@ -0,0 +1,123 @@
+//Decides which chunks stay in memory once the world grows past its budget
+
+use std::collections::HashMap;
+
+use super::positions::ChunkPosition;
+
+//Side of the square of chunks one bitset of DiscoveredChunks covers
+const REGION_SIZE: i32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModifiedChunks {
+    //Modified chunks are never evicted
+    Keep,
+    //Modified chunks are evicted as a compact ChunkSave and restored when generated again
+    Serialize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResidencyPolicy {
+    //Chunks this close (in chunks) to any player are never evicted
+    pub keep_distance: i32,
+    pub max_chunks: usize,
+    pub max_map_chunks: usize,
+    pub modified: ModifiedChunks,
+}
+
+impl ResidencyPolicy {
+    //Evicting stops below the budget so eviction does not run every frame
+    pub fn low_water_mark(max: usize) -> usize {
+        max - max / 4
+    }
+
+    //Reads `--keep-distance <chunks>`, `--max-chunks <n>`, `--max-map-chunks <n>` and
+    //`--keep-modified`, anything missing or unreadable stays at its default
+    pub fn from_args(args: impl Iterator<Item = String>) -> ResidencyPolicy {
+        let mut policy = ResidencyPolicy::default();
+        let args: Vec<String> = args.collect();
+        for pair in args.windows(2) {
+            match (pair[0].as_str(), pair[1].parse::<usize>()) {
+                ("--keep-distance", Ok(distance)) => policy.keep_distance = distance as i32,
+                ("--max-chunks", Ok(max)) if max > 0 => policy.max_chunks = max,
+                ("--max-map-chunks", Ok(max)) if max > 0 => policy.max_map_chunks = max,
+                _ => (),
+            }
+        }
+        if args.iter().any(|arg| arg == "--keep-modified") {
+            policy.modified = ModifiedChunks::Keep;
+        }
+        policy
+    }
+}
+
+impl Default for ResidencyPolicy {
+    fn default() -> Self {
+        ResidencyPolicy {
+            keep_distance: 24,
+            max_chunks: 4096,
+            max_map_chunks: 16384,
+            modified: ModifiedChunks::Serialize,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ResidencyStats {
+    pub chunks: usize,
+    pub map_chunks: usize,
+    pub modified: usize,
+    pub archived: usize,
+    pub evicted: usize,
+    pub discovered: usize,
+}
+
+//Every chunk that has been seen, for the map. Kept as one bit per chunk in square regions so
+//it stays small however far the world is explored, unlike the chunks themselves it is never
+//evicted
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiscoveredChunks {
+    //Row `y` of a region is a bitmask of its columns
+    regions: HashMap<(i32, i32), [u64; REGION_SIZE as usize]>,
+}
+
+impl DiscoveredChunks {
+    pub fn new() -> DiscoveredChunks {
+        DiscoveredChunks {
+            regions: HashMap::new(),
+        }
+    }
+
+    fn locate(pos: &ChunkPosition) -> ((i32, i32), usize, u64) {
+        let region = (pos.x.div_euclid(REGION_SIZE), pos.y.div_euclid(REGION_SIZE));
+        let row = pos.y.rem_euclid(REGION_SIZE) as usize;
+        let bit = 1 << pos.x.rem_euclid(REGION_SIZE);
+        (region, row, bit)
+    }
+
+    pub fn insert(&mut self, pos: ChunkPosition) {
+        let (region, row, bit) = DiscoveredChunks::locate(&pos);
+        self.regions
+            .entry(region)
+            .or_insert([0; REGION_SIZE as usize])[row] |= bit;
+    }
+
+    pub fn contains(&self, pos: &ChunkPosition) -> bool {
+        let (region, row, bit) = DiscoveredChunks::locate(pos);
+        match self.regions.get(&region) {
+            Some(rows) => rows[row] & bit != 0,
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions
+            .values()
+            .flat_map(|rows| rows.iter())
+            .map(|row| row.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+}
@@ -0,0 +1,213 @@
+use serde_derive::{Deserialize, Serialize};
+
+use super::{chunk::Chunk, positions::ChunkPosition, tile::Tile, CHUNK_SIZE};
+
+pub const SAVE_VERSION: u32 = 1;
+
+//Only chunks that differ from what the seed generates are stored
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct WorldSave {
+    pub version: u32,
+    pub seed: u32,
+    pub chunks: Vec<ChunkSave>,
+}
+
+//Tiles are stored as indices into the palette, column by column like `Chunk::tiles`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ChunkSave {
+    pub x: i32,
+    pub y: i32,
+    pub palette: Vec<Tile>,
+    pub tiles: Vec<u8>,
+    //Saves from before multiplayer have no revision, they count as 0
+    #[serde(default)]
+    pub revision: u32,
+}
+
+impl WorldSave {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<WorldSave, String> {
+        let save: WorldSave = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        if save.version != SAVE_VERSION {
+            return Err(format!(
+                "save version {} is not supported, expected {}",
+                save.version, SAVE_VERSION
+            ));
+        }
+        Ok(save)
+    }
+}
+
+impl ChunkSave {
+    pub fn from_chunk(chunk: &Chunk) -> ChunkSave {
+        let mut palette: Vec<Tile> = Vec::new();
+        let mut tiles = Vec::with_capacity((CHUNK_SIZE * CHUNK_SIZE) as usize);
+        for column in chunk.tiles.iter() {
+            for tile in column.iter() {
+                let index = match palette.iter().position(|known| known == tile) {
+                    Some(index) => index,
+                    None => {
+                        palette.push(*tile);
+                        palette.len() - 1
+                    }
+                };
+                tiles.push(index as u8);
+            }
+        }
+        ChunkSave {
+            x: chunk.pos.x,
+            y: chunk.pos.y,
+            palette,
+            tiles,
+            revision: chunk.revision,
+        }
+    }
+
+    pub fn pos(&self) -> ChunkPosition {
+        ChunkPosition {
+            x: self.x,
+            y: self.y,
+        }
+    }
+
+    //Writes the saved tiles over a generated chunk, None if the save is malformed
+    pub fn apply(&self, chunk: &mut Chunk) -> Option<()> {
+        if self.tiles.len() != (CHUNK_SIZE * CHUNK_SIZE) as usize {
+            return None;
+        }
+        //Checked before anything is written so a bad save leaves the chunk as generated
+        if self
+            .tiles
+            .iter()
+            .any(|index| *index as usize >= self.palette.len())
+        {
+            return None;
+        }
+        for (i, index) in self.tiles.iter().enumerate() {
+            let (x, y) = (i / CHUNK_SIZE as usize, i % CHUNK_SIZE as usize);
+            let tile = self.palette[*index as usize];
+            //Whole chunks are saved, only the tiles that differ from it as generated are changes
+            let changed = chunk.changed[x][y] || chunk.tiles[x][y] != tile;
+            chunk.tiles[x][y] = tile;
+            chunk.mark_changed((x, y), changed);
+        }
+        chunk.revision = self.revision;
+        chunk.invalidate();
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        generation::WorldGenConfig,
+        positions::Coords,
+        tile::{TileAction, TileInteraction, TileTexture},
+        World,
+    };
+
+    const WALL: Tile = Tile {
+        texture: TileTexture::Stone,
+        interaction: TileInteraction::Block,
+        action: TileAction::None,
+    };
+
+    fn world() -> World {
+        let mut config = WorldGenConfig::with_seed(11);
+        config.pregenerate_radius = 2;
+        World::generate(config)
+    }
+
+    //Something other than what is there
+    fn other_than(world: &World, coords: &Coords) -> Tile {
+        match world.get_tile(coords) {
+            Some(tile) if *tile == Tile::DUG => WALL,
+            _ => Tile::DUG,
+        }
+    }
+
+    fn saved_chunks(world: &World) -> Vec<ChunkPosition> {
+        let mut chunks: Vec<ChunkPosition> = world
+            .to_save()
+            .chunks
+            .iter()
+            .map(|chunk| chunk.pos())
+            .collect();
+        chunks.sort_by_key(|pos| (pos.x, pos.y));
+        chunks
+    }
+
+    #[test]
+    fn a_saved_world_loads_back_the_same() {
+        let mut world = world();
+        let edits = [
+            Coords { x: 0, y: 0 },
+            Coords { x: 1, y: 0 },
+            Coords { x: -9, y: 12 },
+        ];
+        for coords in edits.iter() {
+            let tile = other_than(&world, coords);
+            world.set_tile(coords, tile);
+        }
+        let save = WorldSave::from_json(&world.to_save().to_json()).unwrap();
+        assert_eq!(
+            saved_chunks(&world),
+            [ChunkPosition { x: -2, y: 1 }, ChunkPosition { x: 0, y: 0 }]
+        );
+
+        let mut loaded = self::world();
+        loaded.load_save(&save);
+        for (pos, chunk) in world.chunks.iter() {
+            assert_eq!(chunk.tiles, loaded.chunks[pos].tiles, "{:?}", pos);
+        }
+        //Only the tiles that were edited count as changes, so the next save is the same
+        assert_eq!(loaded.to_save(), world.to_save());
+        assert_eq!(loaded.chunks[&ChunkPosition { x: 0, y: 0 }].changed, {
+            let mut changed = [[false; CHUNK_SIZE as usize]; CHUNK_SIZE as usize];
+            changed[0][0] = true;
+            changed[1][0] = true;
+            changed
+        });
+    }
+
+    #[test]
+    fn chunks_set_back_to_what_was_generated_are_not_saved() {
+        let mut world = world();
+        let coords = Coords { x: 3, y: -4 };
+        let generated = *world.get_tile(&coords).unwrap();
+        let tile = other_than(&world, &coords);
+        world.set_tile(&coords, tile);
+        assert_eq!(saved_chunks(&world), [ChunkPosition { x: 0, y: -1 }]);
+
+        world.set_tile(&coords, generated);
+        assert_eq!(saved_chunks(&world), []);
+        assert_eq!(world.residency_stats().modified, 0);
+    }
+
+    #[test]
+    fn palette_indices_are_checked_before_anything_is_written() {
+        let world = world();
+        let pos = ChunkPosition { x: 1, y: 1 };
+        let mut chunk = Chunk::generate(pos, &world.generator);
+        let generated = chunk.tiles;
+        let mut save = ChunkSave::from_chunk(&chunk);
+        save.palette = vec![WALL];
+        save.tiles = vec![0; (CHUNK_SIZE * CHUNK_SIZE) as usize];
+        *save.tiles.last_mut().unwrap() = 1;
+        assert_eq!(save.apply(&mut chunk), None);
+        assert_eq!(chunk.tiles, generated);
+        assert!(!chunk.modified);
+
+        save.tiles.pop();
+        assert_eq!(save.apply(&mut chunk), None);
+        assert_eq!(chunk.tiles, generated);
+
+        save.tiles.push(0);
+        assert_eq!(save.apply(&mut chunk), Some(()));
+        assert!(chunk.tiles.iter().flatten().all(|tile| *tile == WALL));
+    }
+}
@@ -0,0 +1,297 @@
+use macroquad::prelude::*;
+
+use super::{entity::Direction, positions::Coords};
+
+//Keeps tiles that sit exactly on an edge, like the sides of a half circle, inside
+const EDGE: f32 = 0.0001;
+
+//An area in front of something, measured from where it stands and turned to face the
+//same way it does. Sizes and angles are in tiles and radians
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    //A slice of a circle, `angle` is its full width so PI is a half circle
+    Cone { length: f32, angle: f32 },
+    //A cone with the part closer than `inner` cut out, like the path of a swing
+    Arc { inner: f32, outer: f32, angle: f32 },
+    //One tile wide, straight ahead
+    Line { length: f32 },
+    //Straight ahead, `width` across with the middle on the facing
+    Rect { length: f32, width: f32 },
+}
+
+impl Shape {
+    //Whether something `offset` away from the origin is inside when facing `direction`.
+    //The origin itself is in every shape that starts there
+    pub fn contains(&self, direction: Direction, offset: Vec2) -> bool {
+        let facing = direction.to_vec2();
+        let distance = offset.length();
+        match *self {
+            Shape::Cone { length, angle } => {
+                distance <= length + EDGE && within_angle(facing, offset, angle)
+            }
+            Shape::Arc {
+                inner,
+                outer,
+                angle,
+            } => {
+                distance + EDGE >= inner
+                    && distance <= outer + EDGE
+                    && within_angle(facing, offset, angle)
+            }
+            Shape::Line { length } => within_rect(facing, offset, length, 1.),
+            Shape::Rect { length, width } => within_rect(facing, offset, length, width),
+        }
+    }
+
+    //Every tile inside when standing on `center`, going by the middle of each tile
+    pub fn tiles(&self, center: Coords, direction: Direction) -> Vec<Coords> {
+        let reach = self.reach().ceil() as i32;
+        let mut tiles = Vec::new();
+        for x in -reach..=reach {
+            for y in -reach..=reach {
+                if self.contains(direction, vec2(x as f32, y as f32)) {
+                    tiles.push(Coords {
+                        x: center.x + x,
+                        y: center.y + y,
+                    });
+                }
+            }
+        }
+        tiles
+    }
+
+    //Furthest from the origin any part of the shape gets along either axis
+    fn reach(&self) -> f32 {
+        match *self {
+            Shape::Cone { length, .. } => length,
+            Shape::Arc { outer, .. } => outer,
+            Shape::Line { length } => length,
+            Shape::Rect { length, width } => length.max(width / 2.),
+        }
+    }
+}
+
+fn within_angle(facing: Vec2, offset: Vec2, angle: f32) -> bool {
+    if offset == Vec2::ZERO {
+        return true;
+    }
+    facing.dot(offset) + EDGE >= offset.length() * (angle / 2.).cos()
+}
+
+fn within_rect(facing: Vec2, offset: Vec2, length: f32, width: f32) -> bool {
+    let along = facing.dot(offset);
+    let across = facing.perp_dot(offset).abs();
+    along + EDGE >= 0. && along <= length + EDGE && across <= width / 2. + EDGE
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use strum::IntoEnumIterator;
+
+    use super::*;
+    use crate::world::DIG_AREA;
+
+    fn sorted(tiles: Vec<Coords>) -> Vec<(i32, i32)> {
+        let mut tiles: Vec<(i32, i32)> = tiles.iter().map(|coords| (coords.x, coords.y)).collect();
+        tiles.sort();
+        tiles
+    }
+
+    #[test]
+    fn digging_takes_the_half_circle_in_front() {
+        let expected = [
+            (
+                Direction::Up,
+                vec![
+                    (-3, 0),
+                    (-2, -2),
+                    (-2, -1),
+                    (-2, 0),
+                    (-1, -2),
+                    (-1, -1),
+                    (-1, 0),
+                    (0, -3),
+                    (0, -2),
+                    (0, -1),
+                    (0, 0),
+                    (1, -2),
+                    (1, -1),
+                    (1, 0),
+                    (2, -2),
+                    (2, -1),
+                    (2, 0),
+                    (3, 0),
+                ],
+            ),
+            (
+                Direction::Down,
+                vec![
+                    (-3, 0),
+                    (-2, 0),
+                    (-2, 1),
+                    (-2, 2),
+                    (-1, 0),
+                    (-1, 1),
+                    (-1, 2),
+                    (0, 0),
+                    (0, 1),
+                    (0, 2),
+                    (0, 3),
+                    (1, 0),
+                    (1, 1),
+                    (1, 2),
+                    (2, 0),
+                    (2, 1),
+                    (2, 2),
+                    (3, 0),
+                ],
+            ),
+            (
+                Direction::Left,
+                vec![
+                    (-3, 0),
+                    (-2, -2),
+                    (-2, -1),
+                    (-2, 0),
+                    (-2, 1),
+                    (-2, 2),
+                    (-1, -2),
+                    (-1, -1),
+                    (-1, 0),
+                    (-1, 1),
+                    (-1, 2),
+                    (0, -3),
+                    (0, -2),
+                    (0, -1),
+                    (0, 0),
+                    (0, 1),
+                    (0, 2),
+                    (0, 3),
+                ],
+            ),
+            (
+                Direction::Right,
+                vec![
+                    (0, -3),
+                    (0, -2),
+                    (0, -1),
+                    (0, 0),
+                    (0, 1),
+                    (0, 2),
+                    (0, 3),
+                    (1, -2),
+                    (1, -1),
+                    (1, 0),
+                    (1, 1),
+                    (1, 2),
+                    (2, -2),
+                    (2, -1),
+                    (2, 0),
+                    (2, 1),
+                    (2, 2),
+                    (3, 0),
+                ],
+            ),
+        ];
+        for (direction, tiles) in expected {
+            let origin = Coords { x: 0, y: 0 };
+            assert_eq!(
+                sorted(DIG_AREA.tiles(origin, direction)),
+                tiles,
+                "{:?}",
+                direction
+            );
+            //Nothing behind, not even right next to the player
+            let behind = -direction.to_vec2();
+            let behind = (behind.x as i32, behind.y as i32);
+            assert!(!tiles.contains(&behind), "{:?}", direction);
+        }
+    }
+
+    #[test]
+    fn tiles_are_around_the_center() {
+        let center = Coords { x: 10, y: -20 };
+        for direction in Direction::iter() {
+            let around = sorted(DIG_AREA.tiles(center, direction));
+            let at_origin = sorted(DIG_AREA.tiles(Coords { x: 0, y: 0 }, direction));
+            let moved: Vec<(i32, i32)> = at_origin.iter().map(|(x, y)| (x + 10, y - 20)).collect();
+            assert_eq!(around, moved);
+        }
+    }
+
+    #[test]
+    fn a_cone_ends_at_its_length_and_angle() {
+        let cone = Shape::Cone {
+            length: 2.,
+            angle: PI / 2.,
+        };
+        let right = Direction::Right;
+        assert!(cone.contains(right, vec2(0., 0.)));
+        assert!(cone.contains(right, vec2(2., 0.)));
+        //On both edges at once, a corner of the slice
+        assert!(cone.contains(right, vec2(2f32.sqrt(), 2f32.sqrt())));
+        assert!(cone.contains(right, vec2(1., -1.)));
+        assert!(!cone.contains(right, vec2(2.01, 0.)));
+        assert!(!cone.contains(right, vec2(1., 1.1)));
+        assert!(!cone.contains(right, vec2(-1., 0.)));
+        assert!(cone.contains(Direction::Up, vec2(0., -2.)));
+        assert!(!cone.contains(Direction::Up, vec2(0., 2.)));
+    }
+
+    #[test]
+    fn an_arc_leaves_out_the_inside() {
+        let arc = Shape::Arc {
+            inner: 1.,
+            outer: 3.,
+            angle: PI,
+        };
+        let right = Direction::Right;
+        assert!(arc.contains(right, vec2(1., 0.)));
+        assert!(arc.contains(right, vec2(3., 0.)));
+        //The sides of a half circle are in
+        assert!(arc.contains(right, vec2(0., 2.)));
+        assert!(arc.contains(right, vec2(0., -3.)));
+        assert!(!arc.contains(right, vec2(0., 0.)));
+        assert!(!arc.contains(right, vec2(0.9, 0.)));
+        assert!(!arc.contains(right, vec2(3.1, 0.)));
+        assert!(!arc.contains(right, vec2(-0.1, 2.)));
+        assert!(arc.contains(Direction::Left, vec2(-2., 0.)));
+        assert!(!arc.contains(Direction::Left, vec2(2., 0.)));
+    }
+
+    #[test]
+    fn a_line_is_one_tile_wide() {
+        let line = Shape::Line { length: 3. };
+        let down = Direction::Down;
+        assert!(line.contains(down, vec2(0., 0.)));
+        assert!(line.contains(down, vec2(0., 3.)));
+        assert!(line.contains(down, vec2(0.5, 2.)));
+        assert!(line.contains(down, vec2(-0.5, 1.)));
+        assert!(!line.contains(down, vec2(0.6, 2.)));
+        assert!(!line.contains(down, vec2(0., 3.1)));
+        assert!(!line.contains(down, vec2(0., -0.5)));
+        assert!(!line.contains(down, vec2(1., 0.)));
+        let tiles = sorted(line.tiles(Coords { x: 0, y: 0 }, Direction::Left));
+        assert_eq!(tiles, vec![(-3, 0), (-2, 0), (-1, 0), (0, 0)]);
+    }
+
+    #[test]
+    fn a_rect_reaches_its_corners() {
+        let rect = Shape::Rect {
+            length: 2.,
+            width: 3.,
+        };
+        let left = Direction::Left;
+        assert!(rect.contains(left, vec2(-2., 1.5)));
+        assert!(rect.contains(left, vec2(-2., -1.5)));
+        assert!(rect.contains(left, vec2(0., 1.5)));
+        assert!(rect.contains(left, vec2(-1., 0.)));
+        assert!(!rect.contains(left, vec2(-2.1, 0.)));
+        assert!(!rect.contains(left, vec2(-1., 1.6)));
+        assert!(!rect.contains(left, vec2(1., 0.)));
+        assert!(rect.contains(Direction::Up, vec2(1.5, -2.)));
+        assert!(!rect.contains(Direction::Up, vec2(1.5, 2.)));
+    }
+}
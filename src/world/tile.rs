@@ -1,6 +1,9 @@
 use macroquad::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use std::{cmp::Ordering, fmt};
+use strum_macros::EnumIter;
 
-#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy)]
+#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy, Deserialize, Serialize)]
 pub struct Tile {
     pub texture: TileTexture,
     pub interaction: TileInteraction,
@@ -8,60 +11,27 @@ pub struct Tile {
 }
 
 impl Tile {
-    pub fn generate(n: f64) -> Tile {
-        let (texture, interaction, action) = if n < 0.25 {
-            (
-                TileTexture::DeepWater,
-                TileInteraction::Swimmable,
-                TileAction::Death,
-            )
-        } else if n < 0.43 {
-            (
-                TileTexture::Water,
-                TileInteraction::Swimmable,
-                TileAction::None,
-            )
-        } else if n < 0.5 {
-            (
-                TileTexture::ShallowWater,
-                TileInteraction::Crawl,
-                TileAction::None,
-            )
-        } else if n < 0.52 {
-            (
-                TileTexture::Sand,
-                TileInteraction::Walkable,
-                TileAction::None,
-            )
-        } else if n < 0.7 {
-            (
-                TileTexture::Grass,
-                TileInteraction::Walkable,
-                TileAction::None,
-            )
-        } else if n < 0.72 {
-            (
-                TileTexture::Dirt,
-                TileInteraction::Block,
-                TileAction::Destroyable,
-            )
-        } else if n < 0.85 {
-            (
-                TileTexture::Stone,
-                TileInteraction::Block,
-                TileAction::Death,
-            )
-        } else {
-            (
-                TileTexture::SnowyMountain,
-                TileInteraction::Block,
-                TileAction::Death,
-            )
-        };
-        Tile {
-            texture,
-            interaction,
-            action,
+    //What a destroyable tile turns into when dug out
+    pub const DUG: Tile = Tile {
+        texture: TileTexture::Grass,
+        interaction: TileInteraction::Walkable,
+        action: TileAction::None,
+    };
+
+    //Tables are checked when they are made, so falling back to the default never happens
+    pub fn generate(n: f64, table: &TileTable) -> Tile {
+        match table
+            .tiles
+            .iter()
+            .find(|range| n < range.to)
+            .or(table.tiles.last())
+        {
+            Some(range) => Tile {
+                texture: range.texture,
+                interaction: range.interaction,
+                action: range.action,
+            },
+            None => Tile::default(),
         }
     }
 }
@@ -76,7 +46,169 @@ impl Default for Tile {
     }
 }
 
-#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct TileRange {
+    pub from: f64,
+    pub to: f64,
+    pub texture: TileTexture,
+    pub interaction: TileInteraction,
+    pub action: TileAction,
+}
+
+//How the tile a biome puts in place of another behaves, found by its texture
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct BiomeTile {
+    pub texture: TileTexture,
+    pub interaction: TileInteraction,
+    pub action: TileAction,
+}
+
+//Maps the noise value (0..1) to tiles, loaded from textures/tiles.json. Deserializing one
+//goes through from_json's checks as well, so every table there is covers 0..1
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "TileTableFile")]
+pub struct TileTable {
+    tiles: Vec<TileRange>,
+    biomes: Vec<BiomeTile>,
+}
+
+//A table as it is written down, before it is checked
+#[derive(Deserialize)]
+struct TileTableFile {
+    tiles: Vec<TileRange>,
+    #[serde(default)]
+    biomes: Vec<BiomeTile>,
+}
+
+impl TryFrom<TileTableFile> for TileTable {
+    type Error = TileTableError;
+
+    fn try_from(file: TileTableFile) -> Result<TileTable, TileTableError> {
+        let mut table = TileTable {
+            tiles: file.tiles,
+            biomes: file.biomes,
+        };
+        table
+            .tiles
+            .sort_by(|a, b| a.from.partial_cmp(&b.from).unwrap_or(Ordering::Equal));
+        table.validate()?;
+        Ok(table)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TileTableError {
+    Parse(String),
+    Empty,
+    InvalidRange(TileTexture),
+    Gap(f64, f64),
+    Overlap(f64, f64),
+    Uncovered(f64),
+    DuplicateBiome(TileTexture),
+}
+
+impl fmt::Display for TileTableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TileTableError::Parse(err) => write!(f, "could not parse tile table: {}", err),
+            TileTableError::Empty => write!(f, "tile table has no tiles"),
+            TileTableError::InvalidRange(texture) => {
+                write!(f, "range for {:?} ends before it starts", texture)
+            }
+            TileTableError::Gap(from, to) => write!(f, "no tile covers {} to {}", from, to),
+            TileTableError::Overlap(from, to) => {
+                write!(f, "more than one tile covers {} to {}", from, to)
+            }
+            TileTableError::Uncovered(n) => write!(f, "no tile covers {}", n),
+            TileTableError::DuplicateBiome(texture) => {
+                write!(f, "biome tile {:?} is listed more than once", texture)
+            }
+        }
+    }
+}
+
+const TILE_TABLE_EPSILON: f64 = 1e-9;
+
+impl TileTable {
+    pub fn from_json(json: &str) -> Result<TileTable, TileTableError> {
+        let file: TileTableFile =
+            serde_json::from_str(json).map_err(|err| TileTableError::Parse(err.to_string()))?;
+        TileTable::try_from(file)
+    }
+
+    //Ranges have to be sorted by `from` and together cover 0..1 exactly once, and every biome
+    //texture is listed at most once
+    pub fn validate(&self) -> Result<(), TileTableError> {
+        let (first, last) = match (self.tiles.first(), self.tiles.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Err(TileTableError::Empty),
+        };
+        if first.from > TILE_TABLE_EPSILON {
+            return Err(TileTableError::Uncovered(0.));
+        }
+        if last.to < 1. - TILE_TABLE_EPSILON {
+            return Err(TileTableError::Uncovered(1.));
+        }
+        for range in self.tiles.iter() {
+            if range.to <= range.from {
+                return Err(TileTableError::InvalidRange(range.texture));
+            }
+        }
+        for pair in self.tiles.windows(2) {
+            let (previous, next) = (&pair[0], &pair[1]);
+            if next.from > previous.to + TILE_TABLE_EPSILON {
+                return Err(TileTableError::Gap(previous.to, next.from));
+            }
+            if next.from < previous.to - TILE_TABLE_EPSILON {
+                return Err(TileTableError::Overlap(next.from, previous.to));
+            }
+        }
+        for (i, biome) in self.biomes.iter().enumerate() {
+            if self.biomes[..i]
+                .iter()
+                .any(|other| other.texture == biome.texture)
+            {
+                return Err(TileTableError::DuplicateBiome(biome.texture));
+            }
+        }
+        Ok(())
+    }
+
+    //The biome's texture in place of `base`, behaving like `base` when the table does not
+    //list the texture
+    pub fn biome_tile(&self, texture: TileTexture, base: Tile) -> Tile {
+        match self.biomes.iter().find(|biome| biome.texture == texture) {
+            Some(biome) => Tile {
+                texture,
+                interaction: biome.interaction,
+                action: biome.action,
+            },
+            None => Tile { texture, ..base },
+        }
+    }
+}
+
+impl Default for TileTable {
+    fn default() -> Self {
+        TileTable::from_json(include_str!("../../textures/tiles.json")).unwrap()
+    }
+}
+
+pub async fn load_tile_table() -> TileTable {
+    let table = match load_string("textures/tiles.json").await {
+        Ok(json) => TileTable::from_json(&json),
+        Err(err) => Err(TileTableError::Parse(err.to_string())),
+    };
+    match table {
+        Ok(table) => table,
+        Err(err) => {
+            error!("textures/tiles.json: {}, using built in tiles", err);
+            TileTable::default()
+        }
+    }
+}
+
+#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy, EnumIter, Deserialize, Serialize)]
 pub enum TileInteraction {
     Block,
     Walkable,
@@ -84,14 +216,14 @@ pub enum TileInteraction {
     Crawl,
 }
 
-#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy)]
+#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy, EnumIter, Deserialize, Serialize)]
 pub enum TileAction {
     None,
     Destroyable,
     Death,
 }
 
-#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy)]
+#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy, EnumIter, Deserialize, Serialize)]
 pub enum TileTexture {
     Grass,
     Water,
@@ -101,9 +233,32 @@ pub enum TileTexture {
     Dirt,
     Stone,
     SnowyMountain,
+    Beach,
+    Desert,
+    Forest,
+    Swamp,
+    Tundra,
 }
 
 impl TileTexture {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TileTexture::Grass => "grass",
+            TileTexture::Water => "water",
+            TileTexture::ShallowWater => "shallow_water",
+            TileTexture::Sand => "sand",
+            TileTexture::DeepWater => "deep_water",
+            TileTexture::Dirt => "dirt",
+            TileTexture::Stone => "stone",
+            TileTexture::SnowyMountain => "snowy_mountain",
+            TileTexture::Beach => "beach",
+            TileTexture::Desert => "desert",
+            TileTexture::Forest => "forest",
+            TileTexture::Swamp => "swamp",
+            TileTexture::Tundra => "tundra",
+        }
+    }
+
     pub fn to_color(&self) -> Color {
         match self {
             TileTexture::Grass => GREEN,
@@ -114,6 +269,133 @@ impl TileTexture {
             TileTexture::SnowyMountain => WHITE,
             TileTexture::Sand => YELLOW,
             TileTexture::Dirt => Color::from_rgba(155, 118, 83, 255),
+            TileTexture::Beach => Color::from_rgba(240, 225, 160, 255),
+            TileTexture::Desert => Color::from_rgba(230, 190, 100, 255),
+            TileTexture::Forest => DARKGREEN,
+            TileTexture::Swamp => Color::from_rgba(80, 100, 60, 255),
+            TileTexture::Tundra => Color::from_rgba(200, 215, 210, 255),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Each range as (from, to, texture), all walkable so only the texture tells them apart
+    fn table_json(ranges: &[(f64, f64, &str)], biomes: &[&str]) -> String {
+        let tiles: Vec<String> = ranges
+            .iter()
+            .map(|(from, to, texture)| {
+                format!(
+                    r#"{{"from":{},"to":{},"texture":"{}","interaction":"Walkable","action":"None"}}"#,
+                    from, to, texture
+                )
+            })
+            .collect();
+        let biomes: Vec<String> = biomes
+            .iter()
+            .map(|texture| {
+                format!(
+                    r#"{{"texture":"{}","interaction":"Walkable","action":"None"}}"#,
+                    texture
+                )
+            })
+            .collect();
+        format!(
+            r#"{{"tiles":[{}],"biomes":[{}]}}"#,
+            tiles.join(","),
+            biomes.join(",")
+        )
+    }
+
+    fn error(ranges: &[(f64, f64, &str)], biomes: &[&str]) -> TileTableError {
+        TileTable::from_json(&table_json(ranges, biomes)).unwrap_err()
+    }
+
+    #[test]
+    fn broken_tables_are_refused() {
+        assert_eq!(error(&[], &[]), TileTableError::Empty);
+        assert_eq!(
+            error(&[(0.1, 1., "Grass")], &[]),
+            TileTableError::Uncovered(0.)
+        );
+        assert_eq!(
+            error(&[(0., 0.9, "Grass")], &[]),
+            TileTableError::Uncovered(1.)
+        );
+        assert_eq!(
+            error(
+                &[(0., 0.5, "Grass"), (0.5, 0.5, "Water"), (0.5, 1., "Sand")],
+                &[]
+            ),
+            TileTableError::InvalidRange(TileTexture::Water)
+        );
+        assert_eq!(
+            error(&[(0., 0.4, "Grass"), (0.5, 1., "Sand")], &[]),
+            TileTableError::Gap(0.4, 0.5)
+        );
+        assert_eq!(
+            error(&[(0., 0.6, "Grass"), (0.5, 1., "Sand")], &[]),
+            TileTableError::Overlap(0.5, 0.6)
+        );
+        assert_eq!(
+            error(&[(0., 1., "Grass")], &["Desert", "Forest", "Desert"]),
+            TileTableError::DuplicateBiome(TileTexture::Desert)
+        );
+        assert!(matches!(
+            TileTable::from_json("{\"tiles\":"),
+            Err(TileTableError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn deserializing_checks_the_table_too() {
+        let empty: Result<TileTable, _> = serde_json::from_str(&table_json(&[], &[]));
+        assert!(empty.is_err());
+        let table = TileTable::default();
+        let json = serde_json::to_string(&table).unwrap();
+        assert_eq!(serde_json::from_str::<TileTable>(&json).unwrap(), table);
+    }
+
+    #[test]
+    fn noise_values_map_to_their_range() {
+        //Out of order on purpose, the table sorts them
+        let json = table_json(
+            &[
+                (0.7, 1., "Stone"),
+                (0., 0.3, "DeepWater"),
+                (0.3, 0.7, "Grass"),
+            ],
+            &["Desert"],
+        );
+        let table = TileTable::from_json(&json).unwrap();
+        let texture = |n: f64| Tile::generate(n, &table).texture;
+        assert_eq!(texture(0.), TileTexture::DeepWater);
+        assert_eq!(texture(0.29), TileTexture::DeepWater);
+        assert_eq!(texture(0.3), TileTexture::Grass);
+        assert_eq!(texture(0.69), TileTexture::Grass);
+        assert_eq!(texture(0.7), TileTexture::Stone);
+        assert_eq!(texture(0.99), TileTexture::Stone);
+        //Noise can land right on 1
+        assert_eq!(texture(1.), TileTexture::Stone);
+
+        let wall = Tile {
+            texture: TileTexture::Stone,
+            interaction: TileInteraction::Block,
+            action: TileAction::Death,
+        };
+        let desert = table.biome_tile(TileTexture::Desert, wall);
+        assert_eq!(desert.texture, TileTexture::Desert);
+        assert_eq!(desert.interaction, TileInteraction::Walkable);
+        assert_eq!(desert.action, TileAction::None);
+        let forest = table.biome_tile(TileTexture::Forest, wall);
+        assert_eq!(
+            forest,
+            Tile {
+                texture: TileTexture::Forest,
+                ..wall
+            }
+        );
+    }
+}
@@ -0,0 +1,391 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use macroquad::prelude::*;
+
+use rustgame::{
+    game::{FrameInput, Game},
+    multiplayer::{DevLocalMultiplayer, Event, MultiplayerHandler},
+    player::{animation::load_animations, animation::Animation, BlockingAction, Interaction},
+    replay::{Recording, Replay},
+    world::{
+        entity::{Direction, WorldEntity, WorldResource},
+        generation::WorldGenConfig,
+        positions::{ChunkPosition, Coords},
+        tile::{Tile, TileAction, TileInteraction, TileTexture},
+        World,
+    },
+};
+
+const SEED: u32 = 7;
+const FPS: f32 = 60.;
+
+fn animations() -> HashMap<(Interaction, Direction), Animation> {
+    let spritesheet = std::fs::read_to_string("textures/spritesheet.json").unwrap();
+    load_animations(&spritesheet)
+}
+
+//Always the same world and the same spot in it
+fn new_game<'a>(
+    animations: &'a HashMap<(Interaction, Direction), Animation>,
+    texture: &'a Texture2D,
+    handler: Box<dyn MultiplayerHandler>,
+    tick_rate: f32,
+) -> Game<'a> {
+    rand::srand(1);
+    let world = World::generate(WorldGenConfig::with_seed(SEED));
+    Game::new(world, handler, animations, texture, tick_rate)
+}
+
+//What the game told the handler, shared with the test once the handler is boxed
+#[derive(Default)]
+struct Log {
+    upstream: Vec<Event>,
+    //Position, velocity and sequence number of every set_your_player_pos
+    positions: Vec<(Vec2, Vec2, u32)>,
+    incoming: Vec<Event>,
+}
+
+struct RecordingHandler(Rc<RefCell<Log>>);
+
+impl MultiplayerHandler for RecordingHandler {
+    fn get_events(&mut self) -> Vec<Event> {
+        self.0.borrow_mut().incoming.drain(..).collect()
+    }
+
+    fn upstream_event(&mut self, event: Event) {
+        self.0.borrow_mut().upstream.push(event);
+    }
+
+    fn set_your_player_pos(&mut self, pos: Vec2, v: Vec2, seq: u32, _time: f64) {
+        self.0.borrow_mut().positions.push((pos, v, seq));
+    }
+
+    fn downstream_event(&mut self, event: Event) {
+        self.0.borrow_mut().incoming.push(event);
+    }
+
+    fn is_authoritative(&self) -> bool {
+        false
+    }
+}
+
+fn recorded_game<'a>(
+    animations: &'a HashMap<(Interaction, Direction), Animation>,
+    texture: &'a Texture2D,
+) -> (Game<'a>, Rc<RefCell<Log>>) {
+    let log = Rc::new(RefCell::new(Log::default()));
+    let handler = Box::new(RecordingHandler(log.clone()));
+    (new_game(animations, texture, handler, 60.), log)
+}
+
+//Puts the player on the nearest tile it can walk on and place things on
+fn stand_on_grass(game: &mut Game) -> Coords {
+    let start = Coords::from_vec2(game.player().get_position());
+    for distance in 0..50 {
+        for x in -distance..=distance {
+            for y in -distance..=distance {
+                let coords = Coords {
+                    x: start.x + x,
+                    y: start.y + y,
+                };
+                let walkable = match game.world.get_tile(&coords) {
+                    Some(tile) => {
+                        tile.interaction == TileInteraction::Walkable
+                            && tile.action == TileAction::None
+                    }
+                    None => false,
+                };
+                if walkable {
+                    game.player_mut().set_position(coords.to_vec2());
+                    return coords;
+                }
+            }
+        }
+    }
+    panic!("nothing to stand on near {:?}", start);
+}
+
+fn moving(movement: Vec2) -> FrameInput {
+    FrameInput {
+        movement,
+        ..FrameInput::default()
+    }
+}
+
+//Walks diagonally for `seconds` at `fps`, returns where the player ended up
+fn walk(game: &mut Game, fps: u32, seconds: u32) -> Vec2 {
+    for _ in 0..fps * seconds {
+        game.update(moving(vec2(1., 1.)), 1. / fps as f32);
+    }
+    game.player().get_position()
+}
+
+#[test]
+fn frame_rate_does_not_change_where_the_player_ends_up() {
+    let animations = animations();
+    let texture = Texture2D::empty();
+    let mut slow = new_game(
+        &animations,
+        &texture,
+        Box::new(DevLocalMultiplayer::new()),
+        60.,
+    );
+    let mut fast = new_game(
+        &animations,
+        &texture,
+        Box::new(DevLocalMultiplayer::new()),
+        60.,
+    );
+    let start = slow.player().get_position();
+    assert_eq!(start, fast.player().get_position());
+
+    let slow_end = walk(&mut slow, 30, 2);
+    let fast_end = walk(&mut fast, 144, 2);
+    assert_eq!(slow.ticks(), fast.ticks());
+    assert_ne!(slow_end, start);
+    assert_eq!(slow_end, fast_end);
+}
+
+#[test]
+fn walking_reports_the_position_with_the_latest_sequence_number() {
+    let animations = animations();
+    let texture = Texture2D::empty();
+    let (mut game, log) = recorded_game(&animations, &texture);
+    stand_on_grass(&mut game);
+    let start = game.player().get_position();
+
+    for _ in 0..30 {
+        game.update(moving(vec2(1., 0.)), 1. / FPS);
+    }
+    let end = game.player().get_position();
+    assert_ne!(end, start);
+
+    let log = log.borrow();
+    assert_eq!(log.positions.len(), 30);
+    let (last_pos, _, last_seq) = *log.positions.last().unwrap();
+    assert_eq!(last_pos, end);
+    assert_eq!(last_seq as u64, game.ticks());
+    assert!(log.positions.windows(2).all(|pair| pair[1].2 >= pair[0].2));
+}
+
+#[test]
+fn placing_dirt_changes_the_tile_and_tells_the_server() {
+    let animations = animations();
+    let texture = Texture2D::empty();
+    let (mut game, log) = recorded_game(&animations, &texture);
+    let coords = stand_on_grass(&mut game);
+    game.player_mut()
+        .set_resources(HashMap::from([(WorldResource::Dirt, 1)]));
+
+    //The pointer in the middle of the screen is on the player
+    let place = FrameInput {
+        pointer: Some(FrameInput::default().screen / 2.),
+        place: true,
+        ..FrameInput::default()
+    };
+    game.update(place, 1. / FPS);
+    game.update(FrameInput::default(), 1. / FPS);
+
+    let dirt = WorldResource::Dirt.to_tile();
+    assert_eq!(game.world.get_tile(&coords), Some(&dirt));
+    assert_eq!(game.player().get_resources()[&WorldResource::Dirt], 0);
+    let told = log.borrow().upstream.iter().any(|event| {
+        matches!(event, Event::TileChanged { x, y, tile, .. }
+            if *x == coords.x && *y == coords.y && *tile == dirt)
+    });
+    assert!(told);
+}
+
+#[test]
+fn attacking_is_sent_upstream() {
+    let animations = animations();
+    let texture = Texture2D::empty();
+    let (mut game, log) = recorded_game(&animations, &texture);
+    stand_on_grass(&mut game);
+
+    let attack = FrameInput {
+        action: Some(BlockingAction::Attack),
+        ..FrameInput::default()
+    };
+    game.update(attack, 1. / FPS);
+
+    let pos = game.player().get_position();
+    let sent = log.borrow().upstream.iter().any(|event| {
+        matches!(event, Event::PlayerAction { x, y, action: BlockingAction::Attack, .. }
+            if vec2(*x, *y) == pos)
+    });
+    assert!(sent);
+}
+
+#[test]
+fn tile_changes_from_the_server_are_applied() {
+    let animations = animations();
+    let texture = Texture2D::empty();
+    let (mut game, _log) = recorded_game(&animations, &texture);
+    let coords = stand_on_grass(&mut game);
+    game.update(FrameInput::default(), 1. / FPS);
+
+    let tile = Tile {
+        texture: TileTexture::Stone,
+        interaction: TileInteraction::Block,
+        action: TileAction::None,
+    };
+    game.receive(Event::TileChanged {
+        x: coords.x + 1,
+        y: coords.y,
+        tile,
+        revision: 3,
+    });
+    game.update(FrameInput::default(), 1. / FPS);
+
+    let changed = Coords {
+        x: coords.x + 1,
+        y: coords.y,
+    };
+    assert_eq!(game.world.get_tile(&changed), Some(&tile));
+}
+
+#[test]
+fn an_acknowledgement_far_from_the_prediction_moves_the_player() {
+    let animations = animations();
+    let texture = Texture2D::empty();
+    let (mut game, log) = recorded_game(&animations, &texture);
+    stand_on_grass(&mut game);
+    for _ in 0..10 {
+        game.update(moving(vec2(0., 1.)), 1. / FPS);
+    }
+    let (pos, _, seq) = *log.borrow().positions.last().unwrap();
+
+    //Nothing was moved after `seq`, so the server position is taken as it is
+    let server_pos = pos + vec2(10., 0.);
+    game.receive(Event::CommandAck {
+        seq,
+        x: server_pos.x,
+        y: server_pos.y,
+    });
+    game.update(FrameInput::default(), 1. / FPS);
+    assert_eq!(game.player().get_position(), server_pos);
+}
+
+#[test]
+fn players_come_and_go_with_the_events_for_them() {
+    let animations = animations();
+    let texture = Texture2D::empty();
+    let (mut game, _log) = recorded_game(&animations, &texture);
+
+    game.receive(Event::PlayerUpdate {
+        name: String::from("Other"),
+        id: 5,
+        x: 3.,
+        y: 4.,
+        vx: 0.,
+        vy: 0.,
+        seq: 0,
+        time: 0.,
+    });
+    game.update(FrameInput::default(), 1. / FPS);
+    assert!(game.other_players().contains_key(&5));
+
+    game.receive(Event::PlayerDisconnect { id: 5 });
+    game.update(FrameInput::default(), 1. / FPS);
+    assert!(!game.other_players().contains_key(&5));
+}
+
+#[test]
+fn a_replay_ends_where_the_recording_did() {
+    let animations = animations();
+    let texture = Texture2D::empty();
+    let (mut game, _log) = recorded_game(&animations, &texture);
+    let start = stand_on_grass(&mut game);
+    game.player_mut()
+        .set_resources(HashMap::from([(WorldResource::Dirt, 3)]));
+
+    game.start_recording(99);
+    //Uneven frame times, so the replay has to take the same steps from them
+    let frame_times = [1. / 60., 1. / 144., 1. / 30., 0.011];
+    let mut frame = 0;
+    let mut play = |game: &mut Game, input: FrameInput| {
+        game.update(input, frame_times[frame % frame_times.len()]);
+        frame += 1;
+    };
+    for _ in 0..40 {
+        play(&mut game, moving(vec2(1., 0.3)));
+    }
+    play(
+        &mut game,
+        FrameInput {
+            pointer: Some(FrameInput::default().screen / 2.),
+            place: true,
+            ..FrameInput::default()
+        },
+    );
+    game.receive(Event::TileChanged {
+        x: start.x,
+        y: start.y - 2,
+        tile: WorldResource::Dirt.to_tile(),
+        revision: 7,
+    });
+    game.receive(Event::CommandHit {
+        health: 66,
+        kx: 0.,
+        ky: 5.,
+    });
+    for _ in 0..20 {
+        play(&mut game, moving(vec2(-0.5, -1.)));
+    }
+    play(
+        &mut game,
+        FrameInput {
+            action: Some(BlockingAction::Attack),
+            ..FrameInput::default()
+        },
+    );
+    for _ in 0..30 {
+        play(&mut game, moving(vec2(0., 1.)));
+    }
+    let recording = game.stop_recording().unwrap();
+    assert_eq!(recording.frames.len(), 92);
+
+    let recording = Recording::from_json(&recording.to_json()).unwrap();
+    let world = recording.world(WorldGenConfig::with_seed(0));
+    let mut replayed = Game::new(
+        world,
+        Box::new(DevLocalMultiplayer::new()),
+        &animations,
+        &texture,
+        recording.tick_rate,
+    );
+    recording.prepare(&mut replayed);
+    let mut replay = Replay::new(recording);
+    while !replay.is_finished() {
+        replay.step(&mut replayed);
+    }
+
+    assert_eq!(
+        replayed.player().get_position(),
+        game.player().get_position()
+    );
+    assert_eq!(replayed.player().get_health(), 66);
+    assert_eq!(replayed.player().get_health(), game.player().get_health());
+    assert_eq!(
+        replayed.player().get_resources(),
+        game.player().get_resources()
+    );
+    let around = ChunkPosition::from_coords(&start);
+    for x in around.x - 4..=around.x + 4 {
+        for y in around.y - 4..=around.y + 4 {
+            let pos = ChunkPosition { x, y };
+            assert_eq!(
+                replayed.world.chunk_data(&pos),
+                game.world.chunk_data(&pos),
+                "{:?}",
+                pos
+            );
+        }
+    }
+    let edited = ChunkPosition::from_coords(&Coords {
+        x: start.x,
+        y: start.y - 2,
+    });
+    assert_eq!(replayed.world.chunk_data(&edited).unwrap().revision, 7);
+}
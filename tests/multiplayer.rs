@@ -0,0 +1,367 @@
+use std::{
+    io::Write,
+    net::{TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+use macroquad::prelude::*;
+
+use rustgame::{
+    multiplayer::{
+        net::{ConnectOptions, Connection, TcpMultiplayer},
+        protocol::{self, PROTOCOL_VERSION},
+        Event, MultiplayerHandler,
+    },
+    player::{animation::load_animations, BlockingAction, SPAWN_POINT},
+    server::Server,
+    world::{generation::WorldGenConfig, positions::Coords, tile::TileAction, World},
+};
+
+const TICK: f32 = 0.05;
+
+//Ticks the server and collects what each client receives until `done` is happy with it
+fn run_until(
+    server: &mut Server,
+    clients: &mut [&mut TcpMultiplayer],
+    received: &mut [Vec<Event>],
+    done: impl Fn(&[Vec<Event>]) -> bool,
+) {
+    let deadline = Instant::now() + Duration::from_secs(20);
+    while !done(received) {
+        assert!(Instant::now() < deadline, "timed out, got {:?}", received);
+        server.tick(TICK);
+        for (client, events) in clients.iter_mut().zip(received.iter_mut()) {
+            events.extend(client.get_events());
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+fn welcome_id(events: &[Event]) -> Option<u32> {
+    events.iter().find_map(|event| match event {
+        Event::Welcome { id, .. } => Some(*id),
+        _ => None,
+    })
+}
+
+fn connect(server: &Server, name: &str, room: &str) -> TcpMultiplayer {
+    let options = ConnectOptions {
+        addr: server.local_addr().unwrap().to_string(),
+        name: name.to_owned(),
+        room: room.to_owned(),
+    };
+    TcpMultiplayer::connect(&options).unwrap()
+}
+
+//Updates go out at most every so often, so the position is offered until the server answers
+//it. Returns where the server has the player
+fn acknowledged(
+    server: &mut Server,
+    client: &mut TcpMultiplayer,
+    pos: Vec2,
+    seq: u32,
+    time: f64,
+) -> Vec2 {
+    let deadline = Instant::now() + Duration::from_secs(20);
+    loop {
+        assert!(
+            Instant::now() < deadline,
+            "timed out waiting for ack {}",
+            seq
+        );
+        client.set_your_player_pos(pos, Vec2::ZERO, seq, time);
+        server.tick(TICK);
+        let ack = client
+            .get_events()
+            .into_iter()
+            .find_map(|event| match event {
+                Event::CommandAck { seq: acked, x, y } if acked == seq => Some(vec2(x, y)),
+                _ => None,
+            });
+        match ack {
+            Some(ack) => return ack,
+            None => thread::sleep(Duration::from_millis(5)),
+        }
+    }
+}
+
+//The middle of a tile that kills, close enough to the spawn point to be the first position
+fn deadly_tile_near_spawn(seed: u32) -> Vec2 {
+    let mut config = WorldGenConfig::with_seed(seed);
+    config.pregenerate_radius = 0;
+    let mut world = World::generate(config);
+    let range = 40;
+    let corner = SPAWN_POINT - Vec2::splat(range as f32);
+    let size = range as f32 * 2.;
+    world.generate_at(
+        Rect::new(corner.x, corner.y, size, size),
+        Rect::new(0., 0., 0., 0.),
+    );
+    let spawn = Coords::from_vec2(SPAWN_POINT);
+    for distance in 0..range {
+        for x in -distance..=distance {
+            for y in -distance..=distance {
+                let coords = Coords {
+                    x: spawn.x + x,
+                    y: spawn.y + y,
+                };
+                match world.get_tile(&coords) {
+                    Some(tile) if tile.action == TileAction::Death => return coords.to_vec2(),
+                    _ => (),
+                }
+            }
+        }
+    }
+    panic!("nothing deadly near the spawn point with seed {}", seed);
+}
+
+#[test]
+fn two_clients_see_each_other_through_a_local_server() {
+    let spritesheet = std::fs::read_to_string("textures/spritesheet.json").unwrap();
+    let animations = load_animations(&spritesheet);
+    let texture = Texture2D::empty();
+    let mut server = Server::new(
+        "127.0.0.1:0",
+        WorldGenConfig::with_seed(1234),
+        &animations,
+        &texture,
+    )
+    .unwrap();
+
+    let mut alice = connect(&server, "Alice", "test");
+    let mut bob = connect(&server, "Bob", "test");
+    let mut received = [Vec::new(), Vec::new()];
+    run_until(
+        &mut server,
+        &mut [&mut alice, &mut bob],
+        &mut received,
+        |received| received.iter().all(|events| welcome_id(events).is_some()),
+    );
+    let alice_id = welcome_id(&received[0]).unwrap();
+    let bob_id = welcome_id(&received[1]).unwrap();
+    assert_ne!(alice_id, bob_id);
+    for events in received.iter() {
+        assert!(events.iter().any(|event| matches!(
+            event,
+            Event::Welcome { room, seed: 1234, .. } if room == "test"
+        )));
+    }
+
+    //Whoever joined second is told about the first, the first hears about the second
+    run_until(
+        &mut server,
+        &mut [&mut alice, &mut bob],
+        &mut received,
+        |received| {
+            let joined = |events: &[Event], id: u32, expected: &str| {
+                events.iter().any(|event| {
+                    matches!(event, Event::PlayerJoined { id: joined, name }
+                        if *joined == id && name == expected)
+                })
+            };
+            joined(&received[0], bob_id, "Bob") && joined(&received[1], alice_id, "Alice")
+        },
+    );
+
+    //The first position after joining lands near the spawn point
+    alice.set_your_player_pos(vec2(1., -2.), Vec2::ZERO, 1, 0.5);
+    run_until(
+        &mut server,
+        &mut [&mut alice, &mut bob],
+        &mut received,
+        |received| {
+            let acked = received[0].iter().any(|event| {
+                matches!(event, Event::CommandAck { seq: 1, x, y } if *x == 1. && *y == -2.)
+            });
+            let seen = received[1].iter().any(|event| {
+                matches!(event, Event::PlayerUpdate { id, x, y, .. }
+                    if *id == alice_id && *x == 1. && *y == -2.)
+            });
+            acked && seen
+        },
+    );
+
+    drop(alice);
+    let mut received = [Vec::new()];
+    run_until(&mut server, &mut [&mut bob], &mut received, |received| {
+        received[0]
+            .iter()
+            .any(|event| matches!(event, Event::PlayerDisconnect { id } if *id == alice_id))
+    });
+    assert!(bob.is_connected());
+}
+
+#[test]
+fn clients_in_other_rooms_do_not_meet() {
+    let spritesheet = std::fs::read_to_string("textures/spritesheet.json").unwrap();
+    let animations = load_animations(&spritesheet);
+    let texture = Texture2D::empty();
+    let mut server = Server::new(
+        "127.0.0.1:0",
+        WorldGenConfig::with_seed(1),
+        &animations,
+        &texture,
+    )
+    .unwrap();
+
+    let mut alice = connect(&server, "Alice", "test");
+    let mut bob = connect(&server, "Bob", "elsewhere");
+    let mut received = [Vec::new(), Vec::new()];
+    run_until(
+        &mut server,
+        &mut [&mut alice, &mut bob],
+        &mut received,
+        |received| received.iter().all(|events| welcome_id(events).is_some()),
+    );
+    for _ in 0..10 {
+        server.tick(TICK);
+        received[0].extend(alice.get_events());
+        received[1].extend(bob.get_events());
+        thread::sleep(Duration::from_millis(5));
+    }
+    for events in received.iter() {
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, Event::PlayerJoined { .. })));
+    }
+}
+
+#[test]
+fn a_client_on_another_version_is_refused() {
+    let spritesheet = std::fs::read_to_string("textures/spritesheet.json").unwrap();
+    let animations = load_animations(&spritesheet);
+    let texture = Texture2D::empty();
+    let mut server = Server::new(
+        "127.0.0.1:0",
+        WorldGenConfig::with_seed(1),
+        &animations,
+        &texture,
+    )
+    .unwrap();
+
+    //Framed the way Connection does it, but with the version of an older client in the header
+    let old = PROTOCOL_VERSION - 1;
+    let frame = |event: &Event| {
+        let mut packet = protocol::to_binary(event);
+        packet[..2].copy_from_slice(&old.to_le_bytes());
+        let mut frame = (packet.len() as u32).to_le_bytes().to_vec();
+        frame.extend(packet);
+        frame
+    };
+    let mut stream = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+    let join = Event::Join {
+        name: String::from("Old"),
+        room: String::from("test"),
+        version: old,
+    };
+    stream.write_all(&frame(&join)).unwrap();
+    //Whatever the old client sends after Join can not be read, but should not stop the refusal
+    stream.write_all(&frame(&Event::Leave)).unwrap();
+
+    let mut connection = Connection::new(stream).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(20);
+    let reason = loop {
+        assert!(Instant::now() < deadline, "timed out waiting for Refused");
+        server.tick(TICK);
+        let events = match connection.receive() {
+            Ok(events) => events,
+            Err(err) => panic!("closed without Refused: {}", err),
+        };
+        let refused = events.into_iter().find_map(|event| match event {
+            Event::Refused { reason } => Some(reason),
+            _ => None,
+        });
+        match refused {
+            Some(reason) => break reason,
+            None => thread::sleep(Duration::from_millis(5)),
+        }
+    };
+    assert!(reason.contains(&old.to_string()), "{}", reason);
+}
+
+#[test]
+fn a_player_that_died_on_a_tile_can_respawn() {
+    let spritesheet = std::fs::read_to_string("textures/spritesheet.json").unwrap();
+    let animations = load_animations(&spritesheet);
+    let texture = Texture2D::empty();
+    let seed = 1234;
+    let mut server = Server::new(
+        "127.0.0.1:0",
+        WorldGenConfig::with_seed(seed),
+        &animations,
+        &texture,
+    )
+    .unwrap();
+
+    let mut alice = connect(&server, "Alice", "test");
+    let mut bob = connect(&server, "Bob", "test");
+    let mut received = [Vec::new(), Vec::new()];
+    run_until(
+        &mut server,
+        &mut [&mut alice, &mut bob],
+        &mut received,
+        |received| received.iter().all(|events| welcome_id(events).is_some()),
+    );
+    let alice_id = welcome_id(&received[0]).unwrap();
+
+    //Nothing but the position is sent, the server has to see the death for itself
+    let deadly = deadly_tile_near_spawn(seed);
+    alice.set_your_player_pos(deadly, Vec2::ZERO, 1, 0.5);
+    run_until(
+        &mut server,
+        &mut [&mut alice, &mut bob],
+        &mut received,
+        |received| {
+            received[1].iter().any(|event| {
+                matches!(event, Event::PlayerAction { id, action: BlockingAction::Dying, .. }
+                    if *id == alice_id)
+            })
+        },
+    );
+
+    //Far further than anyone walks in a tick, but within respawn range of the death
+    let respawned = deadly + vec2(30., -20.);
+    let ack = acknowledged(&mut server, &mut alice, respawned, 2, 1.);
+    assert_eq!(ack, respawned);
+
+    //Back to walking, with the usual limits
+    let walked = respawned + vec2(0.5, 0.);
+    assert_eq!(
+        acknowledged(&mut server, &mut alice, walked, 3, 1.1),
+        walked
+    );
+    let jumped = walked + vec2(30., 0.);
+    assert_eq!(
+        acknowledged(&mut server, &mut alice, jumped, 4, 1.2),
+        walked
+    );
+}
+
+#[test]
+fn a_frame_longer_than_any_packet_drops_the_connection() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut peer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let mut connection = Connection::new(listener.accept().unwrap().0).unwrap();
+
+    //A valid packet first, the stream only goes bad after it
+    let leave = protocol::to_binary(&Event::Leave);
+    let mut frames = (leave.len() as u32).to_le_bytes().to_vec();
+    frames.extend(leave);
+    frames.extend(u32::MAX.to_le_bytes());
+    frames.extend([0; 16]);
+    peer.write_all(&frames).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(20);
+    let mut received = Vec::new();
+    let err = loop {
+        assert!(Instant::now() < deadline, "the frame was not refused");
+        match connection.receive() {
+            Ok(events) => received.extend(events),
+            Err(err) => break err,
+        }
+        thread::sleep(Duration::from_millis(5));
+    };
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(received.is_empty() || received == vec![Event::Leave]);
+}